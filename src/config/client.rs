@@ -1,11 +1,21 @@
 use reqwest::Client;
 use serde::{Serialize, Deserialize};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::fs;
 use std::path::Path;
-use chrono::Local;
+use chrono::{DateTime, Local};
 use std::collections::HashMap;
-use crate::config::api_config::{get_api_endpoints, get_headers, get_device_id, get_user_id};
+use std::sync::Mutex;
+use std::io::Write;
+use base64::Engine;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use crate::config::api_config::{
+    get_api_base_url, get_api_endpoints, get_headers, get_device_id, get_device_name, get_pinned_cert_path, get_proxy_url, get_timezone, get_user_id, API_KEY_ENV_VAR, API_KEY_FILE,
+};
+use crate::config::settings::{RATE_LIMIT_DEFAULT_COOLDOWN_SECS, RATE_LIMIT_MAX_COOLDOWN_SECS};
+use crate::config::upload_queue::{self, QUEUED_ENDPOINTS};
+use crate::core::lock::LockRecover;
 
 #[derive(Serialize)]
 pub struct DeviceInfo {
@@ -20,12 +30,58 @@ pub struct DeviceInfo {
     pub monitor_version: String,
     #[serde(rename = "firstSeen")]
     pub first_seen: String,
+    /// IANA timezone name, e.g. "America/New_York"
+    pub timezone: String,
 }
 
 #[derive(Serialize)]
 pub struct HeartbeatData {
     #[serde(rename = "deviceId")]
     pub device_id: String,
+    /// Monitoring paused from the tray; the device is online but not tracking or blocking
+    pub paused: bool,
+    /// Only sent when network reporting is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkContext>,
+    pub health: EnforcementHealth,
+}
+
+/// Whether blocking is actually working, so the server can flag devices that are online
+/// but not enforcing
+#[derive(Serialize, Clone, Default)]
+pub struct EnforcementHealth {
+    /// "enforce" or "observe"
+    #[serde(rename = "enforcementMode")]
+    pub enforcement_mode: String,
+    /// Whether the last scan read a URL from the open browser windows; null if none was open
+    #[serde(rename = "lastUrlReadOk")]
+    pub last_url_read_ok: Option<bool>,
+    #[serde(rename = "secsSinceLastUrlRead")]
+    pub secs_since_last_url_read: Option<u64>,
+    #[serde(rename = "partialAccessHealthy")]
+    pub partial_access_healthy: bool,
+    #[serde(rename = "blacklistSize")]
+    pub blacklist_size: usize,
+    /// Monitoring is off by schedule, so no enforcement is expected
+    #[serde(rename = "outsideWorkingHours")]
+    pub outside_working_hours: bool,
+    #[serde(rename = "blockedSinceLastHeartbeat")]
+    pub blocked_since_last_heartbeat: u32,
+    #[serde(rename = "suspiciousSinceLastHeartbeat")]
+    pub suspicious_since_last_heartbeat: u32,
+    #[serde(rename = "dialogsClosedSinceLastHeartbeat")]
+    pub dialogs_closed_since_last_heartbeat: u32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct NetworkContext {
+    /// Wi-Fi network name; None on wired connections
+    pub ssid: Option<String>,
+    #[serde(rename = "localIp")]
+    pub local_ip: Option<String>,
+    /// None when no corporate subnets are configured or the local IP is unknown
+    #[serde(rename = "onCorporateNetwork")]
+    pub on_corporate_network: Option<bool>,
 }
 
 #[derive(Serialize)]
@@ -39,6 +95,23 @@ pub struct LogData {
     pub timestamp: String,
     #[serde(rename = "fileSize")]
     pub file_size: usize,
+    /// True when log_content is gzip-compressed and base64-encoded
+    pub compressed: bool,
+}
+
+/// Everything a sync cycle uploads, sent in one request by upload_batch.
+#[derive(Serialize)]
+pub struct BatchData {
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    pub timestamp: String,
+    pub heartbeat: HeartbeatData,
+    #[serde(rename = "appUsage")]
+    pub app_usage: AppUsageData,
+    pub urls: UrlMonitoringData,
+    /// Omitted when the log file is missing or empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logs: Option<LogData>,
 }
 
 #[derive(Serialize)]
@@ -46,13 +119,59 @@ pub struct UrlMonitoringData {
     #[serde(rename = "deviceId")]
     pub device_id: String,
     pub timestamp: String,
+    pub timezone: String,
+    /// Each distinct URL in `visits`, in the same order, for servers that only read the list
     pub urls: Vec<String>,
+    /// One record per URL visited in the upload window, ordered by first visit
+    pub visits: Vec<UrlVisit>,
     #[serde(rename = "blockedCount")]
     pub blocked_count: u32,
     #[serde(rename = "suspiciousCount")]
     pub suspicious_count: u32,
     #[serde(rename = "totalVisits")]
     pub total_visits: u32,
+    /// Private/incognito browsing was seen during this upload window
+    #[serde(rename = "isPrivate")]
+    pub is_private: bool,
+}
+
+impl UrlMonitoringData {
+    /// Replaces the visit records, keeping `urls` in step.
+    pub fn set_visits(&mut self, visits: Vec<UrlVisit>) {
+        self.urls = visits.iter().map(|v| v.url.clone()).collect();
+        self.visits = visits;
+    }
+}
+
+/// A URL's visits within one upload window, so switching back and forth between two tabs
+/// adds to a count rather than repeating entries
+#[derive(Serialize, Clone, Debug)]
+pub struct UrlVisit {
+    pub url: String,
+    #[serde(rename = "firstSeen")]
+    pub first_seen: DateTime<Local>,
+    #[serde(rename = "lastSeen")]
+    pub last_seen: DateTime<Local>,
+    #[serde(rename = "visitCount")]
+    pub visit_count: u32,
+}
+
+/// Combines two visit histories, summing the records of URLs that appear in both. The result
+/// is ordered by first visit.
+pub fn merge_url_visits(older: Vec<UrlVisit>, newer: Vec<UrlVisit>) -> Vec<UrlVisit> {
+    let mut merged: Vec<UrlVisit> = Vec::with_capacity(older.len() + newer.len());
+    for visit in older.into_iter().chain(newer) {
+        match merged.iter_mut().find(|v| v.url == visit.url) {
+            Some(existing) => {
+                existing.first_seen = existing.first_seen.min(visit.first_seen);
+                existing.last_seen = existing.last_seen.max(visit.last_seen);
+                existing.visit_count += visit.visit_count;
+            }
+            None => merged.push(visit),
+        }
+    }
+    merged.sort_by_key(|v| v.first_seen);
+    merged
 }
 
 #[derive(Serialize)]
@@ -60,20 +179,61 @@ pub struct AppUsageData {
     #[serde(rename = "deviceId")]
     pub device_id: String,
     pub timestamp: String,
+    pub timezone: String,
     #[serde(rename = "currentApp")]
     pub current_app: String,
     #[serde(rename = "currentSessionDuration")]
     pub current_session_duration: f64,
+    /// "active" or "passive", depending on how much input the current session has had
+    #[serde(rename = "currentSessionEngagement")]
+    pub current_session_engagement: String,
     #[serde(rename = "totalAppsTracked")]
     pub total_apps_tracked: u32,
+    /// Wall-clock seconds tracked today, idle and ignored-app time included
     #[serde(rename = "totalTimeTracked")]
     pub total_time_tracked: f64,
+    /// Seconds of recorded app sessions today; divided by totalTimeTracked gives utilization
     #[serde(rename = "activeUsageTime")]
     pub active_usage_time: f64,
+    /// Seconds of tracked app time with recent keyboard or mouse input
+    #[serde(rename = "activeInputSeconds")]
+    pub active_input_seconds: f64,
+    /// App switches today, not counting ignored apps
+    #[serde(rename = "appSwitches")]
+    pub app_switches: u32,
+    #[serde(rename = "switchesPerMinute")]
+    pub switches_per_minute: u32,
+    /// Times each app was closed today for exceeding its daily limit
+    #[serde(rename = "appLimitEnforcements")]
+    pub app_limit_enforcements: HashMap<String, u32>,
     #[serde(rename = "topApps")]
     pub top_apps: Vec<serde_json::Value>,
     #[serde(rename = "categoryBreakdown")]
     pub category_breakdown: HashMap<String, f64>,
+    /// Seconds per category in each local hour of today, 24 entries from midnight
+    #[serde(rename = "categoryHourlyBreakdown")]
+    pub category_hourly_breakdown: HashMap<String, [f64; 24]>,
+    /// Windows session the usage was tracked in, to tell users on a terminal server apart
+    #[serde(rename = "sessionId")]
+    pub session_id: u32,
+    #[serde(rename = "remoteSession")]
+    pub remote_session: bool,
+}
+
+/// Every app's totals for the day, sent less often than AppUsageData's top apps
+#[derive(Serialize)]
+pub struct AppUsageExportData {
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    pub timestamp: String,
+    /// Local date the totals belong to (YYYY-MM-DD)
+    pub day: String,
+    #[serde(rename = "appTotalTime")]
+    pub app_total_time: HashMap<String, f64>,
+    #[serde(rename = "appSessions")]
+    pub app_sessions: HashMap<String, u32>,
+    #[serde(rename = "appCategoryTime")]
+    pub app_category_time: HashMap<String, f64>,
 }
 
 #[derive(Deserialize)]
@@ -93,42 +253,329 @@ pub struct AccessAttemptData {
     pub blocked: bool,
     #[serde(rename = "monitorMode")]
     pub monitor_mode: String,
+    /// The suspicious URL pattern the visit matched
+    #[serde(rename = "matchedPattern", skip_serializing_if = "Option::is_none")]
+    pub matched_pattern: Option<String>,
+    /// Seconds spent on the page, for quick exits
+    #[serde(rename = "dwellSecs", skip_serializing_if = "Option::is_none")]
+    pub dwell_secs: Option<f64>,
+}
+
+/// What became of a payload handed to post_or_queue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UploadOutcome {
+    /// Accepted by the server
+    Sent,
+    /// Saved to the upload queue, to be resent by drain_upload_queue
+    Queued,
+    /// Refused by the server, or could be neither sent nor queued
+    Rejected,
 }
+
+impl UploadOutcome {
+    /// True once the payload was sent or safely queued, so the caller can drop its copy.
+    pub fn kept(self) -> bool {
+        self != UploadOutcome::Rejected
+    }
+}
+
+/// What a partial-access attempt tried to do; each kind is reported to its own endpoint.
+/// Uploads, downloads and clipboard copies are only caught by the Win32 dialog watcher.
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(not(windows), allow(dead_code))]
+pub enum AccessAttemptKind {
+    Upload,
+    Download,
+    Clipboard,
+    /// A sensitive URL left within the dwell threshold
+    QuickExit,
+    /// A visit to a URL matching a suspicious pattern
+    SuspiciousUrl,
+}
+
+#[derive(Serialize)]
+pub struct ScreenshotData {
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    /// Base64-encoded PNG
+    pub image: String,
+}
+
+/// Cumulative partial-access enforcement counts, kept across restarts
+#[derive(Serialize)]
+pub struct PartialAccessStatsData {
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    pub timestamp: String,
+    #[serde(rename = "dialogsClosed")]
+    pub dialogs_closed: u32,
+    #[serde(rename = "clipboardCleared")]
+    pub clipboard_cleared: u32,
+}
+
+/// A removable drive inserted on the device
+#[derive(Serialize)]
+pub struct UsbDeviceData {
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    pub timestamp: String,
+    pub drive: String,
+    #[serde(rename = "volumeLabel")]
+    pub volume_label: String,
+    #[serde(rename = "volumeSerial")]
+    pub volume_serial: String,
+    /// Inserted while a restricted (partial-access) site was open
+    pub suspicious: bool,
+    #[serde(rename = "currentUrl", skip_serializing_if = "Option::is_none")]
+    pub current_url: Option<String>,
+}
+
+/// A VPN/anonymizer client or local proxy found running on the device
+#[derive(Serialize)]
+pub struct AnonymizerData {
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    pub timestamp: String,
+    /// "process" or "proxy"
+    pub kind: String,
+    /// Process name, or the proxy server / PAC URL
+    pub name: String,
+    /// Browsing is blocked while it runs (blockBrowsingOnAnonymizer)
+    #[serde(rename = "browsingBlocked")]
+    pub browsing_blocked: bool,
+}
+
+/// A process named like an ignored app that turned out to be a different executable
+#[derive(Serialize)]
+pub struct ProcessSpoofingData {
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    pub timestamp: String,
+    /// The ignored app's name the process runs under
+    pub name: String,
+    pub path: String,
+    /// File name from the executable's version info, when it has one
+    #[serde(rename = "originalName", skip_serializing_if = "Option::is_none")]
+    pub original_name: Option<String>,
+    /// "renamed executable" or "unexpected location"
+    pub reason: String,
+    /// Name its usage is tracked under instead
+    #[serde(rename = "trackedAs")]
+    pub tracked_as: String,
+}
+
 pub struct APIClient {
     pub client: Client,
+    /// Server every endpoint URL is built on, without a trailing slash. None follows
+    /// get_api_base_url, so a reload_api_config reaches every endpoint.
+    base_url: Option<String>,
+    max_retries: u32,
+    retry_base_delay: Duration,
+    /// Per-request timeout for small requests; uploads use the client-wide upload timeout
+    short_timeout: Duration,
+    /// Only servers chaining to the pinned certificate are trusted
+    pinned: bool,
+    /// ETag / Last-Modified of the last config response per endpoint key, sent back on
+    /// conditional fetches so an unchanged config comes back as 304 Not Modified
+    config_validators: Mutex<HashMap<&'static str, CacheValidators>>,
+    /// Set when the server answers 429; no request goes out before this instant
+    cooldown_until: Mutex<Option<Instant>>,
+}
+
+/// Why a request produced no response.
+#[derive(Debug)]
+enum SendError {
+    Http(reqwest::Error),
+    /// Not sent because the server rate-limited us; the cooldown left
+    Cooldown(Duration),
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Http(e) => write!(f, "{}", e),
+            SendError::Cooldown(left) => write!(f, "rate limited by the server for another {}s", left.as_secs()),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct CacheValidators {
+    etag: Option<String>,
+    last_modified: Option<String>,
 }
 
 impl APIClient {
-    pub fn new() -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(10))
-            .default_headers(get_headers())
-            .build()
-            .unwrap();
+    pub fn new(max_retries: u32, retry_base_delay: Duration, short_timeout: Duration, upload_timeout: Duration) -> Self {
+        let mut client = Self::with_base_url(&get_api_base_url(), max_retries, retry_base_delay, short_timeout, upload_timeout);
+        client.base_url = None;
+        client
+    }
+
+    /// Like new, but talking to `base_url` instead of the configured API server.
+    pub fn with_base_url(base_url: &str, max_retries: u32, retry_base_delay: Duration, short_timeout: Duration, upload_timeout: Duration) -> Self {
+        let mut builder = Client::builder()
+            .use_rustls_tls()
+            .connect_timeout(short_timeout)
+            .timeout(upload_timeout)
+            // Config fetches repeat every few seconds: accept compressed bodies and keep
+            // connections open between them instead of a new TLS handshake each time
+            .gzip(true)
+            .pool_idle_timeout(Duration::from_secs(90))
+            .tcp_keepalive(Duration::from_secs(60))
+            .default_headers(get_headers());
+
+        let pinned_cert_path = get_pinned_cert_path();
+        if let Some(path) = &pinned_cert_path {
+            // Trust nothing but the pinned certificate and never fall back to plain http.
+            // If the certificate can't be loaded there are no trusted roots at all, so every
+            // request fails rather than going out unverified.
+            builder = builder.tls_built_in_root_certs(false).https_only(true);
+            match fs::read(path).map_err(|e| e.to_string())
+                .and_then(|pem| reqwest::Certificate::from_pem(&pem).map_err(|e| e.to_string())) {
+                Ok(cert) => {
+                    builder = builder.add_root_certificate(cert);
+                    println!("[INFO] TLS certificate pinning enabled ({})", path);
+                }
+                Err(e) => println!("[ERROR] Could not load pinned certificate {}: {}. No data will be uploaded until it is fixed.", path, e),
+            }
+        } else if base_url.starts_with("http://") {
+            println!("[WARN] API server uses plain http. Device data is sent unencrypted.");
+        }
+
+        if let Some(proxy_url) = get_proxy_url() {
+            match build_proxy(&proxy_url) {
+                Ok(proxy) => {
+                    builder = builder.proxy(proxy);
+                    println!("[INFO] Using proxy {}", redact_proxy_url(&proxy_url));
+                }
+                Err(e) => println!("[ERROR] Invalid proxy '{}': {}. Connecting directly.", redact_proxy_url(&proxy_url), e),
+            }
+        }
+
+        let client = builder.build().unwrap();
         
-        APIClient { client }
+        APIClient {
+            client,
+            base_url: Some(base_url.trim_end_matches('/').to_string()),
+            max_retries,
+            retry_base_delay,
+            short_timeout,
+            pinned: pinned_cert_path.is_some(),
+            config_validators: Mutex::new(HashMap::new()),
+            cooldown_until: Mutex::new(None),
+        }
+    }
+
+    /// Sends a request once, calling out authentication failures so they aren't mistaken for outages.
+    /// While a 429 cooldown is running nothing is sent and every endpoint fails with Cooldown.
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, SendError> {
+        if let Some(left) = self.cooldown_remaining() {
+            println!("[WARN] In rate-limit cooldown, skipping request ({}s left)", left.as_secs());
+            return Err(SendError::Cooldown(left));
+        }
+
+        let result = request.send().await;
+        match &result {
+            Ok(resp) if resp.status() == reqwest::StatusCode::UNAUTHORIZED => {
+                println!("[ERROR] Authentication failed (401) for {}. Check the API key in {} or {}.",
+                    resp.url().path(), API_KEY_ENV_VAR, API_KEY_FILE);
+            }
+            Ok(resp) if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                let cooldown = retry_after(resp)
+                    .unwrap_or(Duration::from_secs(RATE_LIMIT_DEFAULT_COOLDOWN_SECS))
+                    .min(Duration::from_secs(RATE_LIMIT_MAX_COOLDOWN_SECS));
+                println!("[WARN] Rate limited (429) on {}. Pausing all API requests for {}s.", resp.url().path(), cooldown.as_secs());
+                *self.cooldown_until.lock_recover() = Some(Instant::now() + cooldown);
+            }
+            Err(e) if self.pinned && e.is_connect() => {
+                println!("[ERROR] Could not establish a trusted connection ({}). The server certificate may not match the pinned certificate.", e);
+            }
+            _ => {}
+        }
+        result.map_err(SendError::Http)
+    }
+
+    fn endpoints(&self) -> HashMap<&'static str, String> {
+        match &self.base_url {
+            Some(base_url) => get_api_endpoints(base_url),
+            None => get_api_endpoints(&get_api_base_url()),
+        }
+    }
+
+    /// Time left in the rate-limit cooldown, if one is running.
+    fn cooldown_remaining(&self) -> Option<Duration> {
+        let mut until = self.cooldown_until.lock_recover();
+        match *until {
+            Some(deadline) if deadline > Instant::now() => Some(deadline - Instant::now()),
+            Some(_) => {
+                println!("[INFO] Rate-limit cooldown over, resuming API requests");
+                *until = None;
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Sends a request, retrying network errors and 5xx responses with exponential backoff
+    /// (base, 2x base, 4x base, ...) plus up to 50% random jitter. 4xx responses (including 429)
+    /// and requests skipped during a rate-limit cooldown are returned as-is.
+    async fn send_with_retry(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, SendError> {
+        let mut attempt = 0;
+        loop {
+            let result = match request.try_clone() {
+                Some(req) => self.send(req).await,
+                // Streaming bodies can't be replayed, send once
+                None => return self.send(request).await,
+            };
+
+            let retryable = match &result {
+                Ok(resp) => resp.status().is_server_error(),
+                Err(SendError::Http(_)) => true,
+                Err(SendError::Cooldown(_)) => false,
+            };
+            if !retryable || attempt >= self.max_retries {
+                return result;
+            }
+
+            let delay = self.retry_base_delay * 2u32.pow(attempt);
+            let jitter_range = delay.as_millis() as u64 / 2 + 1;
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.subsec_nanos() as u64)
+                .unwrap_or(0);
+            let backoff = delay + Duration::from_millis(nanos % jitter_range);
+
+            attempt += 1;
+            println!("[DEBUG] Request failed, retrying in {}ms (attempt {}/{})", backoff.as_millis(), attempt, self.max_retries);
+            tokio::time::sleep(backoff).await;
+        }
     }
 
     pub async fn register_device(&self) -> bool {
-        let endpoints = get_api_endpoints();
+        let endpoints = self.endpoints();
         let url = endpoints.get("device_register").unwrap();
         
         let device_info = DeviceInfo {
             device_id: get_device_id(),
             user_id: get_user_id(),
-            device_name: whoami::devicename(),
+            device_name: get_device_name(),
             platform: "Windows".to_string(),
             monitor_version: "2.1".to_string(),
             first_seen: Local::now().to_rfc3339(),
+            timezone: get_timezone(),
         };
 
-        match self.client.post(url).json(&device_info).send().await {
+        match self.send_with_retry(self.client.post(url).timeout(self.short_timeout).json(&device_info)).await {
             Ok(resp) if resp.status().is_success() => {
                 println!("  [OK] Device registered successfully.");
                 true
             }
             Ok(resp) => {
                 println!("  [ERROR] Registration failed with status: {}", resp.status());
+                log_failed_response("device_register", resp).await;
                 false
             }
             Err(e) => {
@@ -138,16 +585,23 @@ impl APIClient {
         }
     }
 
-    pub async fn send_heartbeat(&self) -> bool {
-        let endpoints = get_api_endpoints();
+    pub async fn send_heartbeat(&self, paused: bool, network: Option<NetworkContext>, health: EnforcementHealth) -> bool {
+        let endpoints = self.endpoints();
         let url = endpoints.get("heartbeat").unwrap();
         
         let heartbeat_data = HeartbeatData {
             device_id: get_device_id(),
+            paused,
+            network,
+            health,
         };
 
-        match self.client.post(url).json(&heartbeat_data).send().await {
-            Ok(resp) => resp.status().is_success(),
+        match self.send_with_retry(self.client.post(url).timeout(self.short_timeout).json(&heartbeat_data)).await {
+            Ok(resp) if resp.status().is_success() => true,
+            Ok(resp) => {
+                log_failed_response("heartbeat", resp).await;
+                false
+            }
             Err(_) => false,
         }
     }
@@ -166,66 +620,140 @@ impl APIClient {
             return true;
         }
 
-        let lines: Vec<&str> = content.lines().collect();
-        let recent_lines = if lines.len() > 1000 {
-            &lines[lines.len() - 1000..]
-        } else {
-            &lines[..]
+        let log_data = build_log_data(log_path, &content);
+        let compressed = log_data.compressed;
+
+        let payload = match serde_json::to_string(&log_data) {
+            Ok(p) => p,
+            Err(_) => return false,
         };
 
-        let log_data = LogData {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if compressed {
+            headers.insert("X-Content-Encoding", "gzip".parse().unwrap());
+        }
+
+        let uploaded = self.post_or_queue("log_upload", payload, headers).await.kept();
+        if uploaded && clear_after {
+            let _ = fs::write(log_path, "");
+        }
+        uploaded
+    }
+    
+    /// Sends heartbeat, app usage, URLs and the log file as one document so the server applies
+    /// them together. Queued as a unit on failure, like the individual uploads.
+    pub async fn upload_batch(
+        &self,
+        paused: bool,
+        network: Option<NetworkContext>,
+        health: EnforcementHealth,
+        app_usage: AppUsageData,
+        urls: UrlMonitoringData,
+        log_path: &Path,
+    ) -> UploadOutcome {
+        let logs = fs::read_to_string(log_path).ok()
+            .filter(|content| !content.trim().is_empty())
+            .map(|content| build_log_data(log_path, &content));
+
+        let batch = BatchData {
             device_id: get_device_id(),
-            log_type: log_path.file_stem().unwrap().to_str().unwrap().to_string(),
-            log_content: recent_lines.join("\n"),
             timestamp: Local::now().to_rfc3339(),
-            file_size: content.len(),
+            heartbeat: HeartbeatData { device_id: get_device_id(), paused, network, health },
+            app_usage,
+            urls,
+            logs,
         };
 
-        let endpoints = get_api_endpoints();
-        let url = endpoints.get("log_upload").unwrap();
+        match serde_json::to_string(&batch) {
+            Ok(payload) => self.post_or_queue("batch_upload", payload, reqwest::header::HeaderMap::new()).await,
+            Err(_) => UploadOutcome::Rejected,
+        }
+    }
 
-        match self.client.post(url).json(&log_data).send().await {
-            Ok(resp) if resp.status().is_success() => {
-                if clear_after {
-                    let _ = fs::write(log_path, "");
-                }
-                true
-            }
-            _ => false,
+    pub async fn upload_urls(&self, data: UrlMonitoringData) -> UploadOutcome {
+        match serde_json::to_string(&data) {
+            Ok(payload) => self.post_or_queue("url_upload", payload, reqwest::header::HeaderMap::new()).await,
+            Err(_) => UploadOutcome::Rejected,
         }
     }
-    
-    pub async fn upload_urls(&self, data: UrlMonitoringData) -> bool {
-        let endpoints = get_api_endpoints();
-        let url = endpoints.get("url_upload").unwrap();
 
-        match self.client.post(url).json(&data).send().await {
-            Ok(resp) => resp.status().is_success(),
-            Err(_) => false,
+    pub async fn upload_app_usage(&self, data: AppUsageData) -> UploadOutcome {
+        match serde_json::to_string(&data) {
+            Ok(payload) => self.post_or_queue("app_usage_upload", payload, reqwest::header::HeaderMap::new()).await,
+            Err(_) => UploadOutcome::Rejected,
         }
     }
 
-    pub async fn upload_app_usage(&self, data: AppUsageData) -> bool {
-        let endpoints = get_api_endpoints();
-        let url = endpoints.get("app_usage_upload").unwrap();
+    /// POSTs a JSON payload, queueing it to disk for a later retry if the network or server is down.
+    /// Payloads rejected with a 4xx are not queued since resending them would fail the same way.
+    /// Extra headers are only sent on this attempt; queued payloads must be self-describing.
+    async fn post_or_queue(&self, endpoint_key: &str, payload: String, headers: reqwest::header::HeaderMap) -> UploadOutcome {
+        let endpoints = self.endpoints();
+        let url = endpoints.get(endpoint_key).unwrap();
+        let enqueue = |payload: &str| {
+            if upload_queue::enqueue(endpoint_key, payload) { UploadOutcome::Queued } else { UploadOutcome::Rejected }
+        };
 
-        match self.client.post(url).json(&data).send().await {
-            Ok(resp) => resp.status().is_success(),
-            Err(_) => false,
+        match self.send_with_retry(self.client.post(url).headers(headers).body(payload.clone())).await {
+            Ok(resp) if resp.status().is_success() => UploadOutcome::Sent,
+            Ok(resp) => {
+                let rejected = is_rejection(resp.status());
+                log_failed_response(endpoint_key, resp).await;
+                if rejected { UploadOutcome::Rejected } else { enqueue(&payload) }
+            }
+            Err(_) => enqueue(&payload),
         }
     }
 
-    pub async fn get_partial_access_config(&self) -> Option<serde_json::Value> {
-        let endpoints = get_api_endpoints();
+    /// Retries queued payloads oldest first, stopping at the first failure so ordering is kept.
+    pub async fn drain_upload_queue(&self) {
+        let endpoints = self.endpoints();
+
+        for key in QUEUED_ENDPOINTS {
+            let pending = upload_queue::pending(key);
+            if pending.is_empty() {
+                continue;
+            }
+
+            let url = endpoints.get(key).unwrap();
+            let mut processed = 0;
+            for payload in &pending {
+                match self.send(self.client.post(url).body(payload.clone())).await {
+                    Ok(resp) if resp.status().is_success() => processed += 1,
+                    Ok(resp) if is_rejection(resp.status()) => {
+                        println!("[ERROR] Server rejected queued {} payload (status {}). Dropping it.", key, resp.status());
+                        log_failed_response(key, resp).await;
+                        processed += 1;
+                    }
+                    _ => break,
+                }
+            }
+
+            upload_queue::retain(key, &pending[processed..]);
+            println!("[INFO] Sent {} of {} queued {} payloads", processed, pending.len(), key);
+        }
+    }
+
+    /// Fetches the partial access config, sending the validators from the previous response.
+    /// Returns None on failure or 304 Not Modified, so an unchanged config is neither parsed
+    /// nor reapplied.
+    pub async fn get_partial_access_config_conditional(&self) -> Option<serde_json::Value> {
+        let endpoints = self.endpoints();
         let url = endpoints.get("partial_access_config").unwrap();
 
-        match self.client.get(url).send().await {
+        let request = self.with_validators(self.client.get(url).timeout(self.short_timeout), "partial_access_config");
+        match self.send(request).await {
             Ok(resp) => {
                 let status = resp.status();
+                if status == reqwest::StatusCode::NOT_MODIFIED {
+                    println!("[DEBUG] Partial access config not modified");
+                    return None;
+                }
                 if !status.is_success() {
                     println!("[ERROR] Failed to fetch partial access config: status {}", status);
                     return None;
                 }
+                let validators = response_validators(&resp);
                 
                 let body = match resp.text().await {
                     Ok(t) => t,
@@ -235,11 +763,13 @@ impl APIClient {
                     }
                 };
 
+                println!("[DEBUG] Partial access config response: {} bytes", body.len());
                 println!("[DEBUG] Raw partial access response: {}", body);
 
                 match serde_json::from_str::<ApiResponse<serde_json::Value>>(&body) {
                     Ok(api_resp) => {
                         if api_resp.success {
+                            self.store_validators("partial_access_config", validators);
                             return Some(api_resp.data);
                         } else {
                             println!("[ERROR] API returned success=false for partial access config: {}", api_resp.message);
@@ -258,32 +788,134 @@ impl APIClient {
         }
     }
 
+    pub async fn get_monitor_config(&self) -> Option<serde_json::Value> {
+        let endpoints = self.endpoints();
+        let url = endpoints.get("monitor_config").unwrap();
+
+        match self.send(self.client.get(url).timeout(self.short_timeout)).await {
+            Ok(resp) => {
+                let status = resp.status();
+                if !status.is_success() {
+                    println!("[ERROR] Failed to fetch monitor config: status {}", status);
+                    return None;
+                }
+
+                let body = match resp.text().await {
+                    Ok(t) => t,
+                    Err(e) => {
+                        println!("[ERROR] Failed to read response body: {}", e);
+                        return None;
+                    }
+                };
+
+                println!("[DEBUG] Monitor config response: {} bytes", body.len());
+                println!("[DEBUG] Raw monitor config response: {}", body);
+
+                match serde_json::from_str::<ApiResponse<serde_json::Value>>(&body) {
+                    Ok(api_resp) => {
+                        if api_resp.success {
+                            return Some(api_resp.data);
+                        } else {
+                            println!("[ERROR] API returned success=false for monitor config: {}", api_resp.message);
+                        }
+                    }
+                    Err(e) => {
+                        println!("[ERROR] Failed to parse monitor config JSON: {}", e);
+                    }
+                }
+                None
+            }
+            Err(e) => {
+                println!("[ERROR] Network error fetching monitor config: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Asks the server whether this agent has been ordered to stop. The data may be a bare
+    /// boolean, {"shutdown": true} or {"command": "shutdown"}. Any failure means keep running,
+    /// so an unreachable server can never stop the agent.
+    pub async fn check_remote_shutdown(&self) -> bool {
+        let endpoints = self.endpoints();
+        let url = endpoints.get("shutdown").unwrap();
+
+        let resp = match self.send(self.client.get(url).timeout(self.short_timeout)).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                println!("[ERROR] Network error checking for remote shutdown: {}", e);
+                return false;
+            }
+        };
+        // Servers without the endpoint just don't support remote shutdown
+        if !resp.status().is_success() {
+            return false;
+        }
+
+        match resp.json::<ApiResponse<serde_json::Value>>().await {
+            Ok(api_resp) if api_resp.success => match &api_resp.data {
+                serde_json::Value::Bool(shutdown) => *shutdown,
+                data => data.get("shutdown").and_then(|v| v.as_bool()).unwrap_or(false)
+                    || data.get("command").and_then(|v| v.as_str()).is_some_and(|c| c.eq_ignore_ascii_case("shutdown")),
+            },
+            Ok(_) => false,
+            Err(e) => {
+                println!("[ERROR] Failed to parse remote shutdown response: {}", e);
+                false
+            }
+        }
+    }
+
     pub async fn get_blocked_urls(&self) -> Vec<String> {
-        let endpoints = get_api_endpoints();
+        self.fetch_blocked_urls(false).await.unwrap_or_default()
+    }
+
+    /// Like get_blocked_urls, but returns None on 304 Not Modified so the caller can keep its
+    /// compiled blacklist. Failures still return an empty list, as get_blocked_urls does.
+    pub async fn get_blocked_urls_conditional(&self) -> Option<Vec<String>> {
+        self.fetch_blocked_urls(true).await
+    }
+
+    async fn fetch_blocked_urls(&self, conditional: bool) -> Option<Vec<String>> {
+        let endpoints = self.endpoints();
         let url = endpoints.get("blocked_urls").unwrap();
 
-        match self.client.get(url).send().await {
+        let mut request = self.client.get(url).timeout(self.short_timeout);
+        if conditional {
+            request = self.with_validators(request, "blocked_urls");
+        }
+
+        // Any failure below empties the blacklist, so drop the validators too: otherwise the
+        // next fetch could get a 304 and leave the list empty until the server's copy changes
+        match self.send_with_retry(request).await {
             Ok(resp) => {
                 let status = resp.status();
+                if status == reqwest::StatusCode::NOT_MODIFIED {
+                    println!("[DEBUG] Blocked URLs not modified");
+                    return None;
+                }
                 if !status.is_success() {
                     println!("[ERROR] Failed to fetch blocked URLs: status {}", status);
-                    return Vec::new();
+                    self.clear_validators("blocked_urls");
+                    return Some(Vec::new());
                 }
+                let validators = response_validators(&resp);
                 
                 let body = match resp.text().await {
                     Ok(t) => t,
                     Err(e) => {
                         println!("[ERROR] Failed to read response body: {}", e);
-                        return Vec::new();
+                        self.clear_validators("blocked_urls");
+                        return Some(Vec::new());
                     }
                 };
 
-                println!("[DEBUG] Raw blocked URLs response: {}", body);
-
+                // The list can run to thousands of entries, so log its size rather than the body
                 match serde_json::from_str::<ApiResponse<Vec<String>>>(&body) {
                     Ok(api_resp) => {
                         if api_resp.success {
-                            return api_resp.data;
+                            println!("[DEBUG] Blocked URLs response: {} bytes, {} entries", body.len(), api_resp.data.len());
+                            self.store_validators("blocked_urls", validators);
+                            return Some(api_resp.data);
                         } else {
                             println!("[ERROR] API returned success=false for blocked URLs: {}", api_resp.message);
                         }
@@ -292,23 +924,236 @@ impl APIClient {
                         println!("[ERROR] Failed to parse blocked URLs JSON: {}", e);
                     }
                 }
-                Vec::new()
+                self.clear_validators("blocked_urls");
+                Some(Vec::new())
             }
             Err(e) => {
                 println!("[ERROR] Network error fetching blocked URLs: {}", e);
-                Vec::new()
+                self.clear_validators("blocked_urls");
+                Some(Vec::new())
+            }
+        }
+    }
+
+    /// Adds If-None-Match / If-Modified-Since from the last successful response for `key`.
+    fn with_validators(&self, mut request: reqwest::RequestBuilder, key: &str) -> reqwest::RequestBuilder {
+        let validators = self.config_validators.lock_recover().get(key).cloned().unwrap_or_default();
+        if let Some(etag) = validators.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = validators.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    }
+
+    fn store_validators(&self, key: &'static str, validators: CacheValidators) {
+        self.config_validators.lock_recover().insert(key, validators);
+    }
+
+    fn clear_validators(&self, key: &str) {
+        self.config_validators.lock_recover().remove(key);
+    }
+
+    /// Uploads a saved screenshot as base64 PNG. The local copy is kept either way.
+    pub async fn upload_screenshot(&self, path: &Path) -> bool {
+        let bytes = match fs::read(path) {
+            Ok(b) => b,
+            Err(e) => {
+                println!("[ERROR] Failed to read screenshot {}: {}", path.display(), e);
+                return false;
             }
+        };
+        let data = ScreenshotData {
+            device_id: get_device_id(),
+            file_name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+            image: base64::engine::general_purpose::STANDARD.encode(bytes),
+        };
+
+        let endpoints = self.endpoints();
+        let url = endpoints.get("screenshot_upload").unwrap();
+        match self.send_with_retry(self.client.post(url).json(&data)).await {
+            Ok(resp) if resp.status().is_success() => true,
+            Ok(resp) => {
+                log_failed_response("screenshot_upload", resp).await;
+                false
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Sends the complete app totals; like the stats, a failed upload is superseded by the next one.
+    pub async fn upload_app_usage_export(&self, data: AppUsageExportData) -> bool {
+        let endpoints = self.endpoints();
+        let url = endpoints.get("app_usage_export").unwrap();
+        match self.send_with_retry(self.client.post(url).json(&data)).await {
+            Ok(resp) if resp.status().is_success() => true,
+            Ok(resp) => {
+                log_failed_response("app_usage_export", resp).await;
+                false
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Sends the running totals; a failed upload is simply superseded by the next one.
+    pub async fn upload_partial_access_stats(&self, data: PartialAccessStatsData) -> bool {
+        let endpoints = self.endpoints();
+        let url = endpoints.get("partial_access_stats").unwrap();
+        match self.send_with_retry(self.client.post(url).json(&data)).await {
+            Ok(resp) if resp.status().is_success() => true,
+            Ok(resp) => {
+                log_failed_response("partial_access_stats", resp).await;
+                false
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub async fn report_usb_device(&self, data: UsbDeviceData) -> bool {
+        let endpoints = self.endpoints();
+        let url = endpoints.get("usb_device").unwrap();
+        match self.send_with_retry(self.client.post(url).timeout(self.short_timeout).json(&data)).await {
+            Ok(resp) if resp.status().is_success() => true,
+            Ok(resp) => {
+                log_failed_response("usb_device", resp).await;
+                false
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub async fn report_anonymizer(&self, data: AnonymizerData) -> bool {
+        let endpoints = self.endpoints();
+        let url = endpoints.get("anonymizer").unwrap();
+        match self.send_with_retry(self.client.post(url).timeout(self.short_timeout).json(&data)).await {
+            Ok(resp) if resp.status().is_success() => true,
+            Ok(resp) => {
+                log_failed_response("anonymizer", resp).await;
+                false
+            }
+            Err(_) => false,
         }
     }
 
-    pub async fn record_access_attempt(&self, data: AccessAttemptData, is_upload: bool) -> bool {
-        let endpoints = get_api_endpoints();
-        let key = if is_upload { "upload_attempt" } else { "download_attempt" };
+    pub async fn report_process_spoofing(&self, data: ProcessSpoofingData) -> bool {
+        let endpoints = self.endpoints();
+        let url = endpoints.get("process_spoofing").unwrap();
+        match self.send_with_retry(self.client.post(url).timeout(self.short_timeout).json(&data)).await {
+            Ok(resp) if resp.status().is_success() => true,
+            Ok(resp) => {
+                log_failed_response("process_spoofing", resp).await;
+                false
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub async fn record_access_attempt(&self, data: AccessAttemptData, kind: AccessAttemptKind) -> bool {
+        let endpoints = self.endpoints();
+        let key = match kind {
+            AccessAttemptKind::Upload => "upload_attempt",
+            AccessAttemptKind::Download => "download_attempt",
+            AccessAttemptKind::Clipboard => "clipboard_attempt",
+            AccessAttemptKind::QuickExit => "quick_exit",
+            AccessAttemptKind::SuspiciousUrl => "suspicious_url",
+        };
         let url = endpoints.get(key).unwrap();
 
-        match self.client.post(url).json(&data).send().await {
-            Ok(resp) => resp.status().is_success(),
+        match self.send(self.client.post(url).timeout(self.short_timeout).json(&data)).await {
+            Ok(resp) if resp.status().is_success() => true,
+            Ok(resp) => {
+                log_failed_response(key, resp).await;
+                false
+            }
             Err(_) => false,
         }
     }
 }
+
+/// A 4xx other than 429: the server refused the payload itself, so resending it won't help.
+fn is_rejection(status: reqwest::StatusCode) -> bool {
+    status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// The Retry-After header of a response, given either as seconds or as an HTTP date.
+fn retry_after(resp: &reqwest::Response) -> Option<Duration> {
+    let value = resp.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+fn response_validators(resp: &reqwest::Response) -> CacheValidators {
+    let header = |name| resp.headers().get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+    CacheValidators {
+        etag: header(reqwest::header::ETAG),
+        last_modified: header(reqwest::header::LAST_MODIFIED),
+    }
+}
+
+/// Logs the status and body of a failed POST, so schema rejections (e.g. a 422 naming a
+/// missing field) can be diagnosed. Long bodies are truncated.
+async fn log_failed_response(endpoint_key: &str, resp: reqwest::Response) {
+    const MAX_BODY_CHARS: usize = 500;
+    let status = resp.status();
+    let body = resp.text().await.unwrap_or_default();
+    let body = body.trim();
+    let shown: String = body.chars().take(MAX_BODY_CHARS).collect();
+    let ellipsis = if shown.len() < body.len() { "..." } else { "" };
+    println!("[DEBUG] {} request failed with status {}: {}{}", endpoint_key, status, shown, ellipsis);
+}
+
+/// Packages the last 1000 lines of a log file, gzip-compressed when possible.
+fn build_log_data(log_path: &Path, content: &str) -> LogData {
+    let lines: Vec<&str> = content.lines().collect();
+    let recent_lines = if lines.len() > 1000 {
+        &lines[lines.len() - 1000..]
+    } else {
+        &lines[..]
+    };
+
+    let log_text = recent_lines.join("\n");
+    // Fall back to sending the text as-is if compression fails
+    let (log_content, compressed) = match gzip_base64(&log_text) {
+        Some(encoded) => (encoded, true),
+        None => (log_text, false),
+    };
+
+    LogData {
+        device_id: get_device_id(),
+        log_type: log_path.file_stem().unwrap().to_str().unwrap().to_string(),
+        log_content,
+        timestamp: Local::now().to_rfc3339(),
+        file_size: content.len(),
+        compressed,
+    }
+}
+
+fn gzip_base64(text: &str) -> Option<String> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes()).ok()?;
+    let compressed = encoder.finish().ok()?;
+    Some(base64::engine::general_purpose::STANDARD.encode(compressed))
+}
+
+/// Proxy for all requests. reqwest reads basic-auth credentials from the URL itself;
+/// NO_PROXY is still honoured since an explicit proxy would otherwise ignore it.
+fn build_proxy(proxy_url: &str) -> reqwest::Result<reqwest::Proxy> {
+    Ok(reqwest::Proxy::all(proxy_url)?.no_proxy(reqwest::NoProxy::from_env()))
+}
+
+/// The proxy URL with any password removed, for logging.
+fn redact_proxy_url(proxy_url: &str) -> String {
+    match url::Url::parse(proxy_url) {
+        Ok(mut parsed) => {
+            if parsed.password().is_some() {
+                let _ = parsed.set_password(Some("****"));
+            }
+            parsed.to_string()
+        }
+        Err(_) => "<unparseable>".to_string(),
+    }
+}
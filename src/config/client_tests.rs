@@ -0,0 +1,368 @@
+//! APIClient against a mock server: the exact method, path and JSON each call sends, and how
+//! responses are parsed. Field names here are the server's contract, so a failing assertion
+//! usually means a serde rename or endpoint path changed.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde_json::json;
+use wiremock::matchers::{body_json, body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use crate::config::api_config::{get_device_id, get_user_id, reload_api_config, set_base_url_override};
+use crate::config::client::{APIClient, AppUsageData, EnforcementHealth, ProcessSpoofingData, UploadOutcome, UrlMonitoringData, UrlVisit};
+
+const PREFIX: &str = "/api/python-client";
+
+/// A client for the mock server that doesn't retry, so each expectation sees one request.
+fn client_for(server: &MockServer) -> APIClient {
+    APIClient::with_base_url(&server.uri(), 0, Duration::from_millis(1), Duration::from_secs(5), Duration::from_secs(5))
+}
+
+fn device_path(endpoint: &str) -> String {
+    format!("{}/devices/{}/{}", PREFIX, get_device_id(), endpoint)
+}
+
+fn success(data: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({ "success": true, "message": "ok", "data": data }))
+}
+
+fn failure(message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({ "success": false, "message": message, "data": null }))
+}
+
+fn local_time(rfc3339: &str) -> DateTime<Local> {
+    DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Local)
+}
+
+#[tokio::test]
+async fn register_device_posts_device_info() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/devices/register", PREFIX)))
+        .and(header("content-type", "application/json"))
+        .and(header("x-device-id", get_device_id().as_str()))
+        .and(body_partial_json(json!({
+            "deviceId": get_device_id(),
+            "userId": get_user_id(),
+            "platform": "Windows",
+            "monitorVersion": "2.1",
+        })))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert!(client_for(&server).register_device().await);
+
+    let request = &server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    for field in ["deviceName", "firstSeen", "timezone"] {
+        assert!(body[field].is_string(), "missing {}", field);
+    }
+}
+
+#[tokio::test]
+async fn register_device_reports_server_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/devices/register", PREFIX)))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert!(!client_for(&server).register_device().await);
+}
+
+#[tokio::test]
+async fn send_heartbeat_posts_status_and_health() {
+    let server = MockServer::start().await;
+    let health = EnforcementHealth {
+        enforcement_mode: "enforce".to_string(),
+        last_url_read_ok: Some(true),
+        secs_since_last_url_read: Some(4),
+        partial_access_healthy: true,
+        blacklist_size: 12,
+        outside_working_hours: false,
+        blocked_since_last_heartbeat: 2,
+        suspicious_since_last_heartbeat: 1,
+        dialogs_closed_since_last_heartbeat: 0,
+    };
+    Mock::given(method("POST"))
+        .and(path(device_path("heartbeat")))
+        .and(body_json(json!({
+            "deviceId": get_device_id(),
+            "paused": true,
+            "health": {
+                "enforcementMode": "enforce",
+                "lastUrlReadOk": true,
+                "secsSinceLastUrlRead": 4,
+                "partialAccessHealthy": true,
+                "blacklistSize": 12,
+                "outsideWorkingHours": false,
+                "blockedSinceLastHeartbeat": 2,
+                "suspiciousSinceLastHeartbeat": 1,
+                "dialogsClosedSinceLastHeartbeat": 0,
+            },
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert!(client_for(&server).send_heartbeat(true, None, health).await);
+}
+
+#[tokio::test]
+async fn upload_urls_posts_url_data() {
+    let server = MockServer::start().await;
+    let first = local_time("2024-05-01T09:00:00+02:00");
+    let last = local_time("2024-05-01T09:40:00+02:00");
+    Mock::given(method("POST"))
+        .and(path(device_path("urls")))
+        .and(body_json(json!({
+            "deviceId": get_device_id(),
+            "timestamp": "2024-05-01T10:00:00+02:00",
+            "timezone": "Europe/Berlin",
+            "urls": ["https://example.com/", "https://docs.rs/"],
+            "visits": [
+                { "url": "https://example.com/", "firstSeen": first, "lastSeen": last, "visitCount": 4 },
+                { "url": "https://docs.rs/", "firstSeen": last, "lastSeen": last, "visitCount": 1 },
+            ],
+            "blockedCount": 1,
+            "suspiciousCount": 0,
+            "totalVisits": 5,
+            "isPrivate": false,
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let data = UrlMonitoringData {
+        device_id: get_device_id(),
+        timestamp: "2024-05-01T10:00:00+02:00".to_string(),
+        timezone: "Europe/Berlin".to_string(),
+        urls: vec!["https://example.com/".to_string(), "https://docs.rs/".to_string()],
+        visits: vec![
+            UrlVisit { url: "https://example.com/".to_string(), first_seen: first, last_seen: last, visit_count: 4 },
+            UrlVisit { url: "https://docs.rs/".to_string(), first_seen: last, last_seen: last, visit_count: 1 },
+        ],
+        blocked_count: 1,
+        suspicious_count: 0,
+        total_visits: 5,
+        is_private: false,
+    };
+    assert_eq!(client_for(&server).upload_urls(data).await, UploadOutcome::Sent);
+}
+
+#[tokio::test]
+async fn upload_app_usage_posts_usage_data() {
+    let server = MockServer::start().await;
+    let mut hourly = [0.0; 24];
+    hourly[9] = 1500.0;
+    hourly[10] = 500.0;
+    Mock::given(method("POST"))
+        .and(path(device_path("app-usage")))
+        .and(body_json(json!({
+            "deviceId": get_device_id(),
+            "timestamp": "2024-05-01T10:00:00+02:00",
+            "timezone": "Europe/Berlin",
+            "currentApp": "code",
+            "currentSessionDuration": 120.0,
+            "currentSessionEngagement": "active",
+            "totalAppsTracked": 3,
+            "totalTimeTracked": 3600.0,
+            "activeUsageTime": 3000.0,
+            "activeInputSeconds": 2500.0,
+            "appSwitches": 40,
+            "switchesPerMinute": 1,
+            "appLimitEnforcements": { "game": 1 },
+            "topApps": [{ "name": "code", "time": 2000.0 }],
+            "categoryBreakdown": { "Development": 2000.0 },
+            "categoryHourlyBreakdown": { "Development": hourly },
+            "sessionId": 1,
+            "remoteSession": false,
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let data = AppUsageData {
+        device_id: get_device_id(),
+        timestamp: "2024-05-01T10:00:00+02:00".to_string(),
+        timezone: "Europe/Berlin".to_string(),
+        current_app: "code".to_string(),
+        current_session_duration: 120.0,
+        current_session_engagement: "active".to_string(),
+        total_apps_tracked: 3,
+        total_time_tracked: 3600.0,
+        active_usage_time: 3000.0,
+        active_input_seconds: 2500.0,
+        app_switches: 40,
+        switches_per_minute: 1,
+        app_limit_enforcements: HashMap::from([("game".to_string(), 1)]),
+        top_apps: vec![json!({ "name": "code", "time": 2000.0 })],
+        category_breakdown: HashMap::from([("Development".to_string(), 2000.0)]),
+        category_hourly_breakdown: HashMap::from([("Development".to_string(), hourly)]),
+        session_id: 1,
+        remote_session: false,
+    };
+    assert_eq!(client_for(&server).upload_app_usage(data).await, UploadOutcome::Sent);
+}
+
+#[tokio::test]
+async fn rejected_upload_is_not_queued() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(device_path("urls")))
+        .respond_with(ResponseTemplate::new(400))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let data = UrlMonitoringData {
+        device_id: get_device_id(),
+        timestamp: "2024-05-01T10:00:00+02:00".to_string(),
+        timezone: "UTC".to_string(),
+        urls: Vec::new(),
+        visits: Vec::new(),
+        blocked_count: 0,
+        suspicious_count: 0,
+        total_visits: 0,
+        is_private: false,
+    };
+    assert_eq!(client_for(&server).upload_urls(data).await, UploadOutcome::Rejected);
+}
+
+#[tokio::test]
+async fn report_process_spoofing_posts_the_finding() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(device_path("process-spoofing")))
+        .and(body_json(json!({
+            "deviceId": get_device_id(),
+            "timestamp": "2024-05-01T10:00:00+02:00",
+            "name": "explorer",
+            "path": "C:\\Users\\me\\Downloads\\explorer.exe",
+            "originalName": "chrome",
+            "reason": "renamed executable",
+            "trackedAs": "chrome",
+        })))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let data = ProcessSpoofingData {
+        device_id: get_device_id(),
+        timestamp: "2024-05-01T10:00:00+02:00".to_string(),
+        name: "explorer".to_string(),
+        path: "C:\\Users\\me\\Downloads\\explorer.exe".to_string(),
+        original_name: Some("chrome".to_string()),
+        reason: "renamed executable".to_string(),
+        tracked_as: "chrome".to_string(),
+    };
+    assert!(client_for(&server).report_process_spoofing(data).await);
+}
+
+#[tokio::test]
+async fn get_blocked_urls_parses_the_list() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(device_path("blocked-urls")))
+        .respond_with(success(json!(["facebook.com", "*.casino.*"])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert_eq!(client_for(&server).get_blocked_urls().await, vec!["facebook.com", "*.casino.*"]);
+}
+
+#[tokio::test]
+async fn get_blocked_urls_is_empty_when_the_server_reports_failure() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(device_path("blocked-urls")))
+        .respond_with(failure("device not found"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert!(client_for(&server).get_blocked_urls().await.is_empty());
+}
+
+#[tokio::test]
+async fn get_partial_access_config_returns_the_data() {
+    let server = MockServer::start().await;
+    let config = json!({ "enabled": true, "sites": [{ "url": "drive.google.com", "allowUpload": false }] });
+    Mock::given(method("GET"))
+        .and(path(device_path("partial-access")))
+        .respond_with(success(config.clone()))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert_eq!(client_for(&server).get_partial_access_config_conditional().await, Some(config));
+}
+
+#[tokio::test]
+async fn get_partial_access_config_is_none_when_the_server_reports_failure() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(device_path("partial-access")))
+        .respond_with(failure("no policy"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert_eq!(client_for(&server).get_partial_access_config_conditional().await, None);
+}
+
+#[tokio::test]
+async fn get_partial_access_config_revalidates_with_the_etag() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(device_path("partial-access")))
+        .and(header("if-none-match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(device_path("partial-access")))
+        .respond_with(success(json!({ "enabled": false })).insert_header("etag", "\"v1\""))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client_for(&server);
+    assert_eq!(client.get_partial_access_config_conditional().await, Some(json!({ "enabled": false })));
+    // Unchanged config comes back as 304 and isn't reapplied
+    assert_eq!(client.get_partial_access_config_conditional().await, None);
+}
+
+#[tokio::test]
+async fn default_client_follows_a_reloaded_base_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(device_path("blocked-urls")))
+        .respond_with(success(json!(["example.com"])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = APIClient::new(0, Duration::from_millis(1), Duration::from_secs(5), Duration::from_secs(5));
+    // Other tests pass the mock server's URL directly, so only this one follows the override.
+    // The device id re-resolves to the same saved value, under its lock.
+    set_base_url_override(Some(&server.uri()));
+    reload_api_config();
+    let blocked = client.get_blocked_urls().await;
+    set_base_url_override(None);
+    reload_api_config();
+
+    assert_eq!(blocked, vec!["example.com"]);
+}
@@ -1,3 +1,7 @@
 pub mod settings;
 pub mod client;
 pub mod api_config;
+pub mod upload_queue;
+
+#[cfg(test)]
+mod client_tests;
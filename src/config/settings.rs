@@ -1,35 +1,297 @@
 use std::collections::HashMap;
 use std::sync::OnceLock;
+use serde::Deserialize;
 
-pub const CHECK_INTERVAL: u64 = 1;
+/// Milliseconds between browser URL checks, which is how quickly a blocked page is closed
+pub const URL_CHECK_INTERVAL_MS: u64 = 500;
+/// Seconds between foreground-app samples for usage tracking
+pub const APP_SAMPLE_INTERVAL_SECS: u64 = 3;
+/// Seconds between app usage / URL / log uploads
+pub const SYNC_INTERVAL: u64 = 60;
+/// Seconds between blocked-URL, partial-access and monitor config fetches
+pub const CONFIG_UPDATE_INTERVAL: u64 = 30;
+/// Seconds between heartbeats
+pub const HEARTBEAT_INTERVAL: u64 = 60;
+/// Add Wi-Fi SSID, local IP and corporate-subnet membership to heartbeats. Off by default
+/// for privacy.
+pub const REPORT_NETWORK_CONTEXT: bool = false;
+/// Random spread applied to the sync and config-update intervals (0.2 = +/-20%), so a fleet
+/// started at the same moment doesn't hit the server in lockstep. 0 disables it.
+pub const SCHEDULE_JITTER_FRACTION: f64 = 0.2;
+/// Send heartbeat, app usage, URLs and logs in one request per sync instead of four
+pub const BATCH_UPLOADS: bool = false;
+/// Apps listed in each sync's app usage upload, by active time
+pub const TOP_APPS_COUNT: usize = 5;
+/// Seconds between uploads of the complete per-app totals; 0 disables them
+pub const FULL_APP_EXPORT_INTERVAL: u64 = 3600;
+/// Days logs, daily archives and screenshots are kept on disk; 0 keeps them indefinitely
+pub const RETENTION_DAYS: u64 = 30;
+/// Most megabytes each of logs/, data/daily/ and data/screenshots/ may hold; 0 disables the cap
+pub const RETENTION_MAX_DIR_MB: u64 = 500;
+/// Sync snapshots that can wait for the uploader task before new ones are turned away
+pub const UPLOAD_CHANNEL_CAPACITY: usize = 4;
 pub const TRACK_APP_USAGE: bool = true;
+/// Sessions shorter than this many seconds are discarded. Lowering it records brief tool
+/// launches but adds noise to the time log.
 pub const MINIMUM_APP_TIME: u64 = 5;
+/// Seconds after which a long-running session is checkpointed into the totals
+pub const SESSION_CHECKPOINT_SECS: u64 = 300;
+/// Smoothing factor (0-1] for each app's engagement score, an exponential moving average of
+/// session lengths. Higher values follow the latest session more closely.
+pub const ENGAGEMENT_EMA_ALPHA: f64 = 0.3;
+/// Seconds without keyboard/mouse input before the device is considered idle
+pub const IDLE_THRESHOLD_SECS: f64 = 120.0;
+/// Input within this many seconds counts the tick as active use rather than passive viewing
+pub const ACTIVE_INPUT_WINDOW_SECS: f64 = 10.0;
+/// Share of a session with recent input needed to classify it as "active"
+pub const ACTIVE_SESSION_INPUT_RATIO: f64 = 0.5;
+pub const MAX_QUEUE_BYTES: u64 = 10 * 1024 * 1024;
+/// Seconds an address-bar value must stay unchanged before it counts as a visit
+pub const URL_DEBOUNCE_SECS: f64 = 1.5;
+/// Visited URLs kept for the next upload; the oldest are dropped past this
+pub const URL_HISTORY_CAP: usize = 500;
+/// Leaving a sensitive URL (see "sensitiveUrlPatterns") faster than this many seconds is flagged
+/// as a suspicious quick exit
+pub const SENSITIVE_MIN_DWELL_SECS: f64 = 2.0;
+/// Terminate a browser that keeps a blocked page open after WM_CLOSE (e.g. a "Leave site?" prompt)
+pub const FORCE_KILL_ON_BLOCK: bool = false;
+/// Ticks a blocked page may survive WM_CLOSE before the browser process is terminated
+pub const FORCE_KILL_AFTER_TICKS: u32 = 3;
+/// Close a foreground fullscreen/kiosk browser whose URL can't be read from the address bar
+/// or page, so F11 can't be used to hide a blocked page. Off by default since it also closes
+/// fullscreen video on allowed sites when the page URL isn't exposed.
+pub const CLOSE_UNREADABLE_FULLSCREEN: bool = false;
+/// Escalate when a blocked file dialog survives WM_CLOSE: cancel it and close its owner window,
+/// then terminate its process. Off by default since the owner is usually the browser itself.
+pub const ESCALATE_DIALOG_CLOSE: bool = false;
+/// Close attempts on the same dialog before its process is terminated (with escalation on)
+#[cfg(windows)]
+pub const DIALOG_KILL_AFTER_ATTEMPTS: u32 = 3;
+/// Seconds within which a dialog with the same class and title counts as the same dialog,
+/// so one that is reopened right after closing still escalates
+#[cfg(windows)]
+pub const DIALOG_REPEAT_WINDOW_SECS: u64 = 10;
+/// Share of pushed partial-access sites that may fail to parse before the whole push is
+/// refused and the current sites are kept
+pub const PARTIAL_ACCESS_MAX_REJECTED_RATIO: f64 = 0.5;
+/// Seconds to wait before restarting the partial-access thread after it panics
+pub const PARTIAL_ACCESS_RESTART_SECS: u64 = 5;
+/// Seconds before the same blocked URL is logged and audited again. Each URL has its own timer.
+pub const ALERT_THROTTLE_SECS: f64 = 2.0;
+/// Show a toast to the user when a page is blocked
+pub const SHOW_BLOCK_NOTIFICATIONS: bool = true;
+/// Minimum seconds between block toasts for the same URL
+pub const BLOCK_NOTIFICATION_INTERVAL_SECS: f64 = 5.0;
+/// Save a screenshot when a blocked URL or file dialog is detected
+pub const CAPTURE_SCREENSHOTS: bool = false;
+/// Upload saved screenshots during the next sync
+pub const UPLOAD_SCREENSHOTS: bool = false;
+/// Minimum seconds between screenshots, so repeated blocks don't fill the disk
+pub const SCREENSHOT_MIN_INTERVAL_SECS: u64 = 30;
+/// Screenshots kept on disk; older ones are deleted
+pub const MAX_SCREENSHOTS: usize = 200;
+/// Local port for the JSON status endpoint (127.0.0.1 only); 0 disables it
+pub const STATUS_PORT: u16 = 8765;
+pub const STATUS_PORT_ENV_VAR: &str = "ANTIGRAVITY_STATUS_PORT";
+/// Per-install secret that POST requests to the status endpoint must send as X-Status-Token
+pub const STATUS_TOKEN_FILE: &str = "data/status_token";
+pub const API_MAX_RETRIES: u32 = 3;
+/// Seconds before a heartbeat, registration, config fetch or access report gives up, and the
+/// connect timeout for every request, so a dead server is noticed quickly
+pub const API_SHORT_TIMEOUT_SECS: u64 = 5;
+/// Seconds allowed for log, app usage, URL, batch and screenshot uploads, which can be large
+/// on slow VPN links
+pub const API_UPLOAD_TIMEOUT_SECS: u64 = 60;
+pub const API_RETRY_BASE_DELAY_MS: u64 = 200;
+/// How often drive letters are checked for newly inserted USB drives
+#[cfg(windows)]
+pub const USB_POLL_INTERVAL_MS: u64 = 2000;
+/// Seconds to hold off all requests after a 429 without a usable Retry-After header
+pub const RATE_LIMIT_DEFAULT_COOLDOWN_SECS: u64 = 60;
+/// Longest Retry-After honoured, so a bad header can't silence the agent indefinitely
+pub const RATE_LIMIT_MAX_COOLDOWN_SECS: u64 = 3600;
 
-pub fn get_ignore_apps() -> &'static [&'static str] {
-    static IGNORE_APPS: OnceLock<Vec<&'static str>> = OnceLock::new();
-    IGNORE_APPS.get_or_init(|| vec![
-        "explorer", "svchost", "System", "Idle", "Registry", "smss", "csrss",
-        "wininit", "winlogon", "services", "lsass", "taskhost", "dwm", "conhost",
-        "cmd", "powershell", "pwsh", "python", "pythonw", "javaw", "java",
-        "WmiPrvSE", "sihost", "ctfmon", "RuntimeBroker", "SearchUI",
-        "StartMenuExperienceHost", "Widgets", "Calculator", "notepad", "wordpad",
-        "mspaint", "SystemSettings", "Taskmgr", "SecurityHealthSystray",
-        "SecurityHealthService", "CybersecurityMonitor",
-    ])
+/// Status endpoint port from ANTIGRAVITY_STATUS_PORT, falling back to STATUS_PORT.
+pub fn get_status_port() -> u16 {
+    match std::env::var(STATUS_PORT_ENV_VAR) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            println!("[ERROR] Invalid {} '{}'. Using port {}", STATUS_PORT_ENV_VAR, value, STATUS_PORT);
+            STATUS_PORT
+        }),
+        Err(_) => STATUS_PORT,
+    }
 }
 
-pub fn get_app_categories() -> &'static HashMap<&'static str, Vec<&'static str>> {
-    static APP_CATEGORIES: OnceLock<HashMap<&'static str, Vec<&'static str>>> = OnceLock::new();
+/// Optional file extending the built-in ignore list and app categories
+pub const APPS_CONFIG_FILE: &str = "config/apps.json";
+
+#[derive(Deserialize, Default)]
+struct AppsConfig {
+    #[serde(rename = "ignoreApps", default)]
+    ignore_apps: Vec<String>,
+    #[serde(rename = "appCategories", default)]
+    app_categories: HashMap<String, Vec<String>>,
+    #[serde(rename = "domainCategories", default)]
+    domain_categories: HashMap<String, Vec<String>>,
+    #[serde(rename = "browserNames", default)]
+    browser_names: Vec<String>,
+}
+
+fn get_apps_config() -> &'static AppsConfig {
+    static APPS_CONFIG: OnceLock<AppsConfig> = OnceLock::new();
+    APPS_CONFIG.get_or_init(|| {
+        let content = match std::fs::read_to_string(APPS_CONFIG_FILE) {
+            Ok(c) => c,
+            Err(_) => return AppsConfig::default(),
+        };
+        match serde_json::from_str::<AppsConfig>(&content) {
+            Ok(config) => {
+                println!("[INFO] Loaded {}: {} ignored apps, {} app categories, {} domain categories", APPS_CONFIG_FILE,
+                    config.ignore_apps.len(), config.app_categories.len(), config.domain_categories.len());
+                config
+            }
+            Err(e) => {
+                println!("[WARN] Invalid {} ({}). Using built-in app lists.", APPS_CONFIG_FILE, e);
+                AppsConfig::default()
+            }
+        }
+    })
+}
+
+/// Built-in ignore list plus any names from config/apps.json.
+pub fn get_ignore_apps() -> &'static [String] {
+    static IGNORE_APPS: OnceLock<Vec<String>> = OnceLock::new();
+    IGNORE_APPS.get_or_init(|| {
+        let mut apps: Vec<String> = [
+            "explorer", "svchost", "System", "Idle", "Registry", "smss", "csrss",
+            "wininit", "winlogon", "services", "lsass", "taskhost", "dwm", "conhost",
+            "cmd", "powershell", "pwsh", "python", "pythonw", "javaw", "java",
+            "WmiPrvSE", "sihost", "ctfmon", "RuntimeBroker", "SearchUI",
+            "StartMenuExperienceHost", "Widgets", "Calculator", "notepad", "wordpad",
+            "mspaint", "SystemSettings", "Taskmgr", "SecurityHealthSystray",
+            "SecurityHealthService", "CybersecurityMonitor",
+        ].iter().map(|s| s.to_string()).collect();
+
+        for app in &get_apps_config().ignore_apps {
+            let app = app.trim();
+            if !app.is_empty() && !apps.iter().any(|a| a.eq_ignore_ascii_case(app)) {
+                apps.push(app.to_string());
+            }
+        }
+        apps
+    })
+}
+
+/// Check that a foreground process named like an ignored app really is that app, so a browser
+/// renamed to explorer.exe is still tracked
+pub const VERIFY_IGNORED_APPS: bool = true;
+/// Ignored apps that ship with Windows; one of these running from outside the Windows or
+/// Program Files directories is treated as a disguised executable
+pub const WINDOWS_COMPONENT_APPS: [&str; 22] = [
+    "explorer", "svchost", "smss", "csrss", "wininit", "winlogon", "services", "lsass", "taskhost", "dwm", "conhost",
+    "cmd", "wmiprvse", "sihost", "ctfmon", "runtimebroker", "searchui", "startmenuexperiencehost", "systemsettings",
+    "taskmgr", "notepad", "mspaint",
+];
+
+/// Seconds between checks for VPN/anonymizer clients and local proxies; 0 disables them
+pub const ANONYMIZER_CHECK_INTERVAL_SECS: u64 = 60;
+/// Process names (lowercase, without ".exe") of VPN and anonymizer clients used to get around
+/// URL blocking. Replaced by the server's "anonymizerProcesses" list when one is pushed.
+pub const DEFAULT_ANONYMIZER_PROCESSES: [&str; 12] = [
+    "psiphon3", "psiphon-tunnel-core", "protonvpn", "protonvpn.client", "protonvpnservice", "nordvpn", "expressvpn",
+    "windscribe", "hotspotshield", "tor", "ultrasurf", "lantern",
+];
+/// Treat every URL as blocked while an anonymizer is detected, since blocking can't see
+/// where its traffic really goes
+pub const BLOCK_BROWSING_ON_ANONYMIZER: bool = false;
+
+/// Top-level window classes of Chromium-based browsers and Firefox. Electron apps share the
+/// Chromium class, so a window also needs a browser executable or title to count.
+pub const BROWSER_WINDOW_CLASSES: [&str; 2] = ["Chrome_WidgetWin_1", "MozillaWindowClass"];
+/// Executables of the browsers whose windows are inspected regardless of their (possibly
+/// localized) title
+pub const BROWSER_EXECUTABLES: [&str; 7] = ["chrome.exe", "msedge.exe", "brave.exe", "firefox.exe", "opera.exe", "vivaldi.exe", "chromium.exe"];
+
+/// Lowercase substrings identifying browser windows by title, plus any "browserNames" from
+/// config/apps.json (e.g. a rebranded Chromium such as "acme secure browser").
+pub fn get_browser_names() -> &'static [String] {
+    static BROWSER_NAMES: OnceLock<Vec<String>> = OnceLock::new();
+    BROWSER_NAMES.get_or_init(|| {
+        let mut names: Vec<String> = ["chrome", "edge", "brave", "mozilla firefox", "opera", "vivaldi"]
+            .iter().map(|s| s.to_string()).collect();
+
+        for name in &get_apps_config().browser_names {
+            let name = name.trim().to_lowercase();
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    })
+}
+
+/// Built-in categories merged with config/apps.json. An app listed in the file moves to the
+/// file's category, so admins can recategorize built-in apps as well as add new ones.
+pub fn get_app_categories() -> &'static HashMap<String, Vec<String>> {
+    static APP_CATEGORIES: OnceLock<HashMap<String, Vec<String>>> = OnceLock::new();
     APP_CATEGORIES.get_or_init(|| {
-        let mut m = HashMap::new();
-        m.insert("Browsers", vec!["chrome", "firefox", "msedge", "opera", "brave", "vivaldi", "safari", "tor"]);
-        m.insert("Communication", vec!["teams", "zoom", "discord", "slack", "whatsapp", "signal", "telegram", "skype"]);
-        m.insert("Social Media", vec!["facebook", "instagram", "twitter", "tiktok", "reddit", "linkedin", "pinterest"]);
-        m.insert("Productivity", vec!["winword", "excel", "powerpnt", "outlook", "onenote", "notepad++", "vscode", "code"]);
-        m.insert("Entertainment", vec!["spotify", "vlc", "netflix", "disney+", "primevideo", "steam", "epicgameslauncher"]);
-        m.insert("Development", vec!["vscode", "code", "pycharm", "intellij", "androidstudio", "visualstudio", "git", "docker"]);
-        m.insert("Creative", vec!["photoshop", "illustrator", "premiere", "aftereffects", "blender", "audacity", "obs"]);
-        m.insert("Utilities", vec!["explorer", "taskmgr", "control", "settings", "calculator", "mspaint", "cmd", "powershell"]);
+        let defaults: [(&str, &[&str]); 8] = [
+            ("Browsers", &["chrome", "firefox", "msedge", "opera", "brave", "vivaldi", "safari", "tor"]),
+            ("Communication", &["teams", "zoom", "discord", "slack", "whatsapp", "signal", "telegram", "skype"]),
+            ("Social Media", &["facebook", "instagram", "twitter", "tiktok", "reddit", "linkedin", "pinterest"]),
+            ("Productivity", &["winword", "excel", "powerpnt", "outlook", "onenote", "notepad++", "vscode", "code"]),
+            ("Entertainment", &["spotify", "vlc", "netflix", "disney+", "primevideo", "steam", "epicgameslauncher"]),
+            ("Development", &["vscode", "code", "pycharm", "intellij", "androidstudio", "visualstudio", "git", "docker"]),
+            ("Creative", &["photoshop", "illustrator", "premiere", "aftereffects", "blender", "audacity", "obs"]),
+            ("Utilities", &["explorer", "taskmgr", "control", "settings", "calculator", "mspaint", "cmd", "powershell"]),
+        ];
+        let mut m: HashMap<String, Vec<String>> = defaults.iter()
+            .map(|(cat, apps)| (cat.to_string(), apps.iter().map(|a| a.to_string()).collect()))
+            .collect();
+
+        for (category, apps) in &get_apps_config().app_categories {
+            let apps: Vec<String> = apps.iter().map(|a| a.trim().to_lowercase()).filter(|a| !a.is_empty()).collect();
+            for other_apps in m.values_mut() {
+                other_apps.retain(|a| !apps.contains(a));
+            }
+            let entry = m.entry(category.clone()).or_default();
+            for app in apps {
+                if !entry.contains(&app) {
+                    entry.push(app);
+                }
+            }
+        }
+        m
+    })
+}
+
+/// Website categories for category-based URL blocking, keyed like get_app_categories and
+/// merged with "domainCategories" from config/apps.json. Each domain also covers its subdomains.
+pub fn get_domain_categories() -> &'static HashMap<String, Vec<String>> {
+    static DOMAIN_CATEGORIES: OnceLock<HashMap<String, Vec<String>>> = OnceLock::new();
+    DOMAIN_CATEGORIES.get_or_init(|| {
+        let defaults: [(&str, &[&str]); 4] = [
+            ("Social Media", &["facebook.com", "instagram.com", "twitter.com", "x.com", "tiktok.com", "reddit.com", "linkedin.com", "pinterest.com", "snapchat.com"]),
+            ("Entertainment", &["youtube.com", "netflix.com", "twitch.tv", "spotify.com", "disneyplus.com", "primevideo.com", "hulu.com"]),
+            ("Communication", &["discord.com", "web.whatsapp.com", "web.telegram.org", "slack.com", "zoom.us"]),
+            ("Gaming", &["store.steampowered.com", "epicgames.com", "roblox.com", "miniclip.com", "poki.com"]),
+        ];
+        let mut m: HashMap<String, Vec<String>> = defaults.iter()
+            .map(|(cat, domains)| (cat.to_string(), domains.iter().map(|d| d.to_string()).collect()))
+            .collect();
+
+        for (category, domains) in &get_apps_config().domain_categories {
+            let domains: Vec<String> = domains.iter().map(|d| d.trim().to_lowercase()).filter(|d| !d.is_empty()).collect();
+            for other_domains in m.values_mut() {
+                other_domains.retain(|d| !domains.contains(d));
+            }
+            let entry = m.entry(category.clone()).or_default();
+            for domain in domains {
+                if !entry.contains(&domain) {
+                    entry.push(domain);
+                }
+            }
+        }
         m
     })
 }
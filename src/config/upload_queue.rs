@@ -0,0 +1,99 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::config::settings::MAX_QUEUE_BYTES;
+
+const QUEUE_DIR: &str = "data/queue";
+
+/// Endpoints whose payloads are queued to disk when an upload fails.
+pub const QUEUED_ENDPOINTS: [&str; 4] = ["url_upload", "app_usage_upload", "log_upload", "batch_upload"];
+
+fn queue_path(endpoint_key: &str) -> PathBuf {
+    PathBuf::from(QUEUE_DIR).join(format!("{}.jsonl", endpoint_key))
+}
+
+/// Appends a JSON payload to the endpoint's newline-delimited queue file.
+pub fn enqueue(endpoint_key: &str, payload: &str) -> bool {
+    if let Err(e) = fs::create_dir_all(QUEUE_DIR) {
+        println!("[ERROR] Failed to create upload queue directory: {}", e);
+        return false;
+    }
+
+    let path = queue_path(endpoint_key);
+    match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(mut file) => {
+            if let Err(e) = writeln!(file, "{}", payload) {
+                println!("[ERROR] Failed to queue {} payload: {}", endpoint_key, e);
+                return false;
+            }
+        }
+        Err(e) => {
+            println!("[ERROR] Failed to open upload queue for {}: {}", endpoint_key, e);
+            return false;
+        }
+    }
+
+    println!("[INFO] Upload failed, queued {} payload for retry", endpoint_key);
+    enforce_size_cap(endpoint_key);
+    true
+}
+
+/// Returns the queued payloads for an endpoint, oldest first.
+pub fn pending(endpoint_key: &str) -> Vec<String> {
+    match fs::read_to_string(queue_path(endpoint_key)) {
+        Ok(content) => content.lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| l.to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Replaces the endpoint's queue with the payloads that still need sending.
+pub fn retain(endpoint_key: &str, remaining: &[String]) {
+    let path = queue_path(endpoint_key);
+    let result = if remaining.is_empty() {
+        fs::remove_file(&path)
+    } else {
+        fs::write(&path, format!("{}\n", remaining.join("\n")))
+    };
+
+    if let Err(e) = result {
+        println!("[ERROR] Failed to update upload queue for {}: {}", endpoint_key, e);
+    }
+}
+
+fn total_queue_size() -> u64 {
+    match fs::read_dir(QUEUE_DIR) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.metadata().ok())
+            .map(|m| m.len())
+            .sum(),
+        Err(_) => 0,
+    }
+}
+
+/// Drops the oldest entries of the given queue until the whole queue directory fits in MAX_QUEUE_BYTES.
+fn enforce_size_cap(endpoint_key: &str) {
+    let total = total_queue_size();
+    if total <= MAX_QUEUE_BYTES {
+        return;
+    }
+
+    let entries = pending(endpoint_key);
+    let mut excess = total - MAX_QUEUE_BYTES;
+    let mut dropped = 0;
+    // Always keep the newest entry
+    while dropped + 1 < entries.len() && excess > 0 {
+        excess = excess.saturating_sub(entries[dropped].len() as u64 + 1);
+        dropped += 1;
+    }
+
+    if dropped > 0 {
+        println!("[WARN] Upload queue exceeds {} bytes. Dropping {} oldest {} payloads.",
+            MAX_QUEUE_BYTES, dropped, endpoint_key);
+        retain(endpoint_key, &entries[dropped..]);
+    }
+}
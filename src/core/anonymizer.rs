@@ -0,0 +1,131 @@
+use sysinfo::System;
+#[cfg(windows)]
+use windows::core::{HSTRING, PCWSTR};
+#[cfg(windows)]
+use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD, RRF_RT_REG_SZ};
+
+const INTERNET_SETTINGS_KEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Internet Settings";
+
+/// A VPN/anonymizer client or proxy setting that lets browsing bypass URL blocking.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnonymizerFinding {
+    /// "process" or "proxy"
+    pub kind: &'static str,
+    /// Process name, or the proxy server / PAC URL
+    pub name: String,
+}
+
+/// Running processes named in `process_names` (lowercase, without ".exe") and the current
+/// user's WinINet proxy settings. A proxy or PAC script counts when it points at this machine,
+/// as tunnels like Psiphon set up, or at a host outside `allowed_proxies` when that list is set.
+pub fn scan(process_names: &[String], allowed_proxies: &[String]) -> Vec<AnonymizerFinding> {
+    let mut findings = Vec::new();
+
+    if !process_names.is_empty() {
+        let mut sys = System::new();
+        sys.refresh_processes();
+        let mut seen: Vec<String> = sys.processes().values()
+            .map(|p| p.name().to_lowercase().replace(".exe", ""))
+            .filter(|name| process_names.contains(name))
+            .collect();
+        seen.sort();
+        seen.dedup();
+        findings.extend(seen.into_iter().map(|name| AnonymizerFinding { kind: "process", name }));
+    }
+
+    let proxy_enabled = registry_dword(INTERNET_SETTINGS_KEY, "ProxyEnable").unwrap_or(0) != 0;
+    let proxy_server = registry_string(INTERNET_SETTINGS_KEY, "ProxyServer").filter(|_| proxy_enabled);
+    let pac_url = registry_string(INTERNET_SETTINGS_KEY, "AutoConfigURL");
+    for proxy in proxy_server.into_iter().chain(pac_url) {
+        if is_suspicious_proxy(&proxy, allowed_proxies) {
+            findings.push(AnonymizerFinding { kind: "proxy", name: proxy });
+        }
+    }
+
+    findings
+}
+
+/// ProxyServer is "host:port" or per-protocol "http=host:port;https=host:port"; a PAC entry
+/// is a URL. Hosts on the allow list are always accepted, loopback ones included, since some
+/// corporate clients run a local PAC or proxy. Otherwise a host is suspicious if it is loopback
+/// or, with an allow list, isn't on it.
+fn is_suspicious_proxy(value: &str, allowed_proxies: &[String]) -> bool {
+    value.split(';')
+        // Drop a "http=" protocol prefix, but not an '=' in a PAC URL's query
+        .map(|entry| entry.split_once('=').filter(|(proto, _)| !proto.contains('/')).map_or(entry, |(_, proxy)| proxy))
+        .map(|entry| entry.trim().to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            let host = proxy_host(&entry);
+            if allowed_proxies.iter().any(|allowed| host == allowed.as_str()) {
+                return false;
+            }
+            let loopback = host == "localhost" || host.starts_with("127.") || host == "[::1]" || host == "0.0.0.0";
+            loopback || !allowed_proxies.is_empty()
+        })
+}
+
+/// Host of a proxy entry or PAC URL, keeping IPv6 brackets: "http://[::1]:8080/x" -> "[::1]".
+fn proxy_host(entry: &str) -> &str {
+    let without_scheme = entry.split("://").last().unwrap_or(entry);
+    if without_scheme.starts_with('[') {
+        return without_scheme.find(']').map_or(without_scheme, |end| &without_scheme[..=end]);
+    }
+    without_scheme.split(['/', ':']).next().unwrap_or_default()
+}
+
+#[cfg(windows)]
+fn registry_dword(key: &str, value: &str) -> Option<u32> {
+    let mut data: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    unsafe {
+        RegGetValueW(HKEY_CURRENT_USER, &HSTRING::from(key), &HSTRING::from(value), RRF_RT_REG_DWORD, None,
+            Some(&mut data as *mut u32 as *mut _), Some(&mut size)).ok()?;
+    }
+    Some(data)
+}
+
+#[cfg(windows)]
+fn registry_string(key: &str, value: &str) -> Option<String> {
+    let mut buffer = [0u16; 1024];
+    let mut size = std::mem::size_of_val(&buffer) as u32;
+    unsafe {
+        RegGetValueW(HKEY_CURRENT_USER, &HSTRING::from(key), &HSTRING::from(value), RRF_RT_REG_SZ, None,
+            Some(buffer.as_mut_ptr() as *mut _), Some(&mut size)).ok()?;
+        let text = PCWSTR(buffer.as_ptr()).to_string().ok()?;
+        let text = text.trim().to_string();
+        (!text.is_empty()).then_some(text)
+    }
+}
+
+/// WinINet proxy settings only exist on Windows; elsewhere only processes are scanned.
+#[cfg(not(windows))]
+fn registry_dword(_key: &str, _value: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(not(windows))]
+fn registry_string(_key: &str, _value: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_suspicious_proxy;
+
+    #[test]
+    fn loopback_proxy_is_suspicious_unless_allowed() {
+        assert!(is_suspicious_proxy("127.0.0.1:8080", &[]));
+        assert!(is_suspicious_proxy("http://localhost:3128/proxy.pac", &[]));
+        assert!(!is_suspicious_proxy("127.0.0.1:8080", &["127.0.0.1".to_string()]));
+        assert!(!is_suspicious_proxy("http://localhost:9000/proxy.pac", &["localhost".to_string()]));
+    }
+
+    #[test]
+    fn allow_list_limits_remote_proxies() {
+        let allowed = ["proxy.corp.example".to_string()];
+        assert!(!is_suspicious_proxy("proxy.example.net:8080", &[]));
+        assert!(!is_suspicious_proxy("http=proxy.corp.example:8080;https=proxy.corp.example:8443", &allowed));
+        assert!(is_suspicious_proxy("http=proxy.corp.example:8080;https=tunnel.example.net:443", &allowed));
+    }
+}
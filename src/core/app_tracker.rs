@@ -1,44 +1,222 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::time::{SystemTime, UNIX_EPOCH, Duration};
 use std::sync::{Arc, Mutex};
-use std::fs::OpenOptions;
+use std::fs::{self, OpenOptions};
 use std::io::Write;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate, Timelike};
 use serde::{Serialize, Deserialize};
 use sysinfo::{System};
-use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
-use windows::Win32::UI::Input::KeyboardAndMouse::GetLastInputInfo;
-use windows::Win32::UI::Input::KeyboardAndMouse::LASTINPUTINFO;
 
-use crate::config::settings::{get_ignore_apps, get_app_categories, MINIMUM_APP_TIME, TRACK_APP_USAGE};
+use crate::config::client::ProcessSpoofingData;
+use crate::core::audit::audit_event;
+use crate::core::browser_monitor::UrlPrivacy;
+use crate::core::foreground::{default_provider, ForegroundProvider};
+use crate::core::lock::LockRecover;
+use crate::core::process_identity::{in_system_location, original_file_name};
+use crate::core::session::current_session;
+use crate::config::settings::{
+    get_browser_names, get_ignore_apps, get_app_categories, ACTIVE_INPUT_WINDOW_SECS, BROWSER_EXECUTABLES, ACTIVE_SESSION_INPUT_RATIO, ENGAGEMENT_EMA_ALPHA, IDLE_THRESHOLD_SECS, MINIMUM_APP_TIME,
+    SESSION_CHECKPOINT_SECS, TOP_APPS_COUNT, TRACK_APP_USAGE, VERIFY_IGNORED_APPS, WINDOWS_COMPONENT_APPS,
+};
+
+const APP_DATA_FILE: &str = "data/app_data.json";
+/// Distinct window titles remembered per app, so long browsing days don't grow the data unbounded
+const MAX_TITLES_PER_APP: usize = 100;
+/// Normalized title suffixes browsers append that don't contain the process name
+const KNOWN_TITLE_SUFFIXES: [&str; 3] = ["googlechrome", "microsoftedge", "mozillafirefox"];
+/// Process ids whose ignored-app check is remembered before the cache is reset
+const MAX_VERIFIED_PIDS: usize = 1000;
+/// Longer gaps between samples (sleep, hibernation) aren't counted as tracked wall time
+const MAX_WALL_SAMPLE_GAP_SECS: f64 = 60.0;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct AppData {
     pub app_total_time: HashMap<String, f64>,
     pub app_sessions: HashMap<String, u32>,
     pub app_category_time: HashMap<String, f64>,
+    /// Seconds per category in each local hour of the day, indexed 0-23
+    #[serde(default)]
+    pub app_category_hourly: HashMap<String, [f64; 24]>,
+    /// Seconds spent per window title, keyed by app
+    #[serde(default)]
+    pub app_window_titles: HashMap<String, HashMap<String, f64>>,
+    /// CPU and memory samples per app, taken on each tick it is in the foreground
+    #[serde(default)]
+    pub app_resource_usage: HashMap<String, ResourceUsage>,
+    /// Seconds per app with keyboard or mouse input in the last ACTIVE_INPUT_WINDOW_SECS
+    #[serde(default)]
+    pub app_active_input_time: HashMap<String, f64>,
+    /// Times the foreground moved from one tracked app to another
+    #[serde(default)]
+    pub app_switches: u32,
+    /// Exponential moving average of session seconds per app, reported as its engagement score
+    #[serde(default)]
+    pub app_session_ema: HashMap<String, f64>,
+    /// Times each app was terminated for exceeding its daily limit
+    #[serde(default)]
+    pub app_limit_enforcements: HashMap<String, u32>,
+    /// Wall-clock seconds the tracker was running, including idle time, ignored apps and
+    /// sessions too short to record
+    #[serde(default)]
+    pub wall_time_tracked: f64,
+    /// Local date these totals belong to
+    #[serde(default)]
+    pub day: Option<NaiveDate>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ResourceUsage {
+    /// Sum of CPU samples, in percent of total machine CPU
+    pub cpu_total: f64,
+    pub samples: u64,
+    pub peak_memory_bytes: u64,
+}
+
+impl ResourceUsage {
+    pub fn avg_cpu(&self) -> f64 {
+        if self.samples == 0 { 0.0 } else { self.cpu_total / self.samples as f64 }
+    }
+}
+
+impl AppData {
+    fn empty(day: NaiveDate) -> Self {
+        AppData {
+            app_total_time: HashMap::new(),
+            app_sessions: HashMap::new(),
+            app_category_time: HashMap::new(),
+            app_category_hourly: HashMap::new(),
+            app_window_titles: HashMap::new(),
+            app_resource_usage: HashMap::new(),
+            app_active_input_time: HashMap::new(),
+            app_switches: 0,
+            app_session_ema: HashMap::new(),
+            app_limit_enforcements: HashMap::new(),
+            wall_time_tracked: 0.0,
+            day: Some(day),
+        }
+    }
 }
 
 pub struct AppTimeTracker {
     pub current_app: Option<String>,
     pub app_start_time: Option<f64>,
+    pub current_title: Option<String>,
+    title_start_time: Option<f64>,
+    foreground_title: Option<String>,
+    /// CPU percent and memory bytes of the foreground app's process, sampled by get_active_app
+    foreground_usage: Option<(f64, u64)>,
+    /// Process id of the foreground app, set by get_active_app
+    foreground_pid: Option<u32>,
+    /// Seconds of the current session with recent input, used to tell active from passive use
+    session_input_time: f64,
+    last_sample_time: Option<f64>,
+    /// Time of the previous track_app_usage call, for wall_time_tracked
+    last_wall_sample: Option<f64>,
+    /// Last non-ignored app in the foreground, kept across idle and ignored-app gaps
+    last_tracked_app: Option<String>,
+    /// Times of app switches in the last minute
+    recent_switches: VecDeque<f64>,
     pub data: Arc<Mutex<AppData>>,
+    pub idle_threshold_secs: f64,
+    /// Sessions shorter than this are discarded
+    pub minimum_app_time_secs: f64,
+    /// Long sessions are recorded into the totals every this many seconds
+    pub session_checkpoint_secs: f64,
+    /// Smoothing factor for app_session_ema
+    pub engagement_ema_alpha: f64,
+    /// Daily seconds allowed per app (e.g. "steam" -> 3600); the app is closed once it's used up
+    pub app_limits: HashMap<String, f64>,
+    /// Server-pushed (app substring, category) pairs, longest substring first; checked before
+    /// the built-in categories
+    category_overrides: Vec<(String, String)>,
+    /// Apps included in get_app_data_for_api's top list
+    pub top_apps_count: usize,
+    /// Browser window titles name the open page, so they are only reported under UrlPrivacy::Full
+    pub url_privacy: UrlPrivacy,
+    /// Check that processes named like ignored apps aren't renamed executables
+    pub verify_ignored_apps: bool,
+    /// Result of disguised_app_name per process id, so each process is checked and reported once
+    verified_pids: HashMap<u32, Option<String>>,
+    /// Disguised processes found since the last take_spoofed_processes, for reporting to the server
+    spoofed_processes: Vec<ProcessSpoofingData>,
     sys: System,
+    foreground: Box<dyn ForegroundProvider>,
 }
 
 impl AppTimeTracker {
     pub fn new() -> Self {
-        let data = AppData {
-            app_total_time: HashMap::new(),
-            app_sessions: HashMap::new(),
-            app_category_time: HashMap::new(),
-        };
+        let data = Self::load_data();
 
         AppTimeTracker {
             current_app: None,
             app_start_time: None,
+            current_title: None,
+            title_start_time: None,
+            foreground_title: None,
+            foreground_usage: None,
+            foreground_pid: None,
+            session_input_time: 0.0,
+            last_sample_time: None,
+            last_wall_sample: None,
+            last_tracked_app: None,
+            recent_switches: VecDeque::new(),
             data: Arc::new(Mutex::new(data)),
+            idle_threshold_secs: IDLE_THRESHOLD_SECS,
+            minimum_app_time_secs: MINIMUM_APP_TIME as f64,
+            session_checkpoint_secs: SESSION_CHECKPOINT_SECS as f64,
+            engagement_ema_alpha: ENGAGEMENT_EMA_ALPHA,
+            app_limits: HashMap::new(),
+            category_overrides: Vec::new(),
+            top_apps_count: TOP_APPS_COUNT,
+            url_privacy: UrlPrivacy::Full,
+            verify_ignored_apps: VERIFY_IGNORED_APPS,
+            verified_pids: HashMap::new(),
+            spoofed_processes: Vec::new(),
             sys: System::new_all(),
+            foreground: default_provider(),
+        }
+    }
+
+    /// Loads persisted usage totals, starting fresh if the file is missing or corrupt.
+    pub fn load_data() -> AppData {
+        let today = Local::now().date_naive();
+        let empty = AppData::empty(today);
+
+        let content = match fs::read_to_string(APP_DATA_FILE) {
+            Ok(c) => c,
+            Err(_) => return empty,
+        };
+
+        match serde_json::from_str::<AppData>(&content) {
+            Ok(mut data) => {
+                // Files written before totals were kept per day are treated as today's
+                data.day.get_or_insert(today);
+                println!("[INFO] Loaded app usage data for {} apps from {}", data.app_total_time.len(), APP_DATA_FILE);
+                data
+            }
+            Err(e) => {
+                println!("[WARN] Corrupt app usage data in {} ({}). Starting fresh.", APP_DATA_FILE, e);
+                empty
+            }
+        }
+    }
+
+    pub fn save_data(&self) {
+        let json = {
+            let data = self.data.lock_recover();
+            match serde_json::to_string(&*data) {
+                Ok(j) => j,
+                Err(e) => {
+                    println!("[ERROR] Failed to serialize app usage data: {}", e);
+                    return;
+                }
+            }
+        };
+
+        // Write to a temp file first so a crash mid-write can't corrupt the saved totals
+        let tmp_path = format!("{}.tmp", APP_DATA_FILE);
+        if let Err(e) = fs::write(&tmp_path, json).and_then(|_| fs::rename(&tmp_path, APP_DATA_FILE)) {
+            println!("[ERROR] Failed to save app usage data: {}", e);
         }
     }
 
@@ -48,12 +226,15 @@ impl AppTimeTracker {
         }
 
         let now = current_time_secs();
-        let device_active = self.check_device_active();
+        let idle_secs = self.idle_seconds();
+        self.record_wall_time(now);
 
-        if !device_active {
+        if idle_secs >= self.idle_threshold_secs {
+            self.last_sample_time = None;
+            self.close_title_segment(now);
             if let (Some(app), Some(start)) = (self.current_app.take(), self.app_start_time.take()) {
                 let duration = now - start;
-                if duration >= MINIMUM_APP_TIME as f64 {
+                if duration >= self.minimum_app_time_secs {
                     self.record_app_session(&app, start, now, duration);
                 }
             }
@@ -61,112 +242,342 @@ impl AppTimeTracker {
         }
 
         let active_app = self.get_active_app();
+        let active_title = if active_app.is_some() { self.foreground_title.take() } else { None };
+        if let (Some(app), Some((cpu, memory))) = (active_app.as_ref(), self.foreground_usage.take()) {
+            let mut data = self.data.lock_recover();
+            let usage = data.app_resource_usage.entry(app.clone()).or_default();
+            usage.cpu_total += cpu;
+            usage.samples += 1;
+            usage.peak_memory_bytes = usage.peak_memory_bytes.max(memory);
+        }
+        if let Some(app) = active_app.as_ref() {
+            self.enforce_app_limit(app, now);
+        }
+
+        // Consecutive ticks with the same app and title extend one segment
+        if active_app != self.current_app || active_title != self.current_title {
+            self.close_title_segment(now);
+            self.title_start_time = active_title.as_ref().map(|_| now);
+            self.current_title = active_title;
+        }
+
+        if let Some(app) = active_app.as_ref() {
+            self.record_app_switch(app, now);
+        }
 
         if let Some(app) = active_app {
             if Some(&app) != self.current_app.as_ref() {
                 if let (Some(old_app), Some(start)) = (self.current_app.take(), self.app_start_time.take()) {
                     let duration = now - start;
-                    if duration >= MINIMUM_APP_TIME as f64 {
+                    if duration >= self.minimum_app_time_secs {
                         self.record_app_session(&old_app, start, now, duration);
                     }
                 }
                 self.current_app = Some(app);
                 self.app_start_time = Some(now);
-            } else if let Some(start) = self.app_start_time {
-                if now - start >= 300.0 {
-                    let duration = now - start;
-                    self.record_app_session(self.current_app.as_ref().unwrap(), start, now, duration);
-                    self.app_start_time = Some(now);
-                }
+                self.session_input_time = 0.0;
+            } else if let Some(start) = self.app_start_time
+                && now - start >= self.session_checkpoint_secs {
+                let duration = now - start;
+                self.record_app_session(self.current_app.as_ref().unwrap(), start, now, duration);
+                self.app_start_time = Some(now);
+                self.session_input_time = 0.0;
             }
         } else {
             if let (Some(app), Some(start)) = (self.current_app.take(), self.app_start_time.take()) {
                 let duration = now - start;
-                if duration >= MINIMUM_APP_TIME as f64 {
+                if duration >= self.minimum_app_time_secs {
                     self.record_app_session(&app, start, now, duration);
                 }
             }
         }
 
+        self.record_input_activity(now, idle_secs);
         self.current_app.clone()
     }
 
-    fn check_device_active(&self) -> bool {
-        let mut lii = LASTINPUTINFO {
-            cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
-            dwTime: 0,
+    pub fn update_app_limits(&mut self, limits: HashMap<String, f64>) {
+        self.app_limits = limits.into_iter()
+            .map(|(app, secs)| (app.trim().to_lowercase().trim_end_matches(".exe").to_string(), secs))
+            .filter(|(app, secs)| !app.is_empty() && *secs >= 0.0)
+            .collect();
+        println!("[DEBUG] App limits updated. {} limits active.", self.app_limits.len());
+    }
+
+    pub fn update_category_overrides(&mut self, overrides: HashMap<String, String>) {
+        let mut overrides: Vec<(String, String)> = overrides.into_iter()
+            .map(|(app, category)| (app.trim().to_lowercase(), category.trim().to_string()))
+            .filter(|(app, category)| !app.is_empty() && !category.is_empty())
+            .collect();
+        // The most specific substring wins when several match
+        overrides.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        self.category_overrides = overrides;
+        println!("[DEBUG] App category overrides updated. {} overrides active.", self.category_overrides.len());
+    }
+
+    /// Terminates the foreground app once today's time in it, including the running session,
+    /// reaches its limit. Checked every tick, so relaunching it gets it closed again.
+    fn enforce_app_limit(&mut self, app: &str, now: f64) {
+        let Some(&limit) = self.app_limits.get(app) else { return };
+        let Some(pid) = self.foreground_pid else { return };
+
+        let mut used = self.data.lock_recover().app_total_time.get(app).copied().unwrap_or(0.0);
+        if let (Some(current), Some(start)) = (self.current_app.as_deref(), self.app_start_time)
+            && current == app {
+            used += now - start;
+        }
+        if used < limit {
+            return;
+        }
+
+        println!("[ALERT] Daily limit for {} reached ({:.1} of {:.1} min). Terminating process {}",
+            app, used / 60.0, limit / 60.0, pid);
+        let terminated = self.foreground.terminate_process(pid);
+        if terminated {
+            *self.data.lock_recover().app_limit_enforcements.entry(app.to_string()).or_insert(0) += 1;
+        }
+        audit_event("app_limit_enforced", serde_json::json!({
+            "app": app,
+            "used_secs": used,
+            "limit_secs": limit,
+            "pid": pid,
+            "terminated": terminated,
+        }));
+    }
+
+    /// Counts a switch when a different tracked app comes to the foreground. Ignored apps never
+    /// reach here, so alt-tabbing through Explorer back to the same app isn't a switch.
+    fn record_app_switch(&mut self, app: &str, now: f64) {
+        if self.last_tracked_app.as_deref() == Some(app) {
+            return;
+        }
+        if self.last_tracked_app.is_some() {
+            self.data.lock_recover().app_switches += 1;
+            while self.recent_switches.front().is_some_and(|&t| t < now - 60.0) {
+                self.recent_switches.pop_front();
+            }
+            self.recent_switches.push_back(now);
+        }
+        self.last_tracked_app = Some(app.to_string());
+    }
+
+    /// Adds the time since the previous sample to wall_time_tracked.
+    fn record_wall_time(&mut self, now: f64) {
+        if let Some(last) = self.last_wall_sample.replace(now) {
+            let elapsed = now - last;
+            if (0.0..=MAX_WALL_SAMPLE_GAP_SECS).contains(&elapsed) {
+                self.data.lock_recover().wall_time_tracked += elapsed;
+            }
+        }
+    }
+
+    /// App switches in the last 60 seconds.
+    pub fn switches_per_minute(&self) -> u32 {
+        let cutoff = current_time_secs() - 60.0;
+        self.recent_switches.iter().filter(|&&t| t >= cutoff).count() as u32
+    }
+
+    /// Credits the time since the last tick to the current app if there was recent input.
+    /// A video playing with no input keeps the device awake but doesn't count here.
+    fn record_input_activity(&mut self, now: f64, idle_secs: f64) {
+        let elapsed = self.last_sample_time.replace(now).map_or(0.0, |last| (now - last).clamp(0.0, ACTIVE_INPUT_WINDOW_SECS));
+        if idle_secs >= ACTIVE_INPUT_WINDOW_SECS {
+            return;
+        }
+        if let Some(app) = self.current_app.as_ref() {
+            *self.data.lock_recover().app_active_input_time.entry(app.clone()).or_insert(0.0) += elapsed;
+            self.session_input_time += elapsed;
+        }
+    }
+
+    /// "active" if the user was giving input for most of the current session, else "passive".
+    pub fn session_engagement(&self) -> &'static str {
+        let duration = self.app_start_time.map_or(0.0, |start| current_time_secs() - start);
+        if duration > 0.0 && self.session_input_time / duration >= ACTIVE_SESSION_INPUT_RATIO {
+            "active"
+        } else {
+            "passive"
+        }
+    }
+
+    /// Records the in-progress session so it isn't lost when the monitor stops.
+    pub fn flush_current_session(&mut self) {
+        // Time until tracking resumes (e.g. while paused) isn't tracked wall time
+        self.last_wall_sample = None;
+        self.close_title_segment(current_time_secs());
+        if let (Some(app), Some(start)) = (self.current_app.take(), self.app_start_time.take()) {
+            let now = current_time_secs();
+            let duration = now - start;
+            if duration >= self.minimum_app_time_secs {
+                self.record_app_session(&app, start, now, duration);
+            }
+        }
+        self.save_data();
+    }
+
+    /// Starts today's totals from zero, returning the previous day's data.
+    pub fn reset_daily_totals(&mut self, today: NaiveDate) -> AppData {
+        let previous = {
+            let mut data = self.data.lock_recover();
+            std::mem::replace(&mut *data, AppData::empty(today))
         };
-        unsafe {
-            if GetLastInputInfo(&mut lii).as_bool() {
-                let current_tick = windows::Win32::System::SystemInformation::GetTickCount64();
-                let _last_input_tick = lii.dwTime as u64;
-                
-                // Handle the 32-bit wrap around of lii.dwTime
-                let current_tick_32 = (current_tick & 0xFFFFFFFF) as u32;
-                let idle_ticks = if current_tick_32 >= lii.dwTime {
-                    current_tick_32 - lii.dwTime
-                } else {
-                    (u32::MAX - lii.dwTime) + current_tick_32
-                };
-                
-                let idle_secs = idle_ticks as f64 / 1000.0;
-                idle_secs < 120.0 // 2 minutes idle threshold
-            } else {
-                true
+        self.save_data();
+        previous
+    }
+
+    /// Adds the time spent on the current window title to its app's per-title totals.
+    fn close_title_segment(&mut self, now: f64) {
+        if let (Some(app), Some(title), Some(start)) = (self.current_app.as_ref(), self.current_title.take(), self.title_start_time.take()) {
+            let mut data = self.data.lock_recover();
+            let titles = data.app_window_titles.entry(app.clone()).or_default();
+            if titles.len() < MAX_TITLES_PER_APP || titles.contains_key(&title) {
+                *titles.entry(title).or_insert(0.0) += now - start;
             }
         }
     }
 
+    /// Seconds since the last keyboard or mouse input (0 if it can't be determined).
+    pub fn idle_seconds(&self) -> f64 {
+        self.foreground.idle_seconds()
+    }
+
     fn get_active_app(&mut self) -> Option<String> {
-        let hwnd = unsafe { GetForegroundWindow() };
-        if hwnd.0 == 0 {
-            return None;
+        let foreground = self.foreground.active_process()?;
+        let app_pid = foreground.pid;
+
+        // CPU usage is measured between refreshes, so refresh every tick to keep samples one tick apart.
+        // Only the foreground process is refreshed; the whole table is costly with hundreds of
+        // processes and is reread only when the targeted lookup fails.
+        self.sys.refresh_cpu_usage();
+        if !self.sys.refresh_process(sysinfo::Pid::from(app_pid as usize)) {
+            self.sys.refresh_processes();
         }
 
-        let mut pid: u32 = 0;
-        unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+        let mut name = self.process_name(app_pid)?;
+        if self.should_ignore_app(&name) {
+            // Only a process that turns out to be something else escapes the ignore list
+            name = self.disguised_app_name(app_pid, &name)?;
+        }
 
-        self.sys.refresh_processes();
+        self.foreground_pid = Some(app_pid);
+        let cpu_count = self.sys.cpus().len().max(1) as f64;
+        self.foreground_usage = self.sys.process(sysinfo::Pid::from(app_pid as usize))
+            .map(|process| (process.cpu_usage() as f64 / cpu_count, process.memory()));
 
-        if let Some(process) = self.sys.process(sysinfo::Pid::from(pid as usize)) {
-            let name = process.name().to_lowercase().replace(".exe", "");
-            if self.should_ignore_app(&name) {
-                None
-            } else {
-                Some(name)
-            }
-        } else {
-            None
+        let title_str = clean_window_title(&foreground.title, &name);
+        self.foreground_title = if title_str.is_empty() { None } else { Some(title_str) };
+
+        Some(name)
+    }
+
+    /// For a process named like an ignored app, the name to track it under if it is really a
+    /// different executable: its original file name when it was renamed (e.g. "chrome" for a
+    /// chrome.exe copied to explorer.exe), or its own name when a Windows component runs from
+    /// an unexpected directory. None if it checks out.
+    fn disguised_app_name(&mut self, pid: u32, name: &str) -> Option<String> {
+        if !self.verify_ignored_apps {
+            return None;
+        }
+        if let Some(cached) = self.verified_pids.get(&pid) {
+            return cached.clone();
         }
+        if self.verified_pids.len() >= MAX_VERIFIED_PIDS {
+            self.verified_pids.clear();
+        }
+        let result = self.check_disguised(pid, name);
+        self.verified_pids.insert(pid, result.clone());
+        result
+    }
+
+    fn check_disguised(&mut self, pid: u32, name: &str) -> Option<String> {
+        let exe = self.sys.process(sysinfo::Pid::from(pid as usize))?.exe()?.to_path_buf();
+
+        let original = original_file_name(&exe);
+        let (tracked_name, reason) = match original.as_deref() {
+            Some(original) if original != name && !self.should_ignore_app(original) => (original.to_string(), "renamed executable"),
+            _ if WINDOWS_COMPONENT_APPS.contains(&name) && !in_system_location(&exe) => (name.to_string(), "unexpected location"),
+            _ => return None,
+        };
+
+        println!("[ALERT] Process {} ({}) is named like ignored app '{}' but is not: {}. Tracking it as '{}'",
+            pid, exe.display(), name, reason, tracked_name);
+        audit_event("process_name_spoofing", serde_json::json!({
+            "pid": pid,
+            "name": name,
+            "path": exe.display().to_string(),
+            "original_name": original,
+            "reason": reason,
+            "tracked_as": tracked_name,
+        }));
+        self.spoofed_processes.push(ProcessSpoofingData {
+            device_id: crate::config::api_config::get_device_id(),
+            timestamp: Local::now().to_rfc3339(),
+            name: name.to_string(),
+            path: exe.display().to_string(),
+            original_name: original,
+            reason: reason.to_string(),
+            tracked_as: tracked_name.clone(),
+        });
+        Some(tracked_name)
+    }
+
+    /// Disguised processes found since the last call, for reporting to the server.
+    pub fn take_spoofed_processes(&mut self) -> Vec<ProcessSpoofingData> {
+        std::mem::take(&mut self.spoofed_processes)
+    }
+
+    fn process_name(&self, pid: u32) -> Option<String> {
+        self.sys.process(sysinfo::Pid::from(pid as usize))
+            .map(|process| process.name().to_lowercase().replace(".exe", ""))
     }
 
     fn should_ignore_app(&self, app_name: &str) -> bool {
         let ignores = get_ignore_apps();
-        ignores.iter().any(|&i| app_name.contains(&i.to_lowercase()))
+        ignores.iter().any(|i| app_name.contains(&i.to_lowercase()))
     }
 
-    fn record_app_session(&self, app_name: &str, start_time: f64, _end_time: f64, duration: f64) {
+    fn record_app_session(&self, app_name: &str, start_time: f64, end_time: f64, duration: f64) {
         let timestamp = DateTime::<Local>::from(UNIX_EPOCH + Duration::from_secs_f64(start_time))
-            .format("%Y-%m-%d %H:%M:%S")
+            .format("%Y-%m-%d %H:%M:%S %:z")
             .to_string();
         
         let log_line = format!("[{}] {}: {:.1}s\n", timestamp, app_name, duration);
         if let Ok(mut file) = OpenOptions::new().create(true).append(true).open("logs/app_timelog.log") {
             let _ = file.write_all(log_line.as_bytes());
         }
+        let category = self.get_app_category(app_name);
+        audit_event("app_session", serde_json::json!({
+            "app": app_name,
+            "category": category,
+            "start": DateTime::<Local>::from(UNIX_EPOCH + Duration::from_secs_f64(start_time)).to_rfc3339(),
+            "duration_secs": duration,
+        }));
 
-        let mut data = self.data.lock().unwrap();
-        *data.app_total_time.entry(app_name.to_string()).or_insert(0.0) += duration;
-        *data.app_sessions.entry(app_name.to_string()).or_insert(0) += 1;
+        {
+            let mut data = self.data.lock_recover();
+            *data.app_total_time.entry(app_name.to_string()).or_insert(0.0) += duration;
+            *data.app_sessions.entry(app_name.to_string()).or_insert(0) += 1;
 
-        let category = self.get_app_category(app_name);
-        *data.app_category_time.entry(category).or_insert(0.0) += duration;
+            let alpha = self.engagement_ema_alpha;
+            data.app_session_ema.entry(app_name.to_string())
+                .and_modify(|ema| *ema = alpha * duration + (1.0 - alpha) * *ema)
+                .or_insert(duration);
+
+            let hourly = data.app_category_hourly.entry(category.clone()).or_insert([0.0; 24]);
+            add_to_hourly_buckets(hourly, start_time, end_time);
+            *data.app_category_time.entry(category).or_insert(0.0) += duration;
+        }
+
+        self.save_data();
     }
 
     fn get_app_category(&self, app_name: &str) -> String {
+        if let Some((_, category)) = self.category_overrides.iter().find(|(app, _)| app_name.contains(app.as_str())) {
+            return category.clone();
+        }
         let categories = get_app_categories();
         for (cat, apps) in categories {
-            if apps.iter().any(|&a| app_name.contains(&a.to_lowercase())) {
+            if apps.iter().any(|a| app_name.contains(&a.to_lowercase())) {
                 return cat.to_string();
             }
         }
@@ -175,7 +586,7 @@ impl AppTimeTracker {
 
     pub fn get_app_data_for_api(&self) -> crate::config::client::AppUsageData {
         let (data, current_app, start_time) = {
-            let data = self.data.lock().unwrap();
+            let data = self.data.lock_recover();
             (data.clone(), self.current_app.clone(), self.app_start_time)
         };
 
@@ -190,30 +601,106 @@ impl AppTimeTracker {
         let mut sorted_apps: Vec<_> = data.app_total_time.iter().collect();
         sorted_apps.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap_or(std::cmp::Ordering::Equal));
 
-        for (name, time) in sorted_apps.iter().take(5) {
+        for (name, time) in sorted_apps.iter().take(self.top_apps_count) {
             let category = self.get_app_category(name);
+            // Report the title the user spent the most time on for this app
+            let hide_title = self.url_privacy != UrlPrivacy::Full && is_browser_app(name);
+            let window_title = data.app_window_titles.get(*name)
+                .filter(|_| !hide_title)
+                .and_then(|titles| titles.iter().max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal)))
+                .map(|(title, _)| title.clone());
+            let usage = data.app_resource_usage.get(*name).cloned().unwrap_or_default();
             top_apps.push(serde_json::json!({
                 "app": *name,
                 "active_time": **time,
                 "category": category,
-                "sessions": data.app_sessions.get(*name).unwrap_or(&0)
+                "sessions": data.app_sessions.get(*name).unwrap_or(&0),
+                "windowTitle": window_title,
+                "avgCpu": usage.avg_cpu(),
+                "peakMemoryMb": usage.peak_memory_bytes as f64 / (1024.0 * 1024.0),
+                "activeInputTime": data.app_active_input_time.get(*name).copied().unwrap_or(0.0),
+                "engagementScore": data.app_session_ema.get(*name).copied().unwrap_or(0.0)
             }));
         }
 
         let active_usage_time: f64 = data.app_total_time.values().sum();
+        let active_input_seconds: f64 = data.app_active_input_time.values().sum();
 
         crate::config::client::AppUsageData {
             device_id: crate::config::api_config::get_device_id(),
             timestamp: Local::now().to_rfc3339(),
+            timezone: crate::config::api_config::get_timezone(),
             current_app: current_app.unwrap_or_else(|| "Idle".to_string()),
             current_session_duration,
+            current_session_engagement: self.session_engagement().to_string(),
             total_apps_tracked: data.app_total_time.len() as u32,
-            total_time_tracked: active_usage_time, // Or use a separate system uptime if needed
+            total_time_tracked: data.wall_time_tracked,
             active_usage_time,
+            active_input_seconds,
+            app_switches: data.app_switches,
+            app_limit_enforcements: data.app_limit_enforcements.clone(),
+            switches_per_minute: self.switches_per_minute(),
             top_apps,
             category_breakdown: data.app_category_time.clone(),
+            category_hourly_breakdown: data.app_category_hourly.clone(),
+            session_id: current_session().session_id,
+            remote_session: current_session().remote,
+        }
+    }
+
+    /// The complete per-app totals, for the periodic full export.
+    pub fn get_full_app_data_for_api(&self) -> crate::config::client::AppUsageExportData {
+        let data = self.data.lock_recover();
+        crate::config::client::AppUsageExportData {
+            device_id: crate::config::api_config::get_device_id(),
+            timestamp: Local::now().to_rfc3339(),
+            day: data.day.unwrap_or_else(|| Local::now().date_naive()).to_string(),
+            app_total_time: data.app_total_time.clone(),
+            app_sessions: data.app_sessions.clone(),
+            app_category_time: data.app_category_time.clone(),
+        }
+    }
+}
+
+/// True for a browser's process name (e.g. "chrome", "msedge"), including rebranded browsers
+/// from browserNames.
+fn is_browser_app(app_name: &str) -> bool {
+    BROWSER_EXECUTABLES.iter().any(|exe| exe.trim_end_matches(".exe") == app_name)
+        || get_browser_names().iter().any(|name| app_name.contains(name.as_str()))
+}
+
+/// Splits the span from `start` to `end` (seconds since the epoch) over the local hours it
+/// covers. A span running past midnight adds its later part to the early hours.
+fn add_to_hourly_buckets(buckets: &mut [f64; 24], start: f64, end: f64) {
+    let mut t = start;
+    while t < end {
+        let local = DateTime::<Local>::from(UNIX_EPOCH + Duration::from_secs_f64(t));
+        let into_hour = (local.minute() * 60 + local.second()) as f64 + local.nanosecond() as f64 / 1e9;
+        let segment_end = end.min(t + 3600.0 - into_hour);
+        buckets[local.hour() as usize] += segment_end - t;
+        t = segment_end;
+    }
+}
+
+/// Strips a trailing application suffix such as " - Google Chrome" from a window title.
+fn clean_window_title(title: &str, app_name: &str) -> String {
+    let title = title.trim();
+    for separator in [" - ", " \u{2014} ", " \u{2013} "] {
+        if let Some(pos) = title.rfind(separator) {
+            // Compare alphanumerics only; Edge pads its name with a zero-width space
+            let suffix: String = title[pos + separator.len()..]
+                .to_lowercase()
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect();
+            let is_app_suffix = KNOWN_TITLE_SUFFIXES.contains(&suffix.as_str())
+                || (suffix.len() >= 3 && (suffix.contains(app_name) || app_name.contains(&suffix)));
+            if is_app_suffix {
+                return title[..pos].trim().to_string();
+            }
         }
     }
+    title.to_string()
 }
 
 fn current_time_secs() -> f64 {
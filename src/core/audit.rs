@@ -0,0 +1,49 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use chrono::Local;
+use serde::Serialize;
+
+use crate::config::api_config::get_device_id;
+
+/// One JSON object per line, for SIEM ingestion alongside the human-readable app_timelog.log
+const AUDIT_LOG_FILE: &str = "logs/audit.jsonl";
+
+#[derive(Serialize)]
+struct AuditEvent<'a> {
+    event_type: &'a str,
+    timestamp: String,
+    device_id: String,
+    details: serde_json::Value,
+}
+
+/// Appends a structured event (e.g. "url_blocked") to logs/audit.jsonl. Called from the
+/// tracker and dialog threads, so writes are serialized to keep lines whole.
+pub fn audit_event(event_type: &str, details: serde_json::Value) {
+    static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+    let event = AuditEvent {
+        event_type,
+        timestamp: Local::now().to_rfc3339(),
+        device_id: get_device_id(),
+        details,
+    };
+    let line = match serde_json::to_string(&event) {
+        Ok(l) => l,
+        Err(e) => {
+            println!("[ERROR] Failed to serialize audit event {}: {}", event_type, e);
+            return;
+        }
+    };
+
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = fs::create_dir_all("logs");
+    match OpenOptions::new().create(true).append(true).open(AUDIT_LOG_FILE) {
+        Ok(mut file) => {
+            if let Err(e) = writeln!(file, "{}", line) {
+                println!("[ERROR] Failed to write audit event: {}", e);
+            }
+        }
+        Err(e) => println!("[ERROR] Failed to open {}: {}", AUDIT_LOG_FILE, e),
+    }
+}
@@ -1,9 +1,115 @@
-use std::collections::{HashMap, VecDeque};
-use std::time::{SystemTime, UNIX_EPOCH};
-use uiautomation::{UIAutomation, UIElement, UITreeWalker};
-use uiautomation::types::UIProperty;
-use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, SendMessageW, PostMessageW, WM_CLOSE};
-use windows::Win32::Foundation::{LPARAM, WPARAM, HWND};
+use std::collections::{HashMap, HashSet, VecDeque};
+use sha2::{Digest, Sha256};
+use crate::config::settings::{
+    get_browser_names, get_domain_categories, ALERT_THROTTLE_SECS, BLOCK_BROWSING_ON_ANONYMIZER, BROWSER_EXECUTABLES, BROWSER_WINDOW_CLASSES, BLOCK_NOTIFICATION_INTERVAL_SECS, CLOSE_UNREADABLE_FULLSCREEN, FORCE_KILL_AFTER_TICKS, FORCE_KILL_ON_BLOCK, SENSITIVE_MIN_DWELL_SECS, SHOW_BLOCK_NOTIFICATIONS, URL_DEBOUNCE_SECS,
+    URL_HISTORY_CAP,
+};
+use crate::config::client::{merge_url_visits, UrlVisit};
+use crate::core::audit::audit_event;
+use crate::core::url_match;
+use crate::core::url_utils::{extract_domain, to_ascii_host};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::core::window_inspector::{default_inspector, TopLevelWindow, WindowInspector};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterMode {
+    /// Block URLs matching any pattern in the list
+    Blacklist,
+    /// Block every URL that does NOT match a pattern in the list
+    Whitelist,
+}
+
+impl FilterMode {
+    pub fn from_config(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "whitelist" | "allowlist" => FilterMode::Whitelist,
+            _ => FilterMode::Blacklist,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockAction {
+    /// Send Ctrl+W to the browser so only the offending tab is closed
+    CloseTab,
+    /// Post WM_CLOSE to the whole browser window
+    CloseWindow,
+    /// Navigate the tab to the configured block page, closing the tab if that fails
+    Redirect,
+}
+
+impl BlockAction {
+    pub fn from_config(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "closewindow" | "close_window" | "window" => BlockAction::CloseWindow,
+            "redirect" | "blockpage" | "block_page" => BlockAction::Redirect,
+            _ => BlockAction::CloseTab,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnforcementMode {
+    /// Close blocked pages and dialogs
+    Enforce,
+    /// Detect, count and report matches without closing anything, for piloting new rules
+    Observe,
+}
+
+impl EnforcementMode {
+    pub fn from_config(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "observe" | "monitor" | "dryrun" | "dry_run" | "dry-run" => EnforcementMode::Observe,
+            _ => EnforcementMode::Enforce,
+        }
+    }
+}
+
+/// How much of each URL leaves the device. Blocking always sees the full URL.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UrlPrivacy {
+    Full,
+    /// Query string and fragment removed, which is where tokens and search terms live
+    PathOnly,
+    DomainOnly,
+    /// SHA-256 of the salted full URL, so visits can be counted and compared but not read
+    Hashed,
+}
+
+impl UrlPrivacy {
+    pub fn from_config(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "path" | "pathonly" | "path_only" | "path-only" => UrlPrivacy::PathOnly,
+            "domain" | "domainonly" | "domain_only" | "domain-only" => UrlPrivacy::DomainOnly,
+            "hash" | "hashed" => UrlPrivacy::Hashed,
+            _ => UrlPrivacy::Full,
+        }
+    }
+
+    /// The URL as it may be uploaded under this level.
+    pub fn apply(&self, url: &str, salt: &str) -> String {
+        match self {
+            UrlPrivacy::Full => url.to_string(),
+            UrlPrivacy::PathOnly => url.split(['?', '#']).next().unwrap_or("").to_string(),
+            UrlPrivacy::DomainOnly => extract_domain(url),
+            UrlPrivacy::Hashed => {
+                let mut hasher = Sha256::new();
+                hasher.update(salt.as_bytes());
+                hasher.update(url.as_bytes());
+                format!("{:x}", hasher.finalize())
+            }
+        }
+    }
+
+    /// A URL's domain as it may be uploaded under this level. Only Hashed hides it, since the
+    /// other levels keep the domain in the URL anyway.
+    pub fn apply_domain(&self, domain: &str, salt: &str) -> String {
+        match self {
+            UrlPrivacy::Hashed => self.apply(domain, salt),
+            _ => domain.to_string(),
+        }
+    }
+}
 
 pub struct BrowserMonitor {
     pub last_url: String,
@@ -11,293 +117,1044 @@ pub struct BrowserMonitor {
     pub suspicious_count: u32,
     pub url_timers: HashMap<String, f64>,
     pub total_times: HashMap<String, f64>,
-    urls_for_upload: VecDeque<String>,
+    /// Times each URL became the active page
+    pub visit_counts: HashMap<String, u32>,
+    /// Daily seconds allowed per domain (e.g. "youtube.com" -> 1800)
+    pub domain_budgets: HashMap<String, f64>,
+    /// Seconds spent per domain today, reset at local midnight
+    domain_times: HashMap<String, f64>,
+    /// One record per URL visited since the last successful upload, ordered by first visit
+    urls_for_upload: VecDeque<UrlVisit>,
+    /// Most distinct URLs kept in urls_for_upload between successful uploads
+    pub url_history_cap: usize,
+    /// A new address-bar value and when it first appeared; committed as a visit once stable
+    pending_url: Option<(String, f64)>,
     pub api_blacklist: Vec<String>,
+    /// Lowercase URL substrings whose pages are flagged when left too quickly
+    pub sensitive_url_patterns: Vec<String>,
+    /// Seconds a sensitive page must stay open to not count as a quick exit
+    pub sensitive_min_dwell_secs: f64,
+    /// Quick exits from sensitive pages (URL, seconds on the page) not yet reported
+    quick_exits: Vec<(String, f64)>,
+    /// Patterns for known exfiltration and paste sites; "re:" prefixes a regex, anything else
+    /// uses the blacklist's matching rules. Visits to matching, non-blocked URLs are reported.
+    suspicious_url_patterns: Vec<(String, Option<regex::Regex>)>,
+    /// Suspicious visits (URL, matched pattern) not yet reported
+    suspicious_visits: Vec<(String, String)>,
+    /// Lowercase website categories (e.g. "social media") blocked in addition to the filter list
+    pub blocked_categories: HashSet<String>,
+    /// Whether the foreground URL last returned by get_active_browser_urls came from a private window
+    pub last_url_private: bool,
+    /// Browser window the foreground URL last returned by get_active_browser_urls was read from
+    pub last_url_hwnd: Option<isize>,
+    /// Whether the last scan read a URL from any open browser window; None if none was open
+    pub last_url_read_ok: Option<bool>,
+    /// When a URL was last read from a browser window
+    pub last_url_read_at: Option<std::time::Instant>,
+    /// Private browsing seen since the last upload
+    private_browsing_seen: bool,
+    /// Private windows already counted as suspicious, by HWND
+    counted_private_windows: HashSet<isize>,
+    /// URL read from each browser window on the last scan, so reads are only logged on change
+    window_urls: HashMap<isize, Option<String>>,
+    /// Blocked (window, URL) pairs already counted in blocked_count, so a page left open counts
+    /// once rather than once per tick. None is a URL checked without a known window.
+    counted_blocks: HashSet<(Option<isize>, String)>,
+    pub mode: FilterMode,
+    pub block_action: BlockAction,
+    pub enforcement_mode: EnforcementMode,
+    /// Page shown instead of a blocked one when block_action is Redirect, set by the server
+    pub block_page_url: Option<String>,
+    /// Window we sent Ctrl+W or the block-page redirect to, the URL it was showing, and when
+    pending_tab_close: Option<(isize, String, Instant)>,
+    /// Terminate the browser if a blocked page survives WM_CLOSE
+    pub force_kill_on_block: bool,
+    /// Window we posted WM_CLOSE to, the URL it was showing, and ticks it has survived since
+    pending_window_close: Option<(isize, String, u32)>,
+    /// Close fullscreen browsers whose URL can't be read instead of letting them through
+    pub close_unreadable_fullscreen: bool,
+    /// Fullscreen window already reported as unreadable, so it's logged once
+    unreadable_fullscreen_hwnd: Option<isize>,
+    /// api_blacklist compiled for matching
+    blacklist_matcher: url_match::PatternSet,
+    /// Seconds before the same blocked URL is alerted on again
+    pub alert_throttle_secs: f64,
+    /// When each blocked URL was last logged, so one URL's alerts don't hide another's
+    last_alert_times: HashMap<String, f64>,
+    /// Show the user a toast when a page is blocked
+    pub show_notifications: bool,
+    last_notification_times: HashMap<String, f64>,
+    /// Block every URL while an anonymizer is running (see active_anonymizer)
+    pub block_on_anonymizer: bool,
+    /// VPN/anonymizer client or proxy currently detected, set by the monitor
+    pub active_anonymizer: Option<String>,
+    /// Lowercase substrings of window titles that identify a browser
+    pub browser_names: Vec<String>,
+    /// Applied to every URL queued for upload or reported to the server
+    pub url_privacy: UrlPrivacy,
+    /// Prefix hashed with each URL under UrlPrivacy::Hashed, so common URLs can't be looked up
+    pub url_hash_salt: String,
+    inspector: Box<dyn WindowInspector>,
 }
 
 impl BrowserMonitor {
     pub fn new() -> Self {
+        Self::with_inspector(default_inspector())
+    }
+
+    pub fn with_inspector(inspector: Box<dyn WindowInspector>) -> Self {
         BrowserMonitor {
             last_url: String::new(),
             blocked_count: 0,
             suspicious_count: 0,
             url_timers: HashMap::new(),
             total_times: HashMap::new(),
+            visit_counts: HashMap::new(),
+            domain_budgets: HashMap::new(),
+            domain_times: HashMap::new(),
             urls_for_upload: VecDeque::new(),
+            url_history_cap: URL_HISTORY_CAP,
+            pending_url: None,
             api_blacklist: Vec::new(),
+            blocked_categories: HashSet::new(),
+            sensitive_url_patterns: Vec::new(),
+            sensitive_min_dwell_secs: SENSITIVE_MIN_DWELL_SECS,
+            quick_exits: Vec::new(),
+            suspicious_url_patterns: Vec::new(),
+            suspicious_visits: Vec::new(),
+            last_url_private: false,
+            last_url_hwnd: None,
+            last_url_read_ok: None,
+            last_url_read_at: None,
+            private_browsing_seen: false,
+            counted_private_windows: HashSet::new(),
+            window_urls: HashMap::new(),
+            counted_blocks: HashSet::new(),
+            mode: FilterMode::Blacklist,
+            block_action: BlockAction::CloseTab,
+            enforcement_mode: EnforcementMode::Enforce,
+            block_page_url: None,
+            pending_tab_close: None,
+            force_kill_on_block: FORCE_KILL_ON_BLOCK,
+            pending_window_close: None,
+            close_unreadable_fullscreen: CLOSE_UNREADABLE_FULLSCREEN,
+            unreadable_fullscreen_hwnd: None,
+            blacklist_matcher: url_match::PatternSet::new(&[]),
+            alert_throttle_secs: ALERT_THROTTLE_SECS,
+            last_alert_times: HashMap::new(),
+            show_notifications: SHOW_BLOCK_NOTIFICATIONS,
+            block_on_anonymizer: BLOCK_BROWSING_ON_ANONYMIZER,
+            active_anonymizer: None,
+            last_notification_times: HashMap::new(),
+            browser_names: get_browser_names().to_vec(),
+            url_privacy: UrlPrivacy::Full,
+            url_hash_salt: String::new(),
+            inspector,
         }
     }
 
-    pub fn get_active_browser_url_optimized(&mut self) -> Option<String> {
-        let automation = UIAutomation::new().ok()?;
-        let root = automation.get_root_element().ok()?;
-        let walker = automation.get_control_view_walker().ok()?;
-        
-        let mut current = match walker.get_first_child(&root) {
-            Ok(el) => el,
-            _ => return None,
-        };
-        
-        loop {
-            if let Ok(name) = current.get_name() {
-                let name_lower = name.to_lowercase();
-                if name_lower.contains("chrome") || name_lower.contains("edge") || name_lower.contains("brave") {
-                    println!("[DEBUG] Found potential browser window: {}", name);
-                    if let Some(url) = self.find_address_bar_url(&automation, &walker, &current) {
-                        return Some(url);
+    /// URLs of every open browser window, the foreground (or most recently active) one first.
+    pub fn get_active_browser_urls(&mut self) -> Vec<String> {
+        let foreground = self.inspector.foreground_window();
+        let mut found: Vec<(isize, String, bool)> = Vec::new();
+        let mut unreadable_fullscreen = None;
+        let mut browser_windows = 0;
+        let mut window_urls = HashMap::new();
+
+        for window in self.inspector.top_level_windows() {
+            let name_lower = window.name.to_lowercase();
+            if self.is_browser_window(&window, &name_lower) {
+                browser_windows += 1;
+                // Fullscreen (F11) and kiosk windows hide the address bar, and some Chromium builds
+                // and themes don't name it recognisably; fall back to the page itself
+                let url = self.inspector.address_bar_url(window.hwnd)
+                    .or_else(|| self.inspector.document_url(window.hwnd));
+                if self.window_urls.get(&window.hwnd) != Some(&url) {
+                    println!("[DEBUG] Browser window '{}': {}", window.name, url.as_deref().unwrap_or("no URL readable"));
+                }
+                window_urls.insert(window.hwnd, url.clone());
+                if url.is_none() && Some(window.hwnd) == foreground && self.inspector.is_fullscreen(window.hwnd) {
+                    unreadable_fullscreen = Some(window.hwnd);
+                }
+                if let Some(url) = url {
+                    let is_private = is_private_window_name(&name_lower);
+                    if is_private {
+                        self.note_private_window(window.hwnd, &url);
                     }
+                    found.push((window.hwnd, url, is_private));
                 }
             }
-            
-            if let Ok(next) = walker.get_next_sibling(&current) {
-                current = next;
-            } else {
-                break;
-            }
         }
 
-        None
+        // Closed windows drop out, so a reused HWND is logged afresh. A window that moved on
+        // from a blocked URL counts again if it goes back.
+        self.window_urls = window_urls;
+        let window_urls = &self.window_urls;
+        self.counted_blocks.retain(|(hwnd, url)| match hwnd {
+            Some(hwnd) => window_urls.get(hwnd).is_some_and(|current| current.as_deref() == Some(url.as_str())),
+            None => true,
+        });
+
+        match unreadable_fullscreen {
+            Some(hwnd) => self.handle_unreadable_fullscreen(hwnd),
+            None => self.unreadable_fullscreen_hwnd = None,
+        }
+
+        // Windows come back in Z-order; make sure the one the user is looking at leads
+        if let Some(pos) = found.iter().position(|(hwnd, _, _)| Some(*hwnd) == foreground) {
+            let active = found.remove(pos);
+            found.insert(0, active);
+        }
+
+        // A browser that is open but yields no URL means blocking can't see anything
+        self.last_url_read_ok = (browser_windows > 0).then_some(!found.is_empty());
+        if !found.is_empty() {
+            self.last_url_read_at = Some(std::time::Instant::now());
+        }
+
+        self.last_url_private = found.first().is_some_and(|(_, _, is_private)| *is_private);
+        self.last_url_hwnd = found.first().map(|(hwnd, _, _)| *hwnd);
+        found.into_iter().map(|(_, url, _)| url).collect()
     }
 
-    fn find_address_bar_url(&self, automation: &UIAutomation, walker: &UITreeWalker, browser_window: &UIElement) -> Option<String> {
-        if let Some(address_bar) = self.find_address_bar_recursive(automation, walker, browser_window, 0) {
-            if let Ok(val) = address_bar.get_property_value(UIProperty::ValueValue) {
-                let mut url_str = val.to_string();
-                // Clean up "STRING(url)" format from uiautomation Variant
-                if url_str.starts_with("STRING(") && url_str.ends_with(')') {
-                    url_str = url_str[7..url_str.len()-1].to_string();
-                }
-                
-                if !url_str.is_empty() {
-                    println!("[DEBUG] Extracted URL: {}", url_str);
-                    return Some(url_str);
-                }
+    /// A fullscreen browser in the foreground whose URL can't be read would otherwise bypass
+    /// blocking entirely. Reported once per window; closed if close_unreadable_fullscreen is set.
+    fn handle_unreadable_fullscreen(&mut self, hwnd: isize) {
+        let first_seen = self.unreadable_fullscreen_hwnd != Some(hwnd);
+        if first_seen {
+            self.unreadable_fullscreen_hwnd = Some(hwnd);
+            self.suspicious_count += 1;
+            println!("[ALERT] Fullscreen browser window with no readable URL (HWND: {})", hwnd);
+        }
+        if self.close_unreadable_fullscreen && self.enforcement_mode == EnforcementMode::Enforce {
+            println!("[INFO] Closing fullscreen browser window (HWND: {}) since its URL can't be checked", hwnd);
+            if first_seen {
+                self.blocked_count += 1;
             }
+            self.inspector.close_window(hwnd);
         }
-        None
     }
 
-    fn find_address_bar_recursive(&self, automation: &UIAutomation, walker: &UITreeWalker, element: &UIElement, depth: u32) -> Option<UIElement> {
-        if depth > 12 { return None; }
+    /// Counts a private browsing window as suspicious once, however many ticks it stays open.
+    fn note_private_window(&mut self, hwnd: isize, url: &str) {
+        self.private_browsing_seen = true;
+        if self.counted_private_windows.insert(hwnd) {
+            self.suspicious_count += 1;
+            println!("[ALERT] Private browsing window detected (HWND: {}) at {}", hwnd, url);
+        }
+    }
 
-        let mut current = match walker.get_first_child(element) {
-            Ok(el) => el,
-            _ => return None,
-        };
-        
-        loop {
-            if let Ok(name) = current.get_name() {
-                let name_lower = name.to_lowercase();
-                // Check common names
-                if name_lower.contains("address and search bar") || name_lower.contains("address bar") {
-                    return Some(current.clone());
-                }
+    pub fn update_timing(&mut self, current_url: Option<String>) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        if current_url.is_none() {
+            self.pending_url = None;
+        }
+
+        // Without a known window, a block is counted until the foreground moves to another URL
+        self.counted_blocks.retain(|(hwnd, url)| hwnd.is_some() || current_url.as_ref() == Some(url));
+
+        if let Some(url) = current_url {
+            // Active blocking: Check on every iteration if we have a URL
+            let blocked = self.enforce_policy(&url, self.last_url_hwnd, now);
+            if !blocked {
+                // The tab or window we closed (if any) is gone
+                self.pending_tab_close = None;
+                self.pending_window_close = None;
             }
-            
-            // Check for common AutomationIds as a robust fallback
-            if let Ok(auto_id) = current.get_automation_id() {
-                if auto_id == "addressEditBox" || auto_id.contains("url") || auto_id.contains("address") {
-                    return Some(current.clone());
+
+            if url == self.last_url {
+                self.pending_url = None;
+                return;
+            }
+
+            // The address bar changes on every keystroke while a URL is typed, so only
+            // commit a new URL once it has stayed the same for URL_DEBOUNCE_SECS
+            let since = match &self.pending_url {
+                Some((pending, since)) if *pending == url => *since,
+                _ => {
+                    self.pending_url = Some((url, now));
+                    return;
                 }
+            };
+            if now - since < URL_DEBOUNCE_SECS {
+                return;
             }
+            self.pending_url = None;
 
-            if let Some(found) = self.find_address_bar_recursive(automation, walker, &current, depth + 1) {
-                return Some(found);
+            // The navigation happened when the URL first appeared, not when it was committed
+            if !self.last_url.is_empty() {
+                let start_time = self.url_timers.remove(&self.last_url).unwrap_or(since);
+                self.add_url_time(self.last_url.clone(), since - start_time);
+                self.note_page_left(&self.last_url.clone(), since - start_time);
             }
             
-            if let Ok(next) = walker.get_next_sibling(&current) {
-                current = next;
-            } else {
-                break;
+            self.last_url = url.clone();
+            self.url_timers.insert(url.clone(), since);
+            *self.visit_counts.entry(url.clone()).or_insert(0) += 1;
+            // Blocked pages are already reported; this is for ones policy lets through
+            if !blocked {
+                self.note_suspicious_visit(&url);
             }
+            
+            // Keep history for upload: a revisit updates the URL's record, a new URL is added
+            // after the rest, dropping the oldest past the cap
+            let reported = self.reportable_url(&url);
+            let seen = epoch_to_local(since);
+            match self.urls_for_upload.iter_mut().find(|v| v.url == reported) {
+                Some(visit) => {
+                    visit.last_seen = seen;
+                    visit.visit_count += 1;
+                }
+                None => {
+                    self.urls_for_upload.push_back(UrlVisit { url: reported, first_seen: seen, last_seen: seen, visit_count: 1 });
+                    while self.urls_for_upload.len() > self.url_history_cap {
+                        self.urls_for_upload.pop_front();
+                    }
+                }
+            }
+        } else if !self.last_url.is_empty() {
+            self.pending_tab_close = None;
+            self.pending_window_close = None;
+            let start_time = self.url_timers.remove(&self.last_url).unwrap_or(now);
+            self.add_url_time(self.last_url.clone(), now - start_time);
+            self.note_page_left(&self.last_url.clone(), now - start_time);
+            self.last_url = String::new();
         }
-        None
     }
 
-    pub fn update_timing(&mut self, current_url: Option<String>) {
+    /// Runs the blocking check for a URL open in a background browser window, without
+    /// counting it towards active time.
+    pub fn check_background_url(&mut self, url: &str) {
         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        let hwnd = self.window_urls.iter()
+            .find(|(_, window_url)| window_url.as_deref() == Some(url))
+            .map(|(hwnd, _)| *hwnd);
+        self.enforce_policy(url, hwnd, now);
+    }
 
-        if let Some(url) = current_url {
-            // Active blocking: Check on every iteration if we have a URL
-            if self.is_blocked(&url) {
-                static mut LAST_ALERT_TIME: f64 = 0.0;
-                let current_time = now;
-                
-                // Only log the alert every 2 seconds to avoid spamming the console
-                unsafe {
-                    if current_time - LAST_ALERT_TIME > 2.0 {
-                        println!("[ALERT] Accessing blocked URL: {}. Closing window...", url);
-                        LAST_ALERT_TIME = current_time;
+    /// Blocks the URL shown in `hwnd` if the filter list or its domain budget forbids it.
+    /// Returns true if it was blocked.
+    fn enforce_policy(&mut self, url: &str, hwnd: Option<isize>, now: f64) -> bool {
+        let over_budget = self.is_over_budget(url, now);
+        if !over_budget && !self.is_blocked(url) {
+            return false;
+        }
+
+        let reason = if over_budget { "daily time budget exceeded" } else { "blocked by policy" };
+        let enforce = self.enforcement_mode == EnforcementMode::Enforce;
+
+        // Only log each URL's alert every alert_throttle_secs to avoid spamming the console
+        if throttle_elapsed(&mut self.last_alert_times, url, now, self.alert_throttle_secs) {
+            if enforce {
+                println!("[ALERT] Accessing blocked URL: {} ({}). Enforcing block ({:?})...", url, reason, self.block_action);
+            } else {
+                println!("[ALERT] Accessing blocked URL: {} ({}). Observe mode, not enforcing.", url, reason);
+            }
+            audit_event("url_blocked", serde_json::json!({
+                "url": url,
+                "reason": reason,
+                "enforced": enforce,
+            }));
+        }
+
+        if self.counted_blocks.insert((hwnd, url.to_string())) {
+            self.blocked_count += 1;
+        }
+        crate::core::screenshot::capture_block_event(&format!("{} {}", reason, url));
+        if !enforce {
+            return true;
+        }
+
+        // Tell the user why their tab disappeared, without a toast per tick
+        if self.show_notifications
+            && throttle_elapsed(&mut self.last_notification_times, url, now, BLOCK_NOTIFICATION_INTERVAL_SECS) {
+            crate::ui::notifications::show_block_notification(&extract_domain(url), reason);
+        }
+
+        self.enforce_block(url);
+        true
+    }
+
+    /// Flags leaving a sensitive page after less than sensitive_min_dwell_secs, which can mean
+    /// someone opened it just long enough to grab a screenshot.
+    fn note_page_left(&mut self, url: &str, dwell: f64) {
+        if dwell >= self.sensitive_min_dwell_secs {
+            return;
+        }
+        // Same matching rules as the blacklist, so wildcards work here too
+        if !self.sensitive_url_patterns.iter().any(|p| url_match::matches(url, p)) {
+            return;
+        }
+
+        self.suspicious_count += 1;
+        println!("[ALERT] Sensitive URL left after {:.1}s: {}", dwell, url);
+        audit_event("url_quick_exit", serde_json::json!({
+            "url": url,
+            "dwell_secs": dwell,
+        }));
+        self.quick_exits.push((url.to_string(), dwell));
+    }
+
+    /// Counts a visit to a URL matching a suspicious pattern, once per committed visit.
+    fn note_suspicious_visit(&mut self, url: &str) {
+        let Some(pattern) = self.suspicious_url_patterns.iter()
+            .find(|(pattern, regex)| match regex {
+                Some(regex) => regex.is_match(url),
+                None => url_match::matches(url, pattern),
+            })
+            .map(|(pattern, _)| pattern.clone()) else {
+            return;
+        };
+
+        self.suspicious_count += 1;
+        println!("[ALERT] Suspicious URL visited: {} (matched \"{}\")", url, pattern);
+        audit_event("url_suspicious", serde_json::json!({
+            "url": url,
+            "pattern": pattern,
+        }));
+        self.suspicious_visits.push((url.to_string(), pattern));
+    }
+
+    /// Suspicious visits (URL, matched pattern) recorded since the last call, for reporting.
+    pub fn take_suspicious_visits(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.suspicious_visits)
+    }
+
+    /// The URL as url_privacy allows it to be uploaded.
+    pub fn reportable_url(&self, url: &str) -> String {
+        self.url_privacy.apply(url, &self.url_hash_salt)
+    }
+
+    /// The domain of `url` as it may be uploaded under url_privacy.
+    pub fn reportable_domain(&self, url: &str) -> String {
+        self.url_privacy.apply_domain(&extract_domain(url), &self.url_hash_salt)
+    }
+
+    /// Quick exits recorded since the last call, for reporting to the server.
+    pub fn take_quick_exits(&mut self) -> Vec<(String, f64)> {
+        std::mem::take(&mut self.quick_exits)
+    }
+
+    pub fn update_sensitive_patterns(&mut self, patterns: Vec<String>) {
+        self.sensitive_url_patterns = patterns.into_iter()
+            .map(|p| p.trim().to_lowercase())
+            .filter(|p| !p.is_empty())
+            .collect();
+        println!("[DEBUG] Sensitive URL patterns updated. {} patterns active.", self.sensitive_url_patterns.len());
+    }
+
+    pub fn update_suspicious_patterns(&mut self, patterns: Vec<String>) {
+        self.suspicious_url_patterns = patterns.into_iter()
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .filter_map(|p| match p.strip_prefix("re:") {
+                Some(expr) => match regex::Regex::new(&format!("(?i){}", expr)) {
+                    Ok(regex) => Some((p.clone(), Some(regex))),
+                    Err(e) => {
+                        println!("[WARN] Ignoring invalid suspicious URL regex {}: {}", p, e);
+                        None
                     }
-                }
-                
-                self.blocked_count += 1;
-                
-                // Actively block using uiautomation if possible
-                if let Some(automation) = UIAutomation::new().ok() {
-                    if let Some(root) = automation.get_root_element().ok() {
-                        if let Some(walker) = automation.get_control_view_walker().ok() {
-                            let mut handle_found = false;
-                            
-                            if let Some(browser_el) = self.find_browser_window_with_url(&automation, &walker, &root, &url) {
-                                if let Ok(val) = browser_el.get_property_value(UIProperty::NativeWindowHandle) {
-                                    let mut handle_str = val.to_string();
-                                    println!("[DEBUG] Raw HWND property: {}", handle_str);
-                                    
-                                    if let Some(start) = handle_str.find('(') {
-                                        if let Some(end) = handle_str.rfind(')') {
-                                            handle_str = handle_str[start+1..end].to_string();
-                                        }
-                                    }
-                                    
-                                    if let Ok(hwnd_val) = handle_str.parse::<isize>() {
-                                        if hwnd_val != 0 {
-                                            println!("[INFO] Found browser window HWND: {}. Sending close message...", hwnd_val);
-                                            unsafe {
-                                                PostMessageW(HWND(hwnd_val), WM_CLOSE, WPARAM(0), LPARAM(0));
-                                            }
-                                            handle_found = true;
-                                        }
-                                    }
-                                }
-                            }
-
-                            if !handle_found {
-                                // Fallback to foreground window
-                                unsafe {
-                                    let hwnd = GetForegroundWindow();
-                                    if hwnd.0 != 0 {
-                                        println!("[INFO] Falling back to closing foreground window (HWND: {:?})", hwnd);
-                                        PostMessageW(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0));
-                                    }
-                                }
-                            }
-                        }
+                },
+                None => Some((p.to_lowercase(), None)),
+            })
+            .collect();
+        println!("[DEBUG] Suspicious URL patterns updated. {} patterns active.", self.suspicious_url_patterns.len());
+    }
+
+    fn add_url_time(&mut self, url: String, duration: f64) {
+        *self.domain_times.entry(extract_domain(&url)).or_insert(0.0) += duration;
+        *self.total_times.entry(url).or_insert(0.0) += duration;
+    }
+
+    /// Starts today's URL, visit and domain totals from zero, returning the previous day's URL totals.
+    pub fn reset_daily_totals(&mut self) -> HashMap<String, f64> {
+        self.domain_times.clear();
+        self.visit_counts.clear();
+        std::mem::take(&mut self.total_times)
+    }
+
+    pub fn update_domain_budgets(&mut self, budgets: HashMap<String, f64>) {
+        self.domain_budgets = budgets.into_iter()
+            .map(|(domain, secs)| (extract_domain(&domain.trim().to_lowercase()), secs))
+            .filter(|(domain, secs)| !domain.is_empty() && *secs >= 0.0)
+            .collect();
+        println!("[DEBUG] Domain budgets updated. {} budgets active.", self.domain_budgets.len());
+    }
+
+    /// True when the URL's domain (or a parent domain with a budget) has used up today's time,
+    /// counting the time already spent on the current page.
+    fn is_over_budget(&self, url: &str, now: f64) -> bool {
+        if self.domain_budgets.is_empty() {
+            return false;
+        }
+
+        let domain = extract_domain(url);
+        let in_progress = if url == self.last_url {
+            self.url_timers.get(url).map(|start| now - start).unwrap_or(0.0)
+        } else {
+            0.0
+        };
+
+        self.domain_budgets.iter().any(|(budget_domain, budget_secs)| {
+            if !domain_matches(&domain, budget_domain) {
+                return false;
+            }
+            let spent: f64 = self.domain_times.iter()
+                .filter(|(d, _)| domain_matches(d, budget_domain))
+                .map(|(_, secs)| secs)
+                .sum::<f64>() + in_progress;
+            spent >= *budget_secs
+        })
+    }
+
+    fn enforce_block(&mut self, url: &str) {
+        // The page survived a WM_CLOSE (typically a beforeunload "Leave site?" prompt):
+        // once it has done so for long enough, kill the browser outright if allowed
+        if let Some((hwnd_val, pending_url, ticks)) = self.pending_window_close.as_mut() {
+            if pending_url == url {
+                *ticks += 1;
+                let (hwnd_val, ticks) = (*hwnd_val, *ticks);
+                if self.force_kill_on_block && ticks >= FORCE_KILL_AFTER_TICKS {
+                    println!("[ALERT] Browser ignored WM_CLOSE for {} ticks. Terminating its process (HWND: {})...", ticks, hwnd_val);
+                    if !self.inspector.terminate_process(hwnd_val) {
+                        println!("[ERROR] Failed to terminate browser process (HWND: {})", hwnd_val);
                     }
+                    self.pending_window_close = None;
+                    return;
                 }
+            } else {
+                self.pending_window_close = None;
+            }
+        }
+
+        // A Ctrl+W or redirect is already in flight for this URL: give the tab a second to
+        // close or navigate away before escalating to closing the whole window
+        if let Some((hwnd_val, pending_url, sent_at)) = &self.pending_tab_close
+            && pending_url == url {
+            if sent_at.elapsed() < Duration::from_secs(1) {
+                return;
             }
+            let hwnd_val = *hwnd_val;
+            println!("[INFO] Blocked page is still open. Closing browser window (HWND: {})...", hwnd_val);
+            self.close_window(hwnd_val, url);
+            self.pending_tab_close = None;
+            return;
+        }
 
-            if url != self.last_url {
-                if !self.last_url.is_empty() {
-                    let start_time = self.url_timers.remove(&self.last_url).unwrap_or(now);
-                    let duration = now - start_time;
-                    *self.total_times.entry(self.last_url.clone()).or_insert(0.0) += duration;
+        // Actively block the window showing the URL if we can find it
+        if let Some(hwnd_val) = self.find_browser_window_with_url(url) {
+            let redirected = self.block_action == BlockAction::Redirect && match self.block_page_url.clone() {
+                Some(page) => {
+                    println!("[INFO] Found browser window HWND: {}. Redirecting to the block page...", hwnd_val);
+                    self.inspector.navigate(hwnd_val, &page)
                 }
-                
-                self.last_url = url.clone();
-                self.url_timers.insert(url.clone(), now);
-                
-                // Keep history for upload (limit to last 50)
-                self.urls_for_upload.push_back(url);
-                if self.urls_for_upload.len() > 50 {
-                    self.urls_for_upload.pop_front();
+                None => {
+                    println!("[WARN] Block action is redirect but no block page URL is configured");
+                    false
                 }
+            };
+            if redirected {
+                self.pending_tab_close = Some((hwnd_val, url.to_string(), Instant::now()));
+                return;
             }
-        } else if !self.last_url.is_empty() {
-            let start_time = self.url_timers.remove(&self.last_url).unwrap_or(now);
-            let duration = now - start_time;
-            *self.total_times.entry(self.last_url.clone()).or_insert(0.0) += duration;
-            self.last_url = String::new();
+            match self.block_action {
+                BlockAction::CloseTab | BlockAction::Redirect => {
+                    println!("[INFO] Found browser window HWND: {}. Closing blocked tab...", hwnd_val);
+                    if self.inspector.close_tab(hwnd_val) {
+                        self.pending_tab_close = Some((hwnd_val, url.to_string(), Instant::now()));
+                    } else {
+                        println!("[INFO] Failed to send Ctrl+W. Sending close message...");
+                        self.close_window(hwnd_val, url);
+                    }
+                }
+                BlockAction::CloseWindow => {
+                    println!("[INFO] Found browser window HWND: {}. Sending close message...", hwnd_val);
+                    self.close_window(hwnd_val, url);
+                }
+            }
+        } else if let Some(hwnd_val) = self.inspector.foreground_window() {
+            // Fallback to foreground window
+            println!("[INFO] Falling back to closing foreground window (HWND: {})", hwnd_val);
+            self.close_window(hwnd_val, url);
+        }
+    }
+
+    /// Posts WM_CLOSE and remembers the window so a page that survives it can be escalated.
+    fn close_window(&mut self, hwnd_val: isize, url: &str) {
+        self.inspector.close_window(hwnd_val);
+        let already_pending = matches!(&self.pending_window_close,
+            Some((pending_hwnd, pending_url, _)) if *pending_hwnd == hwnd_val && pending_url == url);
+        if !already_pending {
+            self.pending_window_close = Some((hwnd_val, url.to_string(), 0));
+        }
+    }
+
+    /// Logs how often the wildcard prefilter spared a full blacklist match since the list loaded.
+    pub fn log_blacklist_stats(&self) {
+        let (skipped, checked) = self.blacklist_matcher.prefilter_stats();
+        if skipped + checked > 0 {
+            println!("[DEBUG] Wildcard prefilter: {} URLs skipped, {} fully matched ({:.1}% skipped)",
+                skipped, checked, skipped as f64 * 100.0 / (skipped + checked) as f64);
         }
     }
 
     pub fn update_blacklist(&mut self, new_blacklist: Vec<String>) {
+        // Store patterns in punycode so Unicode and xn-- forms of a domain match each other
         self.api_blacklist = new_blacklist.into_iter()
-            .map(|s| s.trim().to_lowercase())
+            .map(|s| to_ascii_host(&s.trim().to_lowercase()))
             .filter(|s| !s.is_empty())
             .collect();
+
+        // Compile the whole list once here rather than walking it on every URL check
+        self.blacklist_matcher = url_match::PatternSet::new(&self.api_blacklist);
         println!("[DEBUG] Blacklist updated. {} patterns active.", self.api_blacklist.len());
         for p in &self.api_blacklist {
             println!("  - Block pattern: {}", p);
         }
     }
 
-    fn is_blocked(&self, url: &str) -> bool {
-        let url_lower = url.to_lowercase();
-        
+    pub fn update_mode(&mut self, mode: FilterMode) {
+        if self.mode != mode {
+            println!("[INFO] URL filter mode changed: {:?} -> {:?}", self.mode, mode);
+            self.mode = mode;
+        }
+    }
+
+    pub fn is_blocked(&self, url: &str) -> bool {
+        self.block_reason(url).is_some()
+    }
+
+    /// Why `url` would be blocked (the matching category or pattern), or None if it's allowed.
+    /// Depends only on the current lists and mode, so it can be checked without a browser.
+    pub fn block_reason(&self, url: &str) -> Option<String> {
         // Prevent matching extremely short "URLs" that are usually just user typing
-        if url_lower.len() < 4 {
+        if url.len() < url_match::MIN_URL_LEN {
+            return None;
+        }
+        // The block page must stay reachable, or an allow list would close it right away
+        if self.is_block_page(url) {
+            return None;
+        }
+
+        // The address bar says nothing about where the traffic really goes
+        if let Some(anonymizer) = self.active_anonymizer.as_ref().filter(|_| self.block_on_anonymizer) {
+            return Some(format!("anonymizer '{}' running", anonymizer));
+        }
+
+        if let Some(category) = self.blocked_category(url) {
+            return Some(format!("category '{}'", category));
+        }
+
+        match self.mode {
+            FilterMode::Blacklist => self.matching_pattern(url).map(|p| format!("pattern '{}'", p)),
+            // An empty allow list is treated as unconfigured rather than "block everything",
+            // so a failed fetch of the list can't lock users out of the browser entirely.
+            FilterMode::Whitelist if !self.api_blacklist.is_empty() && self.matching_pattern(url).is_none() => {
+                Some("not on the allow list".to_string())
+            }
+            FilterMode::Whitelist => None,
+        }
+    }
+
+    /// Whether `url` is the block page: the same host and port, and a path equal to or below the
+    /// page's, split on '/' so "/blocked" doesn't cover "/blocked-not". Browsers hide the scheme
+    /// in the address bar, so http and https are treated alike.
+    fn is_block_page(&self, url: &str) -> bool {
+        let (Some(page), Some(url)) = (self.block_page_url.as_deref().and_then(parse_page_url), parse_page_url(url)) else {
+            return false;
+        };
+        if (page.scheme() == "file") != (url.scheme() == "file") || page.host_str() != url.host_str() || page.port() != url.port() {
             return false;
         }
+        let page_path = page.path().trim_end_matches('/');
+        let path = url.path();
+        path == page_path || path.strip_prefix(page_path).is_some_and(|rest| rest.starts_with('/'))
+    }
 
-        // Normalize URL for matching
-        let mut normalized_url = url_lower.as_str();
-        if normalized_url.starts_with("http://") { normalized_url = &normalized_url[7..]; }
-        if normalized_url.starts_with("https://") { normalized_url = &normalized_url[8..]; }
-        if normalized_url.starts_with("www.") { normalized_url = &normalized_url[4..]; }
-        let normalized_url = normalized_url.trim_end_matches('/');
-
-        self.api_blacklist.iter().any(|pattern| {
-            // Normalize pattern as well
-            let mut normalized_pattern = pattern.as_str();
-            if normalized_pattern.starts_with("http://") { normalized_pattern = &normalized_pattern[7..]; }
-            if normalized_pattern.starts_with("https://") { normalized_pattern = &normalized_pattern[8..]; }
-            if normalized_pattern.starts_with("www.") { normalized_pattern = &normalized_pattern[4..]; }
-            let normalized_pattern = normalized_pattern.trim_end_matches('/');
-
-            let match_found = if pattern.contains('*') {
-                let regex_pattern = pattern.replace(".", "\\.").replace("*", ".*");
-                if let Ok(re) = regex::Regex::new(&format!("(?i)^{}$", regex_pattern)) {
-                    re.is_match(url)
-                } else {
-                    false
-                }
-            } else {
-                // Check if normalized URL contains normalized pattern (Domain Match)
-                // e.g., "facebook.com/login" contains "facebook.com"
-                normalized_url.contains(normalized_pattern)
-            };
+    pub fn update_blocked_categories(&mut self, categories: Vec<String>) {
+        self.blocked_categories = categories.into_iter()
+            .map(|c| c.trim().to_lowercase())
+            .filter(|c| !c.is_empty())
+            .collect();
+        println!("[DEBUG] Blocked categories updated. {} categories active.", self.blocked_categories.len());
+    }
 
-            if match_found {
-                println!("[DEBUG] URL match found! Pattern: '{}' matches URL: '{}' (Normalized: '{}' vs '{}')", 
-                    pattern, url, normalized_pattern, normalized_url);
+    fn blocked_category(&self, url: &str) -> Option<&'static str> {
+        if self.blocked_categories.is_empty() {
+            return None;
+        }
+        match domain_category(&extract_domain(url)) {
+            Some(category) if self.blocked_categories.contains(&category.to_lowercase()) => {
+                println!("[DEBUG] URL match found! Category '{}' is blocked for URL: '{}'", category, url);
+                Some(category)
             }
-            match_found
-        })
+            _ => None,
+        }
+    }
+
+    /// The first filter-list pattern matching `url`, if any.
+    fn matching_pattern(&self, url: &str) -> Option<&str> {
+        // Plain patterns are domain/substring matches: "facebook.com/login" contains "facebook.com"
+        let pattern = self.blacklist_matcher.find(url)?;
+        println!("[DEBUG] URL match found! Pattern: '{}' matches URL: '{}'", pattern, url);
+        Some(pattern)
     }
 
 
-    pub fn get_url_data_for_api(&mut self, clear_after: bool) -> crate::config::client::UrlMonitoringData {
-        let urls: Vec<String> = self.urls_for_upload.iter().cloned().collect();
-        let total_visits = self.total_times.values().map(|&v| v as u32).sum::<u32>();
+    /// Moves the URL history out for upload. If the upload fails, hand the URLs back with
+    /// requeue_urls so they go out with the next one.
+    pub fn take_url_data_for_api(&mut self) -> crate::config::client::UrlMonitoringData {
+        let visits: Vec<UrlVisit> = self.urls_for_upload.drain(..).collect();
+        let total_visits = self.visit_counts.values().sum::<u32>();
+        let is_private = self.private_browsing_seen || self.last_url_private;
+        self.private_browsing_seen = false;
 
-        let result = crate::config::client::UrlMonitoringData {
+        crate::config::client::UrlMonitoringData {
             device_id: crate::config::api_config::get_device_id(),
             timestamp: chrono::Local::now().to_rfc3339(),
-            urls,
+            timezone: crate::config::api_config::get_timezone(),
+            urls: visits.iter().map(|v| v.url.clone()).collect(),
+            visits,
             blocked_count: self.blocked_count,
             suspicious_count: self.suspicious_count,
             total_visits,
+            is_private,
+        }
+    }
+
+    /// Merges URL visits from a failed upload back into those recorded since, still dropping
+    /// the oldest past url_history_cap.
+    pub fn requeue_urls(&mut self, visits: Vec<UrlVisit>, is_private: bool) {
+        let newer: Vec<UrlVisit> = self.urls_for_upload.drain(..).collect();
+        self.urls_for_upload = merge_url_visits(visits, newer).into();
+        while self.urls_for_upload.len() > self.url_history_cap {
+            self.urls_for_upload.pop_front();
+        }
+        self.private_browsing_seen |= is_private;
+    }
+
+    /// Identifies browsers by window class rather than title, since the " - Google Chrome" suffix
+    /// is missing or translated on some builds. Electron apps share the Chromium class, so the
+    /// window must also belong to a browser executable or carry a browser name in its title
+    /// (which covers rebranded browsers from browserNames). Windows whose class can't be read
+    /// fall back to the title alone.
+    fn is_browser_window(&self, window: &TopLevelWindow, name_lower: &str) -> bool {
+        let name_matches = self.browser_names.iter().any(|name| name_lower.contains(name.as_str()));
+        if window.class_name.is_empty() {
+            return name_matches;
+        }
+        if !BROWSER_WINDOW_CLASSES.contains(&window.class_name.as_str()) {
+            return false;
+        }
+        name_matches || self.inspector.window_exe_name(window.hwnd)
+            .is_some_and(|exe| BROWSER_EXECUTABLES.contains(&exe.as_str()))
+    }
+
+    fn find_browser_window_with_url(&self, target_url: &str) -> Option<isize> {
+        self.inspector.top_level_windows().into_iter()
+            .filter(|window| self.is_browser_window(window, &window.name.to_lowercase()))
+            .find(|window| match self.inspector.address_bar_url(window.hwnd).or_else(|| self.inspector.document_url(window.hwnd)) {
+                Some(url) => url.contains(target_url) || target_url.contains(&url),
+                None => false,
+            })
+            .map(|window| window.hwnd)
+    }
+}
+
+/// True if `key` hasn't fired within the last `window` seconds, recording `now` for it when so.
+/// Entries whose window has passed are dropped, so the map only holds recent keys.
+fn throttle_elapsed(last_times: &mut HashMap<String, f64>, key: &str, now: f64, window: f64) -> bool {
+    last_times.retain(|_, t| now - *t <= window);
+    if last_times.contains_key(key) {
+        return false;
+    }
+    last_times.insert(key.to_string(), now);
+    true
+}
+
+/// True for the domain itself and any of its subdomains ("m.youtube.com" matches "youtube.com").
+fn domain_matches(domain: &str, parent: &str) -> bool {
+    domain == parent || domain.ends_with(&format!(".{}", parent))
+}
+
+/// The website category a domain (or its parent domain) belongs to.
+fn domain_category(domain: &str) -> Option<&'static str> {
+    get_domain_categories().iter()
+        .find(|(_, domains)| domains.iter().any(|d| domain_matches(domain, d)))
+        .map(|(category, _)| category.as_str())
+}
+
+/// Incognito (Chrome/Brave), InPrivate (Edge) and Private Browsing (Firefox) windows.
+fn is_private_window_name(name_lower: &str) -> bool {
+    name_lower.contains("incognito")
+        || name_lower.contains("inprivate")
+        || name_lower.contains("private browsing")
+}
+
+/// Parses a URL as read from the address bar, which may lack its scheme.
+fn parse_page_url(value: &str) -> Option<url::Url> {
+    let value = value.trim();
+    if value.contains("://") || value.starts_with("file:") {
+        url::Url::parse(value).ok()
+    } else {
+        url::Url::parse(&format!("http://{}", value)).ok()
+    }
+}
+
+/// Seconds since the Unix epoch as a local timestamp; now if out of range.
+fn epoch_to_local(secs: f64) -> chrono::DateTime<chrono::Local> {
+    use chrono::TimeZone;
+    chrono::Local.timestamp_millis_opt((secs * 1000.0) as i64).single().unwrap_or_else(chrono::Local::now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A browser window as the fake inspector reports it.
+    struct FakeWindow {
+        hwnd: isize,
+        title: &'static str,
+        class_name: &'static str,
+        exe: Option<&'static str>,
+        address_bar: Option<&'static str>,
+        document: Option<&'static str>,
+    }
+
+    /// What the monitor did to the windows, shared with the test after the inspector is boxed.
+    #[derive(Default)]
+    struct Actions {
+        closed_tabs: Vec<isize>,
+        closed_windows: Vec<isize>,
+        navigations: Vec<(isize, String)>,
+    }
+
+    struct FakeInspector {
+        windows: Vec<FakeWindow>,
+        foreground: Option<isize>,
+        actions: Rc<RefCell<Actions>>,
+    }
+
+    impl FakeInspector {
+        fn window(&self, hwnd: isize) -> Option<&FakeWindow> {
+            self.windows.iter().find(|w| w.hwnd == hwnd)
+        }
+    }
+
+    impl WindowInspector for FakeInspector {
+        fn top_level_windows(&self) -> Vec<TopLevelWindow> {
+            self.windows.iter()
+                .map(|w| TopLevelWindow { hwnd: w.hwnd, name: w.title.to_string(), class_name: w.class_name.to_string() })
+                .collect()
+        }
+        fn address_bar_url(&self, hwnd: isize) -> Option<String> {
+            self.window(hwnd)?.address_bar.map(str::to_string)
+        }
+        fn document_url(&self, hwnd: isize) -> Option<String> {
+            self.window(hwnd)?.document.map(str::to_string)
+        }
+        fn is_fullscreen(&self, _hwnd: isize) -> bool {
+            false
+        }
+        fn foreground_window(&self) -> Option<isize> {
+            self.foreground
+        }
+        fn window_exe_name(&self, hwnd: isize) -> Option<String> {
+            self.window(hwnd)?.exe.map(str::to_string)
+        }
+        fn close_tab(&self, hwnd: isize) -> bool {
+            self.actions.borrow_mut().closed_tabs.push(hwnd);
+            true
+        }
+        fn navigate(&self, hwnd: isize, url: &str) -> bool {
+            self.actions.borrow_mut().navigations.push((hwnd, url.to_string()));
+            true
+        }
+        fn close_window(&self, hwnd: isize) {
+            self.actions.borrow_mut().closed_windows.push(hwnd);
+        }
+        fn terminate_process(&self, _hwnd: isize) -> bool {
+            false
+        }
+    }
+
+    fn chrome(hwnd: isize, url: &'static str) -> FakeWindow {
+        FakeWindow { hwnd, title: "Page - Google Chrome", class_name: "Chrome_WidgetWin_1", exe: Some("chrome.exe"), address_bar: Some(url), document: None }
+    }
+
+    fn monitor(windows: Vec<FakeWindow>, foreground: Option<isize>) -> (BrowserMonitor, Rc<RefCell<Actions>>) {
+        let actions = Rc::new(RefCell::new(Actions::default()));
+        let inspector = FakeInspector { windows, foreground, actions: actions.clone() };
+        let mut monitor = BrowserMonitor::with_inspector(Box::new(inspector));
+        monitor.show_notifications = false;
+        monitor.update_blacklist(vec!["facebook.com".to_string()]);
+        (monitor, actions)
+    }
+
+    #[test]
+    fn finds_browser_windows_with_the_foreground_one_first() {
+        let editor = FakeWindow {
+            hwnd: 3, title: "notes.txt - Visual Studio Code", class_name: "Chrome_WidgetWin_1", exe: Some("code.exe"),
+            address_bar: Some("https://not-a-browser.example/"), document: None,
         };
-        
-        if clear_after {
-            self.urls_for_upload.clear();
-        }
-        
-        result
-    }
-
-    fn find_browser_window_with_url(&self, automation: &UIAutomation, walker: &UITreeWalker, root: &UIElement, target_url: &str) -> Option<UIElement> {
-        let mut current = walker.get_first_child(root).ok()?;
-        
-        loop {
-            if let Ok(name) = current.get_name() {
-                let name_lower = name.to_lowercase();
-                if name_lower.contains("chrome") || name_lower.contains("edge") || name_lower.contains("brave") {
-                    if let Some(url) = self.find_address_bar_url(automation, walker, &current) {
-                        if url.contains(target_url) || target_url.contains(&url) {
-                            return Some(current);
-                        }
-                    }
-                }
-            }
-            
-            if let Ok(next) = walker.get_next_sibling(&current) {
-                current = next;
-            } else {
-                break;
-            }
+        let (mut monitor, _) = monitor(vec![chrome(1, "https://docs.rs/"), chrome(2, "https://example.com/"), editor], Some(2));
+
+        assert_eq!(monitor.get_active_browser_urls(), vec!["https://example.com/", "https://docs.rs/"]);
+        assert_eq!(monitor.last_url_read_ok, Some(true));
+    }
+
+    #[test]
+    fn falls_back_to_the_page_document() {
+        let mut window = chrome(1, "");
+        window.address_bar = None;
+        window.document = Some("https://example.com/page");
+        let (mut monitor, _) = monitor(vec![window], Some(1));
+
+        assert_eq!(monitor.get_active_browser_urls(), vec!["https://example.com/page"]);
+    }
+
+    #[test]
+    fn closes_the_tab_showing_a_blocked_url() {
+        let (mut monitor, actions) = monitor(vec![chrome(1, "https://docs.rs/"), chrome(2, "https://www.facebook.com/feed")], Some(2));
+
+        monitor.update_timing(Some("https://www.facebook.com/feed".to_string()));
+
+        assert_eq!(monitor.blocked_count, 1);
+        assert_eq!(actions.borrow().closed_tabs, vec![2]);
+        assert!(actions.borrow().closed_windows.is_empty());
+    }
+
+    #[test]
+    fn close_window_action_closes_the_window() {
+        let (mut monitor, actions) = monitor(vec![chrome(1, "https://www.facebook.com/feed")], Some(1));
+        monitor.block_action = BlockAction::CloseWindow;
+
+        monitor.update_timing(Some("https://www.facebook.com/feed".to_string()));
+
+        assert_eq!(actions.borrow().closed_windows, vec![1]);
+        assert!(actions.borrow().closed_tabs.is_empty());
+    }
+
+    #[test]
+    fn observe_mode_counts_without_closing() {
+        let (mut monitor, actions) = monitor(vec![chrome(1, "https://www.facebook.com/feed")], Some(1));
+        monitor.enforcement_mode = EnforcementMode::Observe;
+
+        monitor.update_timing(Some("https://www.facebook.com/feed".to_string()));
+
+        assert_eq!(monitor.blocked_count, 1);
+        assert!(actions.borrow().closed_tabs.is_empty());
+        assert!(actions.borrow().closed_windows.is_empty());
+    }
+
+    #[test]
+    fn blocked_page_left_open_counts_once() {
+        let (mut monitor, _) = monitor(vec![chrome(1, "https://www.facebook.com/feed")], Some(1));
+        monitor.enforcement_mode = EnforcementMode::Observe;
+
+        monitor.update_timing(Some("https://www.facebook.com/feed".to_string()));
+        monitor.update_timing(Some("https://www.facebook.com/feed".to_string()));
+        assert_eq!(monitor.blocked_count, 1);
+
+        // Going back after another page is a new block
+        monitor.update_timing(Some("https://docs.rs/".to_string()));
+        monitor.update_timing(Some("https://www.facebook.com/feed".to_string()));
+        assert_eq!(monitor.blocked_count, 2);
+    }
+
+    #[test]
+    fn blocked_window_counts_once_across_scans() {
+        let (mut monitor, _) = monitor(vec![chrome(1, "https://docs.rs/"), chrome(2, "https://facebook.com/")], Some(1));
+        monitor.enforcement_mode = EnforcementMode::Observe;
+
+        for _ in 0..3 {
+            let urls = monitor.get_active_browser_urls();
+            monitor.update_timing(Some(urls[0].clone()));
+            monitor.check_background_url(&urls[1]);
+        }
+        assert_eq!(monitor.blocked_count, 1);
+    }
+
+    #[test]
+    fn allowed_url_is_left_alone() {
+        let (mut monitor, actions) = monitor(vec![chrome(1, "https://docs.rs/")], Some(1));
+
+        monitor.update_timing(Some("https://docs.rs/".to_string()));
+
+        assert_eq!(monitor.blocked_count, 0);
+        assert!(actions.borrow().closed_tabs.is_empty());
+    }
+
+    #[test]
+    fn redirect_action_opens_the_block_page() {
+        let (mut monitor, actions) = monitor(vec![chrome(1, "https://www.facebook.com/feed")], Some(1));
+        monitor.block_action = BlockAction::Redirect;
+        monitor.block_page_url = Some("https://block.corp/blocked".to_string());
+
+        monitor.update_timing(Some("https://www.facebook.com/feed".to_string()));
+
+        assert_eq!(actions.borrow().navigations, vec![(1, "https://block.corp/blocked".to_string())]);
+        assert!(actions.borrow().closed_tabs.is_empty());
+    }
+
+    #[test]
+    fn only_the_block_page_itself_is_exempt() {
+        let (mut monitor, _) = monitor(Vec::new(), None);
+        monitor.update_blacklist(vec!["block.corp".to_string()]);
+        monitor.block_page_url = Some("https://block.corp/blocked".to_string());
+
+        for url in ["https://block.corp/blocked", "block.corp/blocked/", "http://block.corp/blocked?site=x", "block.corp/blocked/details"] {
+            assert!(!monitor.is_blocked(url), "{} should be exempt", url);
+        }
+        for url in ["https://block.corp.evil.net/blocked", "https://block.corp/blocked-not", "https://block.corp/other", "https://block.corp:8443/blocked"] {
+            assert!(monitor.is_blocked(url), "{} should not be exempt", url);
         }
-        None
+    }
+
+    #[test]
+    fn background_window_with_a_blocked_url_is_closed() {
+        let (mut monitor, actions) = monitor(vec![chrome(1, "https://docs.rs/"), chrome(2, "https://facebook.com/")], Some(1));
+
+        monitor.check_background_url("https://docs.rs/");
+        assert!(actions.borrow().closed_tabs.is_empty());
+
+        monitor.check_background_url("https://facebook.com/");
+        assert_eq!(monitor.blocked_count, 1);
+        assert_eq!(actions.borrow().closed_tabs, vec![2]);
+        // Background checks don't count as time on the page
+        assert!(monitor.last_url.is_empty());
+    }
+
+    #[test]
+    fn hashed_privacy_hides_the_domain_too() {
+        let (mut monitor, _) = monitor(vec![], None);
+        let url = "https://intranet.example.com/payroll?id=7";
+        assert_eq!(monitor.reportable_domain(url), "intranet.example.com");
+
+        monitor.url_privacy = UrlPrivacy::PathOnly;
+        assert_eq!(monitor.reportable_url(url), "https://intranet.example.com/payroll");
+        assert_eq!(monitor.reportable_domain(url), "intranet.example.com");
+
+        monitor.url_privacy = UrlPrivacy::Hashed;
+        monitor.url_hash_salt = "salt".to_string();
+        let domain = monitor.reportable_domain(url);
+        assert_eq!(domain.len(), 64);
+        assert!(!domain.contains("example"));
+        assert_eq!(domain, UrlPrivacy::Hashed.apply("intranet.example.com", "salt"));
+    }
+
+    #[test]
+    fn uncommitted_sensitive_url_is_not_a_quick_exit() {
+        let (mut monitor, _) = monitor(vec![], None);
+        monitor.update_sensitive_patterns(vec!["payroll".to_string()]);
+
+        // Typing a URL passes through partial values that never become visits
+        monitor.update_timing(Some("https://intranet/payroll".to_string()));
+        monitor.update_timing(Some("https://intranet/payroll/2024".to_string()));
+        monitor.update_timing(None);
+        assert!(monitor.take_quick_exits().is_empty());
+        assert_eq!(monitor.suspicious_count, 0);
     }
 }
@@ -0,0 +1,276 @@
+/// The app in the foreground, as reported by a ForegroundProvider.
+#[derive(Clone, Debug)]
+pub struct ForegroundApp {
+    pub pid: u32,
+    /// Title of the frontmost window; empty if the platform doesn't expose it
+    pub title: String,
+}
+
+/// The platform calls AppTimeTracker needs, so tracking isn't tied to Win32.
+pub trait ForegroundProvider {
+    /// Process owning the frontmost window. Hosted apps (UWP on Windows) resolve to the
+    /// hosted process rather than their frame host.
+    fn active_process(&self) -> Option<ForegroundApp>;
+    /// Seconds since the last keyboard or mouse input (0 if it can't be determined).
+    fn idle_seconds(&self) -> f64;
+    /// Kills the process. Returns false if it couldn't be terminated.
+    fn terminate_process(&self, pid: u32) -> bool;
+}
+
+/// The provider for the platform being built for.
+pub fn default_provider() -> Box<dyn ForegroundProvider> {
+    #[cfg(windows)]
+    let provider: Box<dyn ForegroundProvider> = Box::new(win32::Win32Foreground);
+    #[cfg(target_os = "macos")]
+    let provider: Box<dyn ForegroundProvider> = Box::new(macos::MacForeground);
+    #[cfg(not(any(windows, target_os = "macos")))]
+    let provider: Box<dyn ForegroundProvider> = Box::new(NoForeground);
+    provider
+}
+
+/// Stands in on platforms without a provider yet: nothing is ever in the foreground, so no
+/// app time is tracked.
+#[cfg(not(any(windows, target_os = "macos")))]
+struct NoForeground;
+
+#[cfg(not(any(windows, target_os = "macos")))]
+impl ForegroundProvider for NoForeground {
+    fn active_process(&self) -> Option<ForegroundApp> {
+        None
+    }
+
+    fn idle_seconds(&self) -> f64 {
+        0.0
+    }
+
+    fn terminate_process(&self, _pid: u32) -> bool {
+        false
+    }
+}
+
+#[cfg(windows)]
+mod win32 {
+    use windows::Win32::Foundation::{CloseHandle, BOOL, HWND, LPARAM};
+    use windows::Win32::System::SystemInformation::GetTickCount64;
+    use windows::Win32::System::Threading::{OpenProcess, TerminateProcess, PROCESS_TERMINATE};
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+    use windows::Win32::UI::WindowsAndMessaging::{EnumChildWindows, GetClassNameW, GetForegroundWindow, GetWindowTextW, GetWindowThreadProcessId};
+
+    use super::{ForegroundApp, ForegroundProvider};
+
+    /// ForegroundProvider backed by Win32 window and input APIs.
+    pub struct Win32Foreground;
+
+    impl ForegroundProvider for Win32Foreground {
+        fn active_process(&self) -> Option<ForegroundApp> {
+            let hwnd = unsafe { GetForegroundWindow() };
+            if hwnd.0 == 0 {
+                return None;
+            }
+
+            let mut pid: u32 = 0;
+            unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+
+            // UWP/Store apps are hosted in ApplicationFrameHost; resolve the real app from its child window
+            if window_class(hwnd) == "ApplicationFrameWindow"
+                && let Some(hosted_pid) = find_hosted_app_pid(hwnd, pid) {
+                pid = hosted_pid;
+            }
+
+            let mut title = [0u16; 512];
+            let len = unsafe { GetWindowTextW(hwnd, &mut title) }.max(0) as usize;
+            Some(ForegroundApp { pid, title: String::from_utf16_lossy(&title[..len]) })
+        }
+
+        fn idle_seconds(&self) -> f64 {
+            let mut lii = LASTINPUTINFO {
+                cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+                dwTime: 0,
+            };
+            unsafe {
+                if GetLastInputInfo(&mut lii).as_bool() {
+                    let current_tick = GetTickCount64();
+
+                    // Handle the 32-bit wrap around of lii.dwTime
+                    let current_tick_32 = (current_tick & 0xFFFFFFFF) as u32;
+                    let idle_ticks = if current_tick_32 >= lii.dwTime {
+                        current_tick_32 - lii.dwTime
+                    } else {
+                        (u32::MAX - lii.dwTime) + current_tick_32
+                    };
+
+                    idle_ticks as f64 / 1000.0
+                } else {
+                    0.0
+                }
+            }
+        }
+
+        fn terminate_process(&self, pid: u32) -> bool {
+            unsafe {
+                match OpenProcess(PROCESS_TERMINATE, false, pid) {
+                    Ok(handle) => {
+                        let terminated = TerminateProcess(handle, 1).is_ok();
+                        let _ = CloseHandle(handle);
+                        if !terminated {
+                            println!("[ERROR] Could not terminate process {}", pid);
+                        }
+                        terminated
+                    }
+                    Err(e) => {
+                        println!("[ERROR] Could not open process {} for termination: {}", pid, e);
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    fn window_class(hwnd: HWND) -> String {
+        let mut class_name = [0u16; 256];
+        let len = unsafe { GetClassNameW(hwnd, &mut class_name) }.max(0) as usize;
+        String::from_utf16_lossy(&class_name[..len])
+    }
+
+    /// Finds the process behind a UWP app by looking for a child of the ApplicationFrameHost
+    /// frame window that belongs to a different process than the host.
+    fn find_hosted_app_pid(frame: HWND, host_pid: u32) -> Option<u32> {
+        struct Search {
+            host_pid: u32,
+            found: Option<u32>,
+        }
+
+        unsafe extern "system" fn enum_child(hwnd: HWND, lparam: LPARAM) -> BOOL {
+            unsafe {
+                let search = &mut *(lparam.0 as *mut Search);
+
+                let mut child_pid: u32 = 0;
+                GetWindowThreadProcessId(hwnd, Some(&mut child_pid));
+
+                if child_pid != 0 && child_pid != search.host_pid && window_class(hwnd) != "ApplicationFrameWindow" {
+                    search.found = Some(child_pid);
+                    return BOOL(0); // Stop enumerating
+                }
+                BOOL(1)
+            }
+        }
+
+        let mut search = Search { host_pid, found: None };
+        unsafe {
+            let _ = EnumChildWindows(frame, Some(enum_child), LPARAM(&mut search as *mut Search as isize));
+        }
+        search.found
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::ffi::{c_char, c_void, CStr};
+
+    use super::{ForegroundApp, ForegroundProvider};
+
+    type CFTypeRef = *const c_void;
+
+    const K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY: u32 = 1 << 0;
+    const K_CG_WINDOW_LIST_EXCLUDE_DESKTOP_ELEMENTS: u32 = 1 << 4;
+    const K_CG_NULL_WINDOW_ID: u32 = 0;
+    const K_CG_EVENT_SOURCE_STATE_COMBINED_SESSION: i32 = 0;
+    const K_CG_ANY_INPUT_EVENT_TYPE: u32 = !0;
+    const K_CF_NUMBER_SINT32_TYPE: isize = 3;
+    const K_CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const SIGTERM: i32 = 15;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    unsafe extern "C" {
+        fn CGWindowListCopyWindowInfo(option: u32, relative_to_window: u32) -> CFTypeRef;
+        fn CGEventSourceSecondsSinceLastEventType(state_id: i32, event_type: u32) -> f64;
+        static kCGWindowLayer: CFTypeRef;
+        static kCGWindowOwnerPID: CFTypeRef;
+        static kCGWindowName: CFTypeRef;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        fn CFArrayGetCount(array: CFTypeRef) -> isize;
+        fn CFArrayGetValueAtIndex(array: CFTypeRef, index: isize) -> CFTypeRef;
+        fn CFDictionaryGetValue(dict: CFTypeRef, key: CFTypeRef) -> CFTypeRef;
+        fn CFNumberGetValue(number: CFTypeRef, number_type: isize, value: *mut c_void) -> bool;
+        fn CFStringGetCString(string: CFTypeRef, buffer: *mut c_char, size: isize, encoding: u32) -> bool;
+        fn CFRelease(cf: CFTypeRef);
+    }
+
+    unsafe extern "C" {
+        fn kill(pid: i32, sig: i32) -> i32;
+    }
+
+    /// ForegroundProvider backed by the Quartz window list and event source.
+    pub struct MacForeground;
+
+    impl ForegroundProvider for MacForeground {
+        fn active_process(&self) -> Option<ForegroundApp> {
+            unsafe {
+                let windows = CGWindowListCopyWindowInfo(
+                    K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY | K_CG_WINDOW_LIST_EXCLUDE_DESKTOP_ELEMENTS,
+                    K_CG_NULL_WINDOW_ID,
+                );
+                if windows.is_null() {
+                    return None;
+                }
+
+                // The list is front to back; the first normal-layer window belongs to the frontmost app
+                let mut found = None;
+                for i in 0..CFArrayGetCount(windows) {
+                    let window = CFArrayGetValueAtIndex(windows, i);
+                    if dict_i32(window, kCGWindowLayer) != Some(0) {
+                        continue;
+                    }
+                    if let Some(pid) = dict_i32(window, kCGWindowOwnerPID) {
+                        // Window titles need the Screen Recording permission; without it they're absent
+                        let title = dict_string(window, kCGWindowName).unwrap_or_default();
+                        found = Some(ForegroundApp { pid: pid as u32, title });
+                        break;
+                    }
+                }
+                CFRelease(windows);
+                found
+            }
+        }
+
+        fn idle_seconds(&self) -> f64 {
+            unsafe { CGEventSourceSecondsSinceLastEventType(K_CG_EVENT_SOURCE_STATE_COMBINED_SESSION, K_CG_ANY_INPUT_EVENT_TYPE) }
+        }
+
+        fn terminate_process(&self, pid: u32) -> bool {
+            let terminated = unsafe { kill(pid as i32, SIGTERM) } == 0;
+            if !terminated {
+                println!("[ERROR] Could not terminate process {}", pid);
+            }
+            terminated
+        }
+    }
+
+    unsafe fn dict_i32(dict: CFTypeRef, key: CFTypeRef) -> Option<i32> {
+        unsafe {
+            let number = CFDictionaryGetValue(dict, key);
+            if number.is_null() {
+                return None;
+            }
+            let mut value: i32 = 0;
+            CFNumberGetValue(number, K_CF_NUMBER_SINT32_TYPE, &mut value as *mut i32 as *mut c_void).then_some(value)
+        }
+    }
+
+    unsafe fn dict_string(dict: CFTypeRef, key: CFTypeRef) -> Option<String> {
+        unsafe {
+            let string = CFDictionaryGetValue(dict, key);
+            if string.is_null() {
+                return None;
+            }
+            let mut buffer = [0 as c_char; 512];
+            if !CFStringGetCString(string, buffer.as_mut_ptr(), buffer.len() as isize, K_CF_STRING_ENCODING_UTF8) {
+                return None;
+            }
+            Some(CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use std::sync::{Mutex, MutexGuard};
+
+/// Locking that survives a poisoned Mutex. A thread that panics while holding one of the shared
+/// locks would otherwise make every later `lock().unwrap()` panic too, taking tracking, blocking
+/// and uploads down with it.
+pub trait LockRecover<T> {
+    /// Locks the mutex. If a panicking thread poisoned it, logs a warning, clears the poison and
+    /// carries on with the data as that thread left it.
+    fn lock_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockRecover<T> for Mutex<T> {
+    fn lock_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(|poisoned| {
+            println!("[WARN] Recovering shared state left locked by a panicked thread ({})", std::any::type_name::<T>());
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
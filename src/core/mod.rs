@@ -1,4 +1,20 @@
+pub mod anonymizer;
 pub mod app_tracker;
+pub mod audit;
 pub mod browser_monitor;
+pub mod foreground;
+pub mod lock;
 pub mod monitor;
+pub mod network_info;
 pub mod partial_access_manager;
+pub mod process_identity;
+pub mod retention;
+pub mod schedule;
+pub mod session;
+pub mod screenshot;
+pub mod status_server;
+pub mod uploader;
+pub mod url_match;
+pub mod usb_monitor;
+pub mod url_utils;
+pub mod window_inspector;
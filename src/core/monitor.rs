@@ -1,29 +1,137 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::time::{Duration, Instant};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::io::Write;
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::time::sleep;
-use chrono::Local;
+use chrono::{Local, NaiveDate};
 
-use crate::core::app_tracker::AppTimeTracker;
-use crate::core::browser_monitor::BrowserMonitor;
+use crate::core::anonymizer::{self, AnonymizerFinding};
+use crate::core::audit::audit_event;
+use crate::core::app_tracker::{AppData, AppTimeTracker};
+use crate::core::browser_monitor::{BlockAction, BrowserMonitor, EnforcementMode, FilterMode, UrlPrivacy};
+use crate::core::lock::LockRecover;
+use crate::core::network_info::network_context;
 use crate::core::partial_access_manager::PartialAccessManager;
-use crate::config::client::APIClient;
-use crate::config::settings::CHECK_INTERVAL;
+use crate::core::retention;
+use crate::core::schedule::WorkingHours;
+use crate::core::session::log_session_context;
+use crate::core::screenshot;
+use crate::core::status_server::{spawn_status_server, ControlRequests, MonitorStatus};
+use crate::core::uploader::{spawn_uploader, SyncJob, SyncOutcome};
+use crate::core::usb_monitor::{spawn_usb_monitor, UsbVolume};
+use crate::config::api_config::{get_api_base_url, get_device_id, reload_api_config};
+use crate::config::client::{APIClient, AccessAttemptData, AccessAttemptKind, AnonymizerData, EnforcementHealth, NetworkContext, UploadOutcome, UsbDeviceData};
+use crate::config::settings::{
+    get_status_port, ANONYMIZER_CHECK_INTERVAL_SECS, DEFAULT_ANONYMIZER_PROCESSES, API_MAX_RETRIES, API_RETRY_BASE_DELAY_MS, API_SHORT_TIMEOUT_SECS, API_UPLOAD_TIMEOUT_SECS, APP_SAMPLE_INTERVAL_SECS, BATCH_UPLOADS, CONFIG_UPDATE_INTERVAL, FULL_APP_EXPORT_INTERVAL,
+    HEARTBEAT_INTERVAL,
+    REPORT_NETWORK_CONTEXT, RETENTION_DAYS, RETENTION_MAX_DIR_MB, SCHEDULE_JITTER_FRACTION, SYNC_INTERVAL, UPLOAD_CHANNEL_CAPACITY, URL_CHECK_INTERVAL_MS,
+};
+
+/// Where each finished day's totals are archived as YYYY-MM-DD.json
+pub const DAILY_ARCHIVE_DIR: &str = "data/daily";
 
 pub struct CybersecurityMonitor {
     pub app_tracker: AppTimeTracker,
     pub browser_monitor: BrowserMonitor,
     pub partial_access: PartialAccessManager,
     pub api_client: Arc<APIClient>,
+    pub shutdown: Arc<AtomicBool>,
+    /// Set from the tray to stop tracking and blocking; heartbeats keep flowing
+    pub paused: Arc<AtomicBool>,
+    /// Set while paused or outside working hours; the partial-access thread idles while it is
+    pub suspended: Arc<AtomicBool>,
+    /// When monitoring may run; outside it nothing is tracked, blocked or uploaded
+    pub working_hours: WorkingHours,
+    /// Config refreshes and syncs requested through the status endpoint
+    pub control: Arc<ControlRequests>,
+    pub sync_interval: Duration,
+    pub config_update_interval: Duration,
+    pub heartbeat_interval: Duration,
+    /// How often browser URLs are checked and blocked pages closed; also the loop's tick
+    pub url_check_interval: Duration,
+    /// How often the foreground app is sampled for usage tracking
+    pub app_sample_interval: Duration,
+    /// Fraction of random spread applied to the sync and config-update intervals
+    pub schedule_jitter: f64,
+    /// xorshift state for the jitter, seeded from the device id so devices spread out
+    jitter_state: u64,
+    /// Sync with one combined request instead of separate app usage, URL and log uploads
+    pub batch_uploads: bool,
+    /// How often the complete app totals go out with a sync; zero disables the export
+    pub full_app_export_interval: Duration,
+    /// Include SSID / local IP in heartbeats
+    pub report_network_context: bool,
+    /// CIDR ranges ("10.20.0.0/16") that count as the office network
+    pub corporate_subnets: Vec<String>,
+    /// How often to look for VPN/anonymizer clients and local proxies (zero disables)
+    pub anonymizer_check_interval: Duration,
+    /// Process names treated as anonymizers (lowercase, without ".exe")
+    pub anonymizer_processes: Vec<String>,
+    /// Proxy hosts that are expected (e.g. the corporate proxy); others are reported
+    pub allowed_proxy_servers: Vec<String>,
+    /// Anonymizers found by the last check, so each is reported when it appears
+    active_anonymizers: Vec<AnonymizerFinding>,
+    /// Blocked, suspicious and dialogs-closed totals at the last health report
+    health_baseline: (u32, u32, u32),
+    /// Age in days past which logs, archives and screenshots are deleted (0 = never)
+    pub retention_days: u64,
+    /// Size cap per retained directory in bytes (0 = none)
+    pub retention_max_dir_bytes: u64,
+    /// Local date the app and URL totals are being accumulated for
+    pub current_day: NaiveDate,
+    /// Shared with the local status endpoint
+    pub status: Arc<Mutex<MonitorStatus>>,
+    started_at: Instant,
+    /// Browser windows besides the foreground one found by the last URL check, for the status line
+    background_url_count: usize,
 }
 
 impl CybersecurityMonitor {
     pub fn new() -> Self {
+        let app_tracker = AppTimeTracker::new();
+        // Saved totals may be from an earlier day; the first tick rolls them over
+        let current_day = app_tracker.data.lock_recover().day.unwrap_or_else(|| Local::now().date_naive());
+
         CybersecurityMonitor {
-            app_tracker: AppTimeTracker::new(),
+            app_tracker,
             browser_monitor: BrowserMonitor::new(),
             partial_access: PartialAccessManager::new(),
-            api_client: Arc::new(APIClient::new()),
+            api_client: Arc::new(APIClient::new(
+                API_MAX_RETRIES,
+                Duration::from_millis(API_RETRY_BASE_DELAY_MS),
+                Duration::from_secs(API_SHORT_TIMEOUT_SECS),
+                Duration::from_secs(API_UPLOAD_TIMEOUT_SECS),
+            )),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            suspended: Arc::new(AtomicBool::new(false)),
+            working_hours: WorkingHours::default(),
+            control: Arc::new(ControlRequests::default()),
+            sync_interval: Duration::from_secs(SYNC_INTERVAL),
+            config_update_interval: Duration::from_secs(CONFIG_UPDATE_INTERVAL),
+            heartbeat_interval: Duration::from_secs(HEARTBEAT_INTERVAL),
+            url_check_interval: Duration::from_millis(URL_CHECK_INTERVAL_MS),
+            app_sample_interval: Duration::from_secs(APP_SAMPLE_INTERVAL_SECS),
+            schedule_jitter: SCHEDULE_JITTER_FRACTION,
+            jitter_state: device_jitter_seed(),
+            batch_uploads: BATCH_UPLOADS,
+            full_app_export_interval: Duration::from_secs(FULL_APP_EXPORT_INTERVAL),
+            report_network_context: REPORT_NETWORK_CONTEXT,
+            corporate_subnets: Vec::new(),
+            anonymizer_check_interval: Duration::from_secs(ANONYMIZER_CHECK_INTERVAL_SECS),
+            anonymizer_processes: DEFAULT_ANONYMIZER_PROCESSES.iter().map(|s| s.to_string()).collect(),
+            allowed_proxy_servers: Vec::new(),
+            active_anonymizers: Vec::new(),
+            health_baseline: (0, 0, 0),
+            retention_days: RETENTION_DAYS,
+            retention_max_dir_bytes: RETENTION_MAX_DIR_MB * 1024 * 1024,
+            current_day,
+            status: Arc::new(Mutex::new(MonitorStatus::default())),
+            started_at: Instant::now(),
+            background_url_count: 0,
         }
     }
 
@@ -32,104 +140,671 @@ impl CybersecurityMonitor {
         
         let _ = std::fs::create_dir_all("logs");
         let _ = std::fs::create_dir_all("data");
+        retention::sweep(self.retention_days, self.retention_max_dir_bytes);
+        log_session_context();
+        
+        println!("  API server: {}", get_api_base_url());
         
         println!("  [1/3] Registering device...");
         self.api_client.register_device().await;
         
         println!("  [2/3] Sending initial heartbeat...");
-        self.api_client.send_heartbeat().await;
+        let health = self.enforcement_health();
+        self.api_client.send_heartbeat(self.paused.load(Ordering::SeqCst), self.network_context(), health).await;
         
         println!("  [3/3] Starting background threads...");
-        self.partial_access.start_monitoring(self.api_client.clone());
+        self.partial_access.start_monitoring(self.api_client.clone(), self.suspended.clone());
+        let status_port = get_status_port();
+        if status_port != 0 {
+            spawn_status_server(self.status.clone(), self.control.clone(), status_port);
+        }
         
+        let mut usb_events = spawn_usb_monitor(self.shutdown.clone());
+
+        let (upload_tx, mut outcome_rx, uploader) = spawn_uploader(self.api_client.clone(), UPLOAD_CHANNEL_CAPACITY);
         let mut last_sync = Instant::now();
-        let mut last_config_update = Instant::now() - Duration::from_secs(300); // Trigger update soon
+        let mut last_heartbeat = Instant::now();
+        let mut last_config_update = Instant::now() - self.config_update_interval; // Trigger update right away
+        let mut next_sync = self.jittered(self.sync_interval);
+        let mut last_full_app_export: Option<Instant> = None;
+        let mut next_config_update = self.config_update_interval;
+        let mut last_app_sample: Option<Instant> = None;
+        let mut last_anonymizer_check: Option<Instant> = None;
+        let mut was_suspended = false;
+        let mut was_off_hours = false;
         println!("Monitoring loop active. Press Ctrl+C to stop.");
         
-        loop {
-            // Diagnostic print
-            let now = Local::now().format("%H:%M:%S");
-            print!("\r[{}] Monitor active | App: ", now);
-            let _ = std::io::stdout().flush();
-
-            // Check app usage
-            if let Some(app) = self.app_tracker.track_app_usage() {
-                print!("{} | ", app);
-            } else {
-                print!("None | ");
+        while !self.shutdown.load(Ordering::SeqCst) {
+            let today = Local::now().date_naive();
+            if today != self.current_day {
+                self.rollover_day(today);
             }
-            let _ = std::io::stdout().flush();
-            
-            // Check browser URL
-            if let Some(url) = self.browser_monitor.get_active_browser_url_optimized() {
-                self.browser_monitor.update_timing(Some(url.clone()));
-                
-                // Sync context for Partial Access
-                {
-                    let mut ctx = self.partial_access.context.lock().unwrap();
-                    ctx.current_url = url.clone();
-                    // Basic domain extraction
-                    ctx.current_domain = if let Some(domain_part) = url.split("://").nth(1).and_then(|s| s.split('/').next()) {
-                        domain_part.to_string()
-                    } else {
-                        url.clone()
-                    };
-                }
 
-                print!("URL: {} ", if url.len() > 30 { format!("{}...", &url[..27]) } else { url });
+            let off_hours = !self.working_hours.is_active(Local::now());
+            if off_hours != was_off_hours {
+                println!("\n[INFO] {} working hours", if off_hours { "Outside" } else { "Back within" });
+                audit_event(if off_hours { "working_hours_ended" } else { "working_hours_started" }, serde_json::json!({}));
+                was_off_hours = off_hours;
+            }
+            let paused = self.paused.load(Ordering::SeqCst);
+            self.suspended.store(paused || off_hours, Ordering::SeqCst);
+
+            if paused || off_hours {
+                if !was_suspended {
+                    // Close out in-progress sessions so paused time isn't counted
+                    self.app_tracker.flush_current_session();
+                    self.browser_monitor.update_timing(None);
+                    was_suspended = true;
+                }
+                print!("\r[{}] Monitor {} ", Local::now().format("%H:%M:%S"), if paused { "paused" } else { "off (outside working hours)" });
+                let _ = std::io::stdout().flush();
             } else {
-                self.browser_monitor.update_timing(None);
-                
-                // Do NOT clear context here! 
-                // If a dialog is open, get_active_browser_url_optimized might return None
-                // but we need the current_url to stay set to the browser's URL 
-                // so the partial access check works.
-                
-                print!("URL: None (Preserving context) ");
+                was_suspended = false;
+                // Blocking reacts on every tick; app usage only needs a coarser sample
+                self.check_urls();
+                if last_app_sample.is_none_or(|t| t.elapsed() >= self.app_sample_interval) {
+                    self.sample_apps();
+                    last_app_sample = Some(Instant::now());
+                }
+                if !self.anonymizer_check_interval.is_zero()
+                    && last_anonymizer_check.is_none_or(|t| t.elapsed() >= self.anonymizer_check_interval) {
+                    self.check_anonymizers();
+                    last_anonymizer_check = Some(Instant::now());
+                }
+            }
+            self.update_status();
+
+            while let Ok(volume) = usb_events.try_recv() {
+                self.report_usb_volume(volume);
             }
-            let _ = std::io::stdout().flush();
 
-            // Periodic configuration update (every 30 seconds)
-            if last_config_update.elapsed() >= Duration::from_secs(30) {
+            // Periodic configuration update (every CONFIG_UPDATE_INTERVAL seconds by default)
+            let refresh_requested = self.control.refresh_config.swap(false, Ordering::SeqCst);
+            if refresh_requested || last_config_update.elapsed() >= next_config_update {
                 println!("[{}] Checking for configuration updates...", Local::now().format("%H:%M:%S"));
+                // A manual refresh also re-reads the local API settings (base URL, device id)
+                if refresh_requested {
+                    reload_api_config();
+                }
+
+                // Kill switch: stop through the same flush-and-upload path as Ctrl-C
+                if self.api_client.check_remote_shutdown().await {
+                    println!("\n[ALERT] ==================================================");
+                    println!("[ALERT] Remote shutdown commanded by the server. Stopping monitor...");
+                    println!("[ALERT] ==================================================");
+                    audit_event("remote_shutdown", serde_json::json!({}));
+                    self.shutdown.store(true, Ordering::SeqCst);
+                    continue;
+                }
                 
                 // Update Partial Access Config
                 self.partial_access.update_config(&self.api_client).await;
                 
-                // Update Blocked URLs
-                let blocked_urls = self.api_client.get_blocked_urls().await;
-                // Always update, even if empty, so changes (like removals) are reflected
-                self.browser_monitor.update_blacklist(blocked_urls);
-                println!("[{}] Updated blocked URL list (Blacklist size: {})", 
-                    Local::now().format("%H:%M:%S"), 
-                    self.browser_monitor.api_blacklist.len());
+                // Update monitor-wide settings
+                if let Some(monitor_config) = self.api_client.get_monitor_config().await {
+                    self.apply_monitor_config(&monitor_config);
+                }
+                
+                // Update Blocked URLs (None means unchanged since the last fetch)
+                if let Some(blocked_urls) = self.api_client.get_blocked_urls_conditional().await {
+                    // Always update, even if empty, so changes (like removals) are reflected
+                    self.browser_monitor.update_blacklist(blocked_urls);
+                    println!("[{}] Updated blocked URL list (Blacklist size: {})", 
+                        Local::now().format("%H:%M:%S"), 
+                        self.browser_monitor.api_blacklist.len());
+                }
                 
                 last_config_update = Instant::now();
+                next_config_update = self.jittered(self.config_update_interval);
             }
 
-            // Periodic Sync (every 60 seconds)
-            if last_sync.elapsed() >= Duration::from_secs(60) {
-                println!("[{}] Synchronizing with API...", Local::now().format("%H:%M:%S"));
-                
-                // Send heartbeat
-                self.api_client.send_heartbeat().await;
-                
-                // Upload app usage
-                let app_data = self.app_tracker.get_app_data_for_api();
-                self.api_client.upload_app_usage(app_data).await;
-                
-                // Upload URL data
-                let url_data = self.browser_monitor.get_url_data_for_api(true);
-                self.api_client.upload_urls(url_data).await;
-                
-                // Upload logs (non-clearing for now, or use true if desired)
-                self.api_client.upload_logs(std::path::Path::new("logs/app_timelog.log"), false).await;
-                
-                println!("[{}] API sync complete.", Local::now().format("%H:%M:%S"));
+            // Periodic heartbeat (every HEARTBEAT_INTERVAL seconds by default)
+            if last_heartbeat.elapsed() >= self.heartbeat_interval {
+                let health = self.enforcement_health();
+                self.api_client.send_heartbeat(self.paused.load(Ordering::SeqCst), self.network_context(), health).await;
+                last_heartbeat = Instant::now();
+            }
+
+            // Apply results of uploads finished since the last tick
+            while let Ok(outcome) = outcome_rx.try_recv() {
+                if outcome.heartbeat_sent {
+                    last_heartbeat = Instant::now();
+                }
+                self.handle_sync_outcome(outcome);
+            }
+
+            // Periodic Sync (every SYNC_INTERVAL seconds by default). The uploads run on the
+            // uploader task so a slow server doesn't hold up tracking and blocking.
+            let sync_requested = self.control.sync_now.swap(false, Ordering::SeqCst);
+            // Nothing is uploaded outside working hours; what's pending goes with the first sync after
+            if !off_hours && (sync_requested || last_sync.elapsed() >= next_sync) {
+                self.browser_monitor.log_blacklist_stats();
+                let full_export_due = sync_requested || (!self.full_app_export_interval.is_zero()
+                    && last_full_app_export.is_none_or(|t| t.elapsed() >= self.full_app_export_interval));
+                let job = SyncJob {
+                    paused: self.paused.load(Ordering::SeqCst),
+                    network: if self.batch_uploads { self.network_context() } else { None },
+                    health: self.batch_uploads.then(|| self.enforcement_health()),
+                    app_data: self.app_tracker.get_app_data_for_api(),
+                    url_data: self.browser_monitor.take_url_data_for_api(),
+                    partial_access_stats: self.partial_access.get_stats_for_api(),
+                    full_app_export: full_export_due.then(|| self.app_tracker.get_full_app_data_for_api()),
+                    batch: self.batch_uploads,
+                };
+                match upload_tx.try_send(job) {
+                    Ok(()) if full_export_due => last_full_app_export = Some(Instant::now()),
+                    Ok(()) => {}
+                    Err(e) => {
+                        let job = match e {
+                            TrySendError::Full(job) | TrySendError::Closed(job) => job,
+                        };
+                        println!("[WARN] Uploader is backed up, skipping this sync. {} URLs kept for the next one.", job.url_data.visits.len());
+                        self.browser_monitor.requeue_urls(job.url_data.visits, job.url_data.is_private);
+                    }
+                }
                 last_sync = Instant::now();
+                next_sync = self.jittered(self.sync_interval);
             }
 
-            sleep(Duration::from_secs(CHECK_INTERVAL)).await;
+            sleep(self.url_check_interval).await;
         }
+
+        // Let the uploader finish what it has queued, then pick up anything it couldn't send
+        drop(upload_tx);
+        let _ = uploader.await;
+        while let Ok(outcome) = outcome_rx.try_recv() {
+            self.handle_sync_outcome(outcome);
+        }
+
+        self.final_sync().await;
     }
+
+    fn handle_sync_outcome(&mut self, outcome: SyncOutcome) {
+        if !outcome.url_upload.kept() {
+            println!("[WARN] URL upload failed, keeping {} URLs for the next sync", outcome.unsent_urls.len());
+            self.browser_monitor.requeue_urls(outcome.unsent_urls, outcome.unsent_private);
+        }
+        // Queued uploads haven't reached the server yet
+        if outcome.app_upload == UploadOutcome::Sent && outcome.url_upload == UploadOutcome::Sent {
+            self.status.lock_recover().last_successful_sync = Some(Local::now().to_rfc3339());
+        }
+    }
+
+    /// Samples the foreground app for usage tracking and prints the status line.
+    fn sample_apps(&mut self) {
+        // Diagnostic print
+        let now = Local::now().format("%H:%M:%S");
+        print!("\r[{}] Monitor active | App: ", now);
+        let _ = std::io::stdout().flush();
+
+        // Check app usage
+        if let Some(app) = self.app_tracker.track_app_usage() {
+            print!("{} | ", app);
+        } else {
+            print!("None | ");
+        }
+        print!("Idle: {:.0}s | ", self.app_tracker.idle_seconds());
+        for data in self.app_tracker.take_spoofed_processes() {
+            let api_client = self.api_client.clone();
+            tokio::spawn(async move {
+                api_client.report_process_spoofing(data).await;
+            });
+        }
+
+        let url = &self.browser_monitor.last_url;
+        if url.is_empty() {
+            print!("URL: None ");
+        } else {
+            // Truncate by characters: a byte index can fall inside a non-ASCII character
+            let shown = if url.chars().count() > 30 { format!("{}...", url.chars().take(27).collect::<String>()) } else { url.clone() };
+            print!("URL: {} ", shown);
+        }
+        if self.background_url_count > 0 {
+            print!("(+{} background) ", self.background_url_count);
+        }
+        let _ = std::io::stdout().flush();
+    }
+
+    /// Reads the browser URLs, enforcing blocking and tracking page time, and reports any
+    /// quick exits or suspicious visits found.
+    fn check_urls(&mut self) {
+        // Check browser URLs: the foreground window gets active time, every window gets blocked
+        let urls = self.browser_monitor.get_active_browser_urls();
+        if let Some((url, background_urls)) = urls.split_first() {
+            self.browser_monitor.update_timing(Some(url.clone()));
+            for background_url in background_urls {
+                self.browser_monitor.check_background_url(background_url);
+            }
+            self.background_url_count = background_urls.len();
+            
+            // Sync context for Partial Access
+            self.partial_access.update_context(url, self.browser_monitor.last_url_hwnd);
+        } else {
+            self.browser_monitor.update_timing(None);
+            self.background_url_count = 0;
+            
+            // Do NOT clear context here! 
+            // If a dialog is open, get_active_browser_urls might return nothing
+            // but we need the current_url to stay set to the browser's URL 
+            // so the partial access check works.
+        }
+
+        for (url, dwell) in self.browser_monitor.take_quick_exits() {
+            let attempt = AccessAttemptData {
+                domain: self.browser_monitor.reportable_domain(&url),
+                url: self.browser_monitor.reportable_url(&url),
+                file_type: "quick-exit".to_string(),
+                blocked: false,
+                monitor_mode: "monitor".to_string(),
+                matched_pattern: None,
+                dwell_secs: Some(dwell),
+            };
+            let api_client = self.api_client.clone();
+            tokio::spawn(async move {
+                api_client.record_access_attempt(attempt, AccessAttemptKind::QuickExit).await;
+            });
+        }
+
+        for (url, pattern) in self.browser_monitor.take_suspicious_visits() {
+            let attempt = AccessAttemptData {
+                domain: self.browser_monitor.reportable_domain(&url),
+                url: self.browser_monitor.reportable_url(&url),
+                file_type: "suspicious-url".to_string(),
+                blocked: false,
+                monitor_mode: "monitor".to_string(),
+                matched_pattern: Some(pattern),
+                dwell_secs: None,
+            };
+            let api_client = self.api_client.clone();
+            tokio::spawn(async move {
+                api_client.record_access_attempt(attempt, AccessAttemptKind::SuspiciousUrl).await;
+            });
+        }
+    }
+
+    /// Logs and reports an inserted USB drive, flagged as suspicious when a restricted site is open.
+    fn report_usb_volume(&mut self, volume: UsbVolume) {
+        if self.suspended.load(Ordering::SeqCst) {
+            return;
+        }
+        let suspicious = self.partial_access.restricted_site_open();
+        if suspicious {
+            self.browser_monitor.suspicious_count += 1;
+            println!("\n[ALERT] USB drive {} inserted ({} {}) while a restricted site is open", volume.drive, volume.label, volume.serial);
+        } else {
+            println!("\n[INFO] USB drive {} inserted ({} {})", volume.drive, volume.label, volume.serial);
+        }
+        audit_event("usb_inserted", serde_json::json!({
+            "drive": volume.drive,
+            "label": volume.label,
+            "serial": volume.serial,
+            "suspicious": suspicious,
+        }));
+
+        let data = UsbDeviceData {
+            device_id: get_device_id(),
+            timestamp: Local::now().to_rfc3339(),
+            drive: volume.drive,
+            volume_label: volume.label,
+            volume_serial: volume.serial,
+            suspicious,
+            current_url: suspicious.then(|| self.browser_monitor.reportable_url(&self.browser_monitor.last_url)),
+        };
+        let api_client = self.api_client.clone();
+        tokio::spawn(async move {
+            api_client.report_usb_device(data).await;
+        });
+    }
+
+    /// Looks for VPN/anonymizer clients and local proxies, reporting each one when it appears.
+    fn check_anonymizers(&mut self) {
+        let findings = anonymizer::scan(&self.anonymizer_processes, &self.allowed_proxy_servers);
+        let blocking = self.browser_monitor.block_on_anonymizer;
+
+        for finding in findings.iter().filter(|f| !self.active_anonymizers.contains(f)) {
+            self.browser_monitor.suspicious_count += 1;
+            println!("\n[ALERT] Anonymizer detected ({}): {}{}", finding.kind, finding.name,
+                if blocking { ". Blocking browsing while it runs" } else { "" });
+            audit_event("anonymizer_detected", serde_json::json!({
+                "kind": finding.kind,
+                "name": finding.name,
+                "browsing_blocked": blocking,
+            }));
+
+            let data = AnonymizerData {
+                device_id: get_device_id(),
+                timestamp: Local::now().to_rfc3339(),
+                kind: finding.kind.to_string(),
+                name: finding.name.clone(),
+                browsing_blocked: blocking,
+            };
+            let api_client = self.api_client.clone();
+            tokio::spawn(async move {
+                api_client.report_anonymizer(data).await;
+            });
+        }
+        for gone in self.active_anonymizers.iter().filter(|f| !findings.contains(f)) {
+            println!("\n[INFO] Anonymizer no longer detected ({}): {}", gone.kind, gone.name);
+        }
+
+        self.browser_monitor.active_anonymizer = findings.first().map(|f| f.name.clone());
+        self.active_anonymizers = findings;
+    }
+
+    /// Enforcement state for a heartbeat. The counts are since the previous call.
+    fn enforcement_health(&mut self) -> EnforcementHealth {
+        let dialogs_closed = self.partial_access.stats.lock_recover().dialogs_closed;
+        let totals = (self.browser_monitor.blocked_count, self.browser_monitor.suspicious_count, dialogs_closed);
+        let (blocked, suspicious, dialogs) = std::mem::replace(&mut self.health_baseline, totals);
+
+        EnforcementHealth {
+            enforcement_mode: match self.browser_monitor.enforcement_mode {
+                EnforcementMode::Enforce => "enforce",
+                EnforcementMode::Observe => "observe",
+            }.to_string(),
+            last_url_read_ok: self.browser_monitor.last_url_read_ok,
+            secs_since_last_url_read: self.browser_monitor.last_url_read_at.map(|t| t.elapsed().as_secs()),
+            partial_access_healthy: self.partial_access.running.load(Ordering::SeqCst)
+                && self.partial_access.healthy.load(Ordering::SeqCst),
+            blacklist_size: self.browser_monitor.api_blacklist.len(),
+            outside_working_hours: !self.working_hours.is_active(Local::now()),
+            blocked_since_last_heartbeat: totals.0.saturating_sub(blocked),
+            suspicious_since_last_heartbeat: totals.1.saturating_sub(suspicious),
+            dialogs_closed_since_last_heartbeat: totals.2.saturating_sub(dialogs),
+        }
+    }
+
+    /// Network details for heartbeats, when reporting them is enabled.
+    fn network_context(&self) -> Option<NetworkContext> {
+        self.report_network_context.then(|| network_context(&self.corporate_subnets))
+    }
+
+    fn update_status(&self) {
+        let dialogs_closed = self.partial_access.stats.lock_recover().dialogs_closed;
+        let (sites_accepted, sites_rejected) = {
+            let config = self.partial_access.config.lock_recover();
+            (config.sites_accepted, config.sites_rejected)
+        };
+        let mut status = self.status.lock_recover();
+        status.uptime_secs = self.started_at.elapsed().as_secs();
+        status.current_app = self.app_tracker.current_app.clone();
+        status.current_url = Some(self.browser_monitor.last_url.clone()).filter(|u| !u.is_empty());
+        status.blocked_count = self.browser_monitor.blocked_count;
+        status.suspicious_count = self.browser_monitor.suspicious_count;
+        status.dialogs_closed = dialogs_closed;
+        status.partial_access_sites_accepted = sites_accepted;
+        status.partial_access_sites_rejected = sites_rejected;
+        status.partial_access_healthy = self.partial_access.running.load(Ordering::SeqCst)
+            && self.partial_access.healthy.load(Ordering::SeqCst);
+        status.paused = self.paused.load(Ordering::SeqCst);
+        status.outside_working_hours = !self.working_hours.is_active(Local::now());
+    }
+
+    /// Closes out the previous day's sessions, archives its totals and starts counting from zero.
+    fn rollover_day(&mut self, today: NaiveDate) {
+        let previous_day = self.current_day;
+        println!("\n[{}] Date changed to {}. Resetting daily totals...", Local::now().format("%H:%M:%S"), today);
+
+        self.app_tracker.flush_current_session();
+        self.browser_monitor.update_timing(None);
+
+        let app_data = self.app_tracker.reset_daily_totals(today);
+        let url_times = self.browser_monitor.reset_daily_totals();
+        archive_day(previous_day, &app_data, &url_times);
+        retention::sweep(self.retention_days, self.retention_max_dir_bytes);
+
+        self.current_day = today;
+    }
+
+    /// The interval stretched or shrunk by a random amount within schedule_jitter.
+    fn jittered(&mut self, interval: Duration) -> Duration {
+        if self.schedule_jitter <= 0.0 {
+            return interval;
+        }
+        let mut x = self.jitter_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.jitter_state = x;
+
+        let unit = (x >> 11) as f64 / (1u64 << 53) as f64;
+        interval.mul_f64(1.0 + self.schedule_jitter * (2.0 * unit - 1.0))
+    }
+
+    /// Closes out in-progress sessions and performs one last upload before the monitor exits.
+    async fn final_sync(&mut self) {
+        println!("\n[{}] Shutting down, performing final sync...", Local::now().format("%H:%M:%S"));
+        self.partial_access.stop_monitoring();
+
+        self.app_tracker.flush_current_session();
+        self.browser_monitor.update_timing(None);
+
+        // App usage totals are persisted to disk, so a failed upload here is resent on next start
+        let app_data = self.app_tracker.get_app_data_for_api();
+        if !self.api_client.upload_app_usage(app_data).await.kept() {
+            println!("[WARN] Final app usage upload failed");
+        }
+
+        let url_data = self.browser_monitor.take_url_data_for_api();
+        let url_count = url_data.urls.len();
+        if !self.api_client.upload_urls(url_data).await.kept() {
+            println!("[WARN] Final URL upload failed, {} URLs were not sent", url_count);
+        }
+
+        self.api_client.upload_logs(std::path::Path::new("logs/app_timelog.log"), false).await;
+
+        println!("[{}] Final sync complete.", Local::now().format("%H:%M:%S"));
+    }
+
+    fn apply_monitor_config(&mut self, config: &serde_json::Value) {
+        if let Some(mode) = config.get("urlFilterMode").and_then(|v| v.as_str()) {
+            self.browser_monitor.update_mode(FilterMode::from_config(mode));
+        }
+        if let Some(level) = config.get("urlPrivacy").and_then(|v| v.as_str()) {
+            let level = UrlPrivacy::from_config(level);
+            if self.browser_monitor.url_privacy != level {
+                println!("[INFO] URL privacy level changed: {:?} -> {:?}", self.browser_monitor.url_privacy, level);
+            }
+            self.browser_monitor.url_privacy = level;
+        }
+        if let Some(salt) = config.get("urlHashSalt").and_then(|v| v.as_str()) {
+            self.browser_monitor.url_hash_salt = salt.to_string();
+        }
+        self.partial_access.set_url_privacy(self.browser_monitor.url_privacy, &self.browser_monitor.url_hash_salt);
+        self.app_tracker.url_privacy = self.browser_monitor.url_privacy;
+        if let Some(action) = config.get("blockAction").and_then(|v| v.as_str()) {
+            self.browser_monitor.block_action = BlockAction::from_config(action);
+        }
+        if let Some(page) = config.get("blockPageUrl").and_then(|v| v.as_str()) {
+            let page = page.trim();
+            self.browser_monitor.block_page_url = (!page.is_empty()).then(|| page.to_string());
+        }
+        if let Some(mode) = config.get("enforcementMode").and_then(|v| v.as_str()) {
+            let mode = EnforcementMode::from_config(mode);
+            if self.browser_monitor.enforcement_mode != mode {
+                println!("[INFO] Enforcement mode changed: {:?} -> {:?}", self.browser_monitor.enforcement_mode, mode);
+            }
+            self.browser_monitor.enforcement_mode = mode;
+            self.partial_access.set_enforcement_mode(mode);
+        }
+        if let Some(capture) = config.get("captureScreenshots").and_then(|v| v.as_bool()) {
+            let upload = config.get("uploadScreenshots").and_then(|v| v.as_bool()).unwrap_or(false);
+            screenshot::set_enabled(capture, upload);
+        }
+        if let Some(force_kill) = config.get("forceKillOnBlock").and_then(|v| v.as_bool()) {
+            self.browser_monitor.force_kill_on_block = force_kill;
+        }
+        if let Some(notify) = config.get("showBlockNotifications").and_then(|v| v.as_bool()) {
+            self.browser_monitor.show_notifications = notify;
+        }
+        if let Some(throttle) = config.get("alertThrottleSecs").and_then(|v| v.as_f64())
+            && throttle >= 0.0 {
+            self.browser_monitor.alert_throttle_secs = throttle;
+        }
+        if let Some(close) = config.get("closeUnreadableFullscreen").and_then(|v| v.as_bool()) {
+            self.browser_monitor.close_unreadable_fullscreen = close;
+        }
+        if let Some(names) = config.get("browserNames").and_then(|v| v.as_array()) {
+            let names: Vec<String> = names.iter()
+                .filter_map(|n| n.as_str())
+                .map(|n| n.trim().to_lowercase())
+                .filter(|n| !n.is_empty())
+                .collect();
+            if !names.is_empty() {
+                self.browser_monitor.browser_names = names;
+            }
+        }
+        if let Some(days) = config.get("retentionDays").and_then(|v| v.as_u64()) {
+            self.retention_days = days;
+        }
+        if let Some(mb) = config.get("retentionMaxDirMb").and_then(|v| v.as_u64()) {
+            self.retention_max_dir_bytes = mb * 1024 * 1024;
+        }
+        if let Some(ms) = config.get("urlCheckIntervalMs").and_then(|v| v.as_u64()) {
+            // Below this the UI Automation walk of every browser window would hog a core
+            if ms >= 100 {
+                self.url_check_interval = Duration::from_millis(ms);
+            }
+        }
+        if let Some(secs) = config.get("appSampleIntervalSecs").and_then(|v| v.as_u64())
+            && secs > 0 {
+            self.app_sample_interval = Duration::from_secs(secs);
+        }
+        if let Some(jitter) = config.get("scheduleJitterFraction").and_then(|v| v.as_f64())
+            && (0.0..1.0).contains(&jitter) {
+            self.schedule_jitter = jitter;
+        }
+        if let Some(batch) = config.get("batchUploads").and_then(|v| v.as_bool()) {
+            self.batch_uploads = batch;
+        }
+        if let Some(report) = config.get("reportNetworkContext").and_then(|v| v.as_bool()) {
+            self.report_network_context = report;
+        }
+        if let Some(subnets) = config.get("corporateSubnets").and_then(|v| v.as_array()) {
+            self.corporate_subnets = subnets.iter()
+                .filter_map(|s| s.as_str())
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(processes) = config.get("anonymizerProcesses").and_then(|v| v.as_array()) {
+            self.anonymizer_processes = processes.iter()
+                .filter_map(|s| s.as_str())
+                .map(|s| s.trim().to_lowercase().trim_end_matches(".exe").to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(proxies) = config.get("allowedProxyServers").and_then(|v| v.as_array()) {
+            self.allowed_proxy_servers = proxies.iter()
+                .filter_map(|s| s.as_str())
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(hours) = config.get("workingHours").and_then(WorkingHours::from_config) {
+            if hours.is_restricted() {
+                println!("[INFO] Monitoring limited to working hours: {:?}", hours);
+            }
+            self.working_hours = hours;
+        }
+        if let Some(block) = config.get("blockBrowsingOnAnonymizer").and_then(|v| v.as_bool()) {
+            self.browser_monitor.block_on_anonymizer = block;
+        }
+        if let Some(secs) = config.get("anonymizerCheckIntervalSecs").and_then(|v| v.as_u64()) {
+            self.anonymizer_check_interval = Duration::from_secs(secs);
+        }
+        if let Some(cap) = config.get("urlHistoryCap").and_then(|v| v.as_u64()) {
+            self.browser_monitor.url_history_cap = (cap as usize).max(1);
+        }
+        if let Some(categories) = config.get("blockedCategories").and_then(|v| v.as_array()) {
+            let categories = categories.iter().filter_map(|c| c.as_str()).map(str::to_string).collect();
+            self.browser_monitor.update_blocked_categories(categories);
+        }
+        if let Some(patterns) = config.get("sensitiveUrlPatterns").and_then(|v| v.as_array()) {
+            let patterns = patterns.iter().filter_map(|p| p.as_str()).map(str::to_string).collect();
+            self.browser_monitor.update_sensitive_patterns(patterns);
+        }
+        if let Some(patterns) = config.get("suspiciousUrlPatterns").and_then(|v| v.as_array()) {
+            let patterns = patterns.iter().filter_map(|p| p.as_str()).map(str::to_string).collect();
+            self.browser_monitor.update_suspicious_patterns(patterns);
+        }
+        if let Some(dwell) = config.get("sensitiveMinDwellSecs").and_then(|v| v.as_f64())
+            && dwell >= 0.0 {
+            self.browser_monitor.sensitive_min_dwell_secs = dwell;
+        }
+        if let Some(budgets) = config.get("domainBudgetMinutes").and_then(|v| v.as_object()) {
+            let budgets = budgets.iter()
+                .filter_map(|(domain, minutes)| minutes.as_f64().map(|m| (domain.clone(), m * 60.0)))
+                .collect();
+            self.browser_monitor.update_domain_budgets(budgets);
+        }
+        if let Some(limits) = config.get("appLimitMinutes").and_then(|v| v.as_object()) {
+            let limits = limits.iter()
+                .filter_map(|(app, minutes)| minutes.as_f64().map(|m| (app.clone(), m * 60.0)))
+                .collect();
+            self.app_tracker.update_app_limits(limits);
+        }
+        if let Some(verify) = config.get("verifyIgnoredApps").and_then(|v| v.as_bool()) {
+            self.app_tracker.verify_ignored_apps = verify;
+        }
+        if let Some(categories) = config.get("appCategories").and_then(|v| v.as_object()) {
+            let categories = categories.iter()
+                .filter_map(|(app, category)| category.as_str().map(|c| (app.clone(), c.to_string())))
+                .collect();
+            self.app_tracker.update_category_overrides(categories);
+        }
+        for (key, interval) in [
+            ("syncIntervalSecs", &mut self.sync_interval),
+            ("configUpdateIntervalSecs", &mut self.config_update_interval),
+            ("heartbeatIntervalSecs", &mut self.heartbeat_interval),
+        ] {
+            if let Some(secs) = config.get(key).and_then(|v| v.as_u64())
+                && secs > 0 {
+                *interval = Duration::from_secs(secs);
+            }
+        }
+        if let Some(threshold) = config.get("idleThresholdSecs").and_then(|v| v.as_f64())
+            && threshold > 0.0 {
+            self.app_tracker.idle_threshold_secs = threshold;
+        }
+        if let Some(minimum) = config.get("minimumAppTimeSecs").and_then(|v| v.as_f64())
+            && minimum >= 0.0 {
+            self.app_tracker.minimum_app_time_secs = minimum;
+        }
+        if let Some(checkpoint) = config.get("sessionCheckpointSecs").and_then(|v| v.as_f64())
+            && checkpoint > 0.0 {
+            self.app_tracker.session_checkpoint_secs = checkpoint;
+        }
+        if let Some(count) = config.get("topAppsCount").and_then(|v| v.as_u64()) {
+            self.app_tracker.top_apps_count = count as usize;
+        }
+        if let Some(secs) = config.get("fullAppExportIntervalSecs").and_then(|v| v.as_u64()) {
+            self.full_app_export_interval = Duration::from_secs(secs);
+        }
+        if let Some(alpha) = config.get("engagementEmaAlpha").and_then(|v| v.as_f64())
+            && alpha > 0.0 && alpha <= 1.0 {
+            self.app_tracker.engagement_ema_alpha = alpha;
+        }
+    }
+}
+
+fn archive_day(day: NaiveDate, app_data: &AppData, url_times: &HashMap<String, f64>) {
+    let archive = serde_json::json!({
+        "date": day.to_string(),
+        "appUsage": app_data,
+        "urlTimes": url_times,
+    });
+    let path = format!("{}/{}.json", DAILY_ARCHIVE_DIR, day.format("%Y-%m-%d"));
+
+    match std::fs::create_dir_all(DAILY_ARCHIVE_DIR).and_then(|_| std::fs::write(&path, archive.to_string())) {
+        Ok(_) => println!("[INFO] Archived totals for {} to {}", day, path),
+        Err(e) => println!("[ERROR] Failed to archive totals for {}: {}", day, e),
+    }
+}
+
+/// Non-zero xorshift seed derived from the device id.
+fn device_jitter_seed() -> u64 {
+    let mut hasher = DefaultHasher::new();
+    get_device_id().hash(&mut hasher);
+    hasher.finish() | 1
 }
@@ -0,0 +1,107 @@
+use std::net::{IpAddr, ToSocketAddrs, UdpSocket};
+#[cfg(windows)]
+use windows::Win32::Foundation::HANDLE;
+#[cfg(windows)]
+use windows::Win32::NetworkManagement::WiFi::{
+    wlan_interface_state_connected, wlan_intf_opcode_current_connection, WlanCloseHandle, WlanEnumInterfaces,
+    WlanFreeMemory, WlanOpenHandle, WlanQueryInterface, WLAN_CONNECTION_ATTRIBUTES, WLAN_INTERFACE_INFO_LIST,
+};
+
+use crate::config::api_config::get_api_base_url;
+use crate::config::client::NetworkContext;
+
+/// Where and how the device is connected, for heartbeats. Each part is None when it can't be
+/// determined (wired connection, no network, WLAN service stopped); `on_corporate_network` is
+/// also None when no corporate subnets are configured.
+pub fn network_context(corporate_subnets: &[String]) -> NetworkContext {
+    let local_ip = local_ip();
+    let on_corporate_network = match local_ip {
+        Some(ip) if !corporate_subnets.is_empty() => Some(corporate_subnets.iter().any(|s| in_subnet(ip, s))),
+        _ => None,
+    };
+
+    NetworkContext {
+        ssid: current_ssid(),
+        local_ip: local_ip.map(|ip| ip.to_string()),
+        on_corporate_network,
+    }
+}
+
+/// Address of the interface used to reach the API server. Connecting a UDP socket only picks
+/// the route; nothing is sent.
+fn local_ip() -> Option<IpAddr> {
+    let parsed = url::Url::parse(&get_api_base_url()).ok()?;
+    let host = parsed.host_str()?.trim_matches(['[', ']']);
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    let server = (host, port).to_socket_addrs().ok()?.next()?;
+
+    let bind_addr = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind_addr).ok()?;
+    socket.connect(server).ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+/// SSID of the first connected wireless interface.
+#[cfg(windows)]
+fn current_ssid() -> Option<String> {
+    unsafe {
+        let mut negotiated_version = 0u32;
+        let mut handle = HANDLE::default();
+        if WlanOpenHandle(2, None, &mut negotiated_version, &mut handle) != 0 {
+            return None;
+        }
+
+        let mut ssid = None;
+        let mut list: *mut WLAN_INTERFACE_INFO_LIST = std::ptr::null_mut();
+        if WlanEnumInterfaces(handle, None, &mut list) == 0 && !list.is_null() {
+            let interfaces = std::slice::from_raw_parts((*list).InterfaceInfo.as_ptr(), (*list).dwNumberOfItems as usize);
+            for interface in interfaces.iter().filter(|i| i.isState == wlan_interface_state_connected) {
+                let mut size = 0u32;
+                let mut data: *mut std::ffi::c_void = std::ptr::null_mut();
+                let status = WlanQueryInterface(handle, &interface.InterfaceGuid, wlan_intf_opcode_current_connection,
+                    None, &mut size, &mut data, None);
+                if status != 0 || data.is_null() {
+                    continue;
+                }
+
+                let attributes = &*(data as *const WLAN_CONNECTION_ATTRIBUTES);
+                let raw = attributes.wlanAssociationAttributes.dot11Ssid;
+                let len = (raw.uSSIDLength as usize).min(raw.ucSSID.len());
+                ssid = Some(String::from_utf8_lossy(&raw.ucSSID[..len]).to_string());
+                WlanFreeMemory(data);
+                break;
+            }
+            WlanFreeMemory(list as *const std::ffi::c_void);
+        }
+
+        WlanCloseHandle(handle, None);
+        ssid.filter(|s: &String| !s.is_empty())
+    }
+}
+
+/// Only the Windows WLAN service is queried for now.
+#[cfg(not(windows))]
+fn current_ssid() -> Option<String> {
+    None
+}
+
+/// True if `ip` is inside `cidr` ("10.20.0.0/16", "fd00::/8"). A bare address matches only itself.
+fn in_subnet(ip: IpAddr, cidr: &str) -> bool {
+    let (network, prefix) = match cidr.trim().split_once('/') {
+        Some((network, prefix)) => (network, prefix.parse::<u32>().ok()),
+        None => (cidr.trim(), None),
+    };
+    match (ip, network.parse::<IpAddr>()) {
+        (IpAddr::V4(ip), Ok(IpAddr::V4(network))) => {
+            let prefix = prefix.unwrap_or(32).min(32);
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), Ok(IpAddr::V6(network))) => {
+            let prefix = prefix.unwrap_or(128).min(128);
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
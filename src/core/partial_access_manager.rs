@@ -1,20 +1,58 @@
+// Dialogs and the clipboard are only watched through Win32; elsewhere the config is still
+// kept up to date but most of it is never read
+#![cfg_attr(not(windows), allow(dead_code))]
+
+use std::panic::AssertUnwindSafe;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
-use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetClassNameW, GetWindowTextW, SendMessageW, WM_CLOSE};
-use windows::Win32::Foundation::{LPARAM, WPARAM, HWND};
-use serde::Deserialize;
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::{
+    EnumChildWindows, GetForegroundWindow, GetClassNameW, GetWindow, GetWindowTextW, GetWindowThreadProcessId, PostMessageW, SendMessageTimeoutW,
+    GW_OWNER, IDCANCEL, SMTO_ABORTIFHUNG, WM_CLOSE, WM_COMMAND, WM_GETTEXT,
+};
+#[cfg(windows)]
+use windows::Win32::Foundation::{BOOL, LPARAM, WPARAM, HWND};
+#[cfg(windows)]
+use windows::Win32::System::DataExchange::{CloseClipboard, EmptyClipboard, GetClipboardOwner, GetClipboardSequenceNumber, OpenClipboard};
+use serde::{Deserialize, Serialize};
+
+#[cfg(windows)]
+use crate::config::client::AccessAttemptKind;
+use crate::config::settings::{ESCALATE_DIALOG_CLOSE, PARTIAL_ACCESS_MAX_REJECTED_RATIO, PARTIAL_ACCESS_RESTART_SECS};
+#[cfg(windows)]
+use crate::config::settings::{DIALOG_KILL_AFTER_ATTEMPTS, DIALOG_REPEAT_WINDOW_SECS};
+use crate::core::audit::audit_event;
+use crate::core::browser_monitor::{EnforcementMode, UrlPrivacy};
+use crate::core::lock::LockRecover;
+use crate::core::url_utils::extract_domain;
+#[cfg(windows)]
+use crate::core::window_inspector::{process_exe_name, UiaWindowInspector, WindowInspector};
+
+const DEFAULT_DIALOG_CLASSES: [&str; 3] = ["#32770", "FileChooserDialogClass", "NativeHWNDHost"];
+const DEFAULT_UPLOAD_KEYWORDS: [&str; 4] = ["open", "upload", "select file", "choose file"];
+const DEFAULT_DOWNLOAD_KEYWORDS: [&str; 2] = ["save", "download"];
+const DEFAULT_ALLOWED_DIALOG_PROCESSES: [&str; 4] = ["winword.exe", "excel.exe", "powerpnt.exe", "outlook.exe"];
+const PARTIAL_ACCESS_STATS_FILE: &str = "data/partial_access_stats.json";
 
 pub struct PartialAccessManager {
-    pub running: bool,
+    /// Cleared by stop_monitoring to end the monitoring thread
+    pub running: Arc<AtomicBool>,
+    monitor_thread: Option<JoinHandle<()>>,
     pub stats: Arc<Mutex<PartialAccessStats>>,
     pub config: Arc<Mutex<PartialAccessConfig>>,
     pub context: Arc<Mutex<PartialAccessContext>>,
+    /// False while the monitoring thread is recovering from a panic
+    pub healthy: Arc<AtomicBool>,
 }
 
 #[derive(Clone)]
 pub struct PartialAccessContext {
     pub current_url: String,
     pub current_domain: String,
+    /// Browser window showing current_url, if known
+    pub browser_hwnd: Option<isize>,
 }
 
 #[derive(PartialEq)]
@@ -24,14 +62,60 @@ pub enum DialogType {
     Download,
 }
 
+/// Enforcement totals, persisted to data/partial_access_stats.json so they survive restarts
+#[derive(Serialize, Deserialize, Default)]
 pub struct PartialAccessStats {
+    #[serde(default)]
     pub dialogs_closed: u32,
+    #[serde(default)]
+    pub clipboard_cleared: u32,
+}
+
+impl PartialAccessStats {
+    /// Loads the saved totals, starting from zero if the file is missing or corrupt.
+    pub fn load() -> Self {
+        let content = match std::fs::read_to_string(PARTIAL_ACCESS_STATS_FILE) {
+            Ok(c) => c,
+            Err(_) => return Self::default(),
+        };
+        match serde_json::from_str::<PartialAccessStats>(&content) {
+            Ok(stats) => {
+                println!("[INFO] Loaded partial access stats: {} dialogs closed, {} clipboard copies cleared",
+                    stats.dialogs_closed, stats.clipboard_cleared);
+                stats
+            }
+            Err(e) => {
+                println!("[WARN] Corrupt partial access stats in {} ({}). Starting fresh.", PARTIAL_ACCESS_STATS_FILE, e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let json = match serde_json::to_string(self) {
+            Ok(j) => j,
+            Err(e) => {
+                println!("[ERROR] Failed to serialize partial access stats: {}", e);
+                return;
+            }
+        };
+        let tmp_path = format!("{}.tmp", PARTIAL_ACCESS_STATS_FILE);
+        if let Err(e) = std::fs::write(&tmp_path, json).and_then(|_| std::fs::rename(&tmp_path, PARTIAL_ACCESS_STATS_FILE)) {
+            println!("[ERROR] Failed to save partial access stats: {}", e);
+        }
+    }
 }
 
 #[derive(Clone, Deserialize)]
 pub struct PartialAccessSite {
+    /// Substring of the URL, or a regex when prefixed with "re:" or urlPatternIsRegex is set
     #[serde(rename = "urlPattern")]
     pub url_pattern: String,
+    #[serde(rename = "urlPatternIsRegex", default)]
+    pub url_pattern_is_regex: bool,
+    /// Compiled form of a regex url_pattern, built by compile_pattern
+    #[serde(skip)]
+    url_regex: Option<regex::Regex>,
     #[serde(rename = "allowUpload")]
     pub allow_upload: bool,
     #[serde(rename = "allowDownload")]
@@ -39,182 +123,731 @@ pub struct PartialAccessSite {
     #[serde(rename = "monitorMode")]
     pub monitor_mode: String,
     pub active: bool,
+    /// When set, only files with these extensions may pass a blocked dialog (e.g. ["pdf"])
+    #[serde(rename = "allowedExtensions", default)]
+    pub allowed_extensions: Vec<String>,
+    /// Extensions that always get the dialog closed (e.g. ["exe", "zip"])
+    #[serde(rename = "blockedExtensions", default)]
+    pub blocked_extensions: Vec<String>,
+    /// Clear anything copied to the clipboard while this site is open
+    #[serde(rename = "blockClipboard", default)]
+    pub block_clipboard: bool,
+}
+
+impl PartialAccessSite {
+    fn is_regex_pattern(&self) -> bool {
+        self.url_pattern_is_regex || self.url_pattern.starts_with("re:")
+    }
+
+    /// Compiles a regex url_pattern once so dialog checks don't rebuild it.
+    fn compile_pattern(&mut self) {
+        if !self.is_regex_pattern() {
+            return;
+        }
+        let pattern = self.url_pattern.strip_prefix("re:").unwrap_or(&self.url_pattern);
+        match regex::Regex::new(&format!("(?i){}", pattern)) {
+            Ok(re) => self.url_regex = Some(re),
+            Err(e) => println!("[ERROR] Invalid partial access URL regex '{}': {}", pattern, e),
+        }
+    }
+
+    /// Regex patterns match anywhere in the URL, plain ones as a case-insensitive substring.
+    /// A regex that failed to compile matches nothing.
+    fn matches_url(&self, url: &str) -> bool {
+        if self.is_regex_pattern() {
+            self.url_regex.as_ref().is_some_and(|re| re.is_match(url))
+        } else {
+            url.to_lowercase().contains(&self.url_pattern.to_lowercase())
+        }
+    }
+
+    fn has_extension_rules(&self) -> bool {
+        !self.allowed_extensions.is_empty() || !self.blocked_extensions.is_empty()
+    }
+
+    /// True if the extension is explicitly blocked or missing from a non-empty allow list.
+    fn blocks_extension(&self, extension: &str) -> bool {
+        let listed = |list: &[String]| list.iter().any(|e| normalize_extension(e) == extension);
+        listed(&self.blocked_extensions)
+            || (!self.allowed_extensions.is_empty() && !listed(&self.allowed_extensions))
+    }
 }
 
 #[derive(Clone)]
 pub struct PartialAccessConfig {
     pub enabled: bool,
     pub sites: Vec<PartialAccessSite>,
+    /// In Observe mode matching dialogs are reported but left open
+    pub enforcement_mode: EnforcementMode,
+    /// Window classes treated as file dialogs
+    pub dialog_classes: Vec<String>,
+    /// Lowercase title keywords marking upload and download dialogs; the server can push
+    /// localized lists (e.g. "öffnen", "speichern") for non-English Windows installs
+    pub upload_keywords: Vec<String>,
+    pub download_keywords: Vec<String>,
+    /// Lowercase executable names (e.g. "winword.exe") whose dialogs are never blocked
+    pub allowed_dialog_processes: Vec<String>,
+    /// Cancel a dialog that survives WM_CLOSE, close its owner, and finally kill its process
+    pub escalate_dialog_close: bool,
+    /// How much of the site URL access attempts may report, and the salt for hashing it
+    pub url_privacy: UrlPrivacy,
+    pub url_hash_salt: String,
+    /// Sites in the last pushed list that parsed, and those that didn't
+    pub sites_accepted: usize,
+    pub sites_rejected: usize,
 }
 
 impl PartialAccessManager {
     pub fn new() -> Self {
         PartialAccessManager {
-            running: false,
-            stats: Arc::new(Mutex::new(PartialAccessStats {
-                dialogs_closed: 0,
-            })),
+            running: Arc::new(AtomicBool::new(false)),
+            monitor_thread: None,
+            stats: Arc::new(Mutex::new(PartialAccessStats::load())),
             config: Arc::new(Mutex::new(PartialAccessConfig {
                 enabled: true,
                 sites: Vec::new(),
+                enforcement_mode: EnforcementMode::Enforce,
+                dialog_classes: to_strings(&DEFAULT_DIALOG_CLASSES),
+                upload_keywords: to_strings(&DEFAULT_UPLOAD_KEYWORDS),
+                download_keywords: to_strings(&DEFAULT_DOWNLOAD_KEYWORDS),
+                allowed_dialog_processes: to_strings(&DEFAULT_ALLOWED_DIALOG_PROCESSES),
+                escalate_dialog_close: ESCALATE_DIALOG_CLOSE,
+                url_privacy: UrlPrivacy::Full,
+                url_hash_salt: String::new(),
+                sites_accepted: 0,
+                sites_rejected: 0,
             })),
             context: Arc::new(Mutex::new(PartialAccessContext {
                 current_url: String::new(),
                 current_domain: String::new(),
+                browser_hwnd: None,
             })),
+            healthy: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    pub fn start_monitoring(&mut self, api_client: Arc<crate::config::client::APIClient>) {
-        self.running = true;
+    /// Current totals for upload.
+    pub fn get_stats_for_api(&self) -> crate::config::client::PartialAccessStatsData {
+        let stats = self.stats.lock_recover();
+        crate::config::client::PartialAccessStatsData {
+            device_id: crate::config::api_config::get_device_id(),
+            timestamp: chrono::Local::now().to_rfc3339(),
+            dialogs_closed: stats.dialogs_closed,
+            clipboard_cleared: stats.clipboard_cleared,
+        }
+    }
+
+    /// Records the browser URL the user is on and the window showing it, used to decide which
+    /// site rules apply to dialogs and whether a dialog belongs to that browser.
+    pub fn update_context(&self, url: &str, browser_hwnd: Option<isize>) {
+        let mut ctx = self.context.lock_recover();
+        ctx.current_url = url.to_string();
+        ctx.current_domain = extract_domain(url);
+        ctx.browser_hwnd = browser_hwnd;
+    }
+
+    /// True if the current browser URL matches an active partial-access site.
+    pub fn restricted_site_open(&self) -> bool {
+        let url = self.context.lock_recover().current_url.clone();
+        !url.is_empty() && self.config.lock_recover().sites.iter().any(|s| s.active && s.matches_url(&url))
+    }
+
+    /// Starts the dialog-watching thread. No dialogs are closed while `paused` is set.
+    pub fn start_monitoring(&mut self, api_client: Arc<crate::config::client::APIClient>, paused: Arc<AtomicBool>) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let running = self.running.clone();
         let stats = self.stats.clone();
         let config = self.config.clone();
         let context = self.context.clone();
+        let healthy = self.healthy.clone();
+        healthy.store(true, Ordering::SeqCst);
         
-        // Monitoring thread
-        std::thread::spawn(move || {
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
-
-            let mut last_blocked_hwnd: Option<HWND> = None;
-            let mut last_blocked_time = Instant::now();
-
-            loop {
-                let current_config = {
-                    let c = config.lock().unwrap();
-                    c.clone()
-                };
-
-                if current_config.enabled {
-                    // Check if current URL has specific config
-                    let ctx = {
-                        let c = context.lock().unwrap();
-                        c.clone()
-                    };
+        // Monitoring thread. A panic (e.g. an unexpected Win32 result) would otherwise end
+        // dialog blocking silently, so it is caught, reported and the loop restarted.
+        self.monitor_thread = Some(std::thread::spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    watch_dialogs(&running, &stats, &config, &context, &api_client, &paused);
+                }));
+                let Err(panic) = result else { break };
 
-                    let site_config = current_config.sites.iter().find(|s| {
-                        s.active && ctx.current_url.to_lowercase().contains(&s.url_pattern.to_lowercase())
-                    });
+                healthy.store(false, Ordering::SeqCst);
+                let message = panic.downcast_ref::<&str>().map(|m| m.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                println!("[ERROR] Partial access monitoring crashed: {}. Restarting in {}s.", message, PARTIAL_ACCESS_RESTART_SECS);
+                audit_event("partial_access_restart", serde_json::json!({ "error": message }));
 
-                    if let Some(site) = site_config {
-                        if let Some(hwnd) = unsafe { 
-                            let h = GetForegroundWindow();
-                            if h.0 != 0 { Some(h) } else { None }
-                        } {
-                            // Avoid repetitive blocking/logging for the same window within a short period
-                            if Some(hwnd) == last_blocked_hwnd && last_blocked_time.elapsed() < Duration::from_secs(2) {
-                                std::thread::sleep(Duration::from_millis(200));
-                                continue;
-                            }
-
-                            let mut class_name = [0u16; 256];
-                            let mut title = [0u16; 256];
-                            
-                            unsafe {
-                                GetClassNameW(hwnd, &mut class_name);
-                                let len = GetWindowTextW(hwnd, &mut title);
-                                if len == 0 {
-                                    std::thread::sleep(Duration::from_millis(150));
-                                    continue;
-                                }
-                            }
-                            
-                            let class_name_str = String::from_utf16_lossy(&class_name).trim_matches('\0').to_string();
-                            let title_str = String::from_utf16_lossy(&title).trim_matches('\0').to_string();
-                            
-                            let dialog_type = get_dialog_type(&class_name_str, &title_str, site);
-                            if dialog_type != DialogType::None {
-                                println!("[INFO] Blocking partial-access dialog: {} ({}) for site: {}", 
-                                    title_str, class_name_str, site.url_pattern);
-                                
-                                // Use PostMessageW to be non-blocking and more likely to succeed for dialogs
-                                unsafe { 
-                                    windows::Win32::UI::WindowsAndMessaging::PostMessageW(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0)) 
-                                };
-                                
-                                last_blocked_hwnd = Some(hwnd);
-                                last_blocked_time = Instant::now();
-                                
-                                let mut s = stats.lock().unwrap();
-                                s.dialogs_closed += 1;
-
-                                // Report attempt
-                                let attempt_data = crate::config::client::AccessAttemptData {
-                                    url: ctx.current_url.clone(),
-                                    domain: ctx.current_domain.clone(),
-                                    file_type: "Unknown".to_string(),
-                                    blocked: true,
-                                    monitor_mode: site.monitor_mode.clone(),
-                                };
-
-                                let api = api_client.clone();
-                                let is_upload = dialog_type == DialogType::Upload;
-                                rt.block_on(async move {
-                                    api.record_access_attempt(attempt_data, is_upload).await;
-                                });
-                            }
-                        }
-                    }
+                let deadline = Instant::now() + Duration::from_secs(PARTIAL_ACCESS_RESTART_SECS);
+                while running.load(Ordering::SeqCst) && Instant::now() < deadline {
+                    std::thread::sleep(Duration::from_millis(200));
                 }
-                std::thread::sleep(Duration::from_millis(200));
+                healthy.store(true, Ordering::SeqCst);
             }
-        });
+            println!("[INFO] Partial access monitoring stopped.");
+        }));
+    }
+
+    /// Signals the monitoring thread to exit and waits for it to finish its current check.
+    pub fn stop_monitoring(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.monitor_thread.take() {
+            let _ = handle.join();
+        }
+    }
+
+    pub fn set_enforcement_mode(&self, mode: EnforcementMode) {
+        self.config.lock_recover().enforcement_mode = mode;
+    }
+
+    pub fn set_url_privacy(&self, level: UrlPrivacy, salt: &str) {
+        let mut config = self.config.lock_recover();
+        config.url_privacy = level;
+        config.url_hash_salt = salt.to_string();
     }
 
     pub async fn update_config(&self, api_client: &crate::config::client::APIClient) {
-        if let Some(new_config_val) = api_client.get_partial_access_config().await {
-            let mut config = self.config.lock().unwrap();
+        if let Some(new_config_val) = api_client.get_partial_access_config_conditional().await {
+            let mut config = self.config.lock_recover();
             
             // The backend might send 'enabled' or 'success'
             if let Some(enabled) = new_config_val.get("enabled").and_then(|v| v.as_bool())
                 .or_else(|| new_config_val.get("active").and_then(|v| v.as_bool())) {
                 config.enabled = enabled;
             }
+            if let Some(escalate) = new_config_val.get("escalateDialogClose").and_then(|v| v.as_bool()) {
+                config.escalate_dialog_close = escalate;
+            }
 
-            if let Some(sites_array) = new_config_val.get("partialAccessSites").and_then(|v| v.as_array()) {
-                let sites: Vec<PartialAccessSite> = sites_array.iter()
-                    .filter_map(|s| serde_json::from_value(s.clone()).ok())
-                    .collect();
-                
-                println!("Updated partial access config: {} sites received", sites.len());
-                config.sites = sites;
-            } else if let Some(sites_array) = new_config_val.get("sites").and_then(|v| v.as_array()) {
-                // Try alternate key 'sites'
-                let sites: Vec<PartialAccessSite> = sites_array.iter()
-                    .filter_map(|s| serde_json::from_value(s.clone()).ok())
-                    .collect();
-                
-                println!("Updated partial access config: {} sites received (via 'sites' key)", sites.len());
-                config.sites = sites;
+            // Missing lists fall back to the English defaults
+            config.dialog_classes = string_list(&new_config_val, "dialogClasses", false)
+                .unwrap_or_else(|| to_strings(&DEFAULT_DIALOG_CLASSES));
+            config.upload_keywords = string_list(&new_config_val, "uploadKeywords", true)
+                .unwrap_or_else(|| to_strings(&DEFAULT_UPLOAD_KEYWORDS));
+            config.download_keywords = string_list(&new_config_val, "downloadKeywords", true)
+                .unwrap_or_else(|| to_strings(&DEFAULT_DOWNLOAD_KEYWORDS));
+            config.allowed_dialog_processes = string_list(&new_config_val, "allowedDialogProcesses", true)
+                .unwrap_or_else(|| to_strings(&DEFAULT_ALLOWED_DIALOG_PROCESSES));
+
+            // Try alternate key 'sites'
+            let sites_array = new_config_val.get("partialAccessSites").and_then(|v| v.as_array())
+                .or_else(|| new_config_val.get("sites").and_then(|v| v.as_array()));
+            if let Some(sites_array) = sites_array {
+                let (sites, rejected) = parse_sites(sites_array);
+                config.sites_accepted = sites.len();
+                config.sites_rejected = rejected;
+
+                // A bad push shouldn't wipe enforcement: keep the current rules if too much of it is broken
+                if rejected > 0 && rejected as f64 > sites_array.len() as f64 * PARTIAL_ACCESS_MAX_REJECTED_RATIO {
+                    println!("[ERROR] Refusing partial access config: {} of {} sites are invalid. Keeping the current {} sites.",
+                        rejected, sites_array.len(), config.sites.len());
+                } else {
+                    println!("Updated partial access config: {} sites accepted, {} rejected", sites.len(), rejected);
+                    config.sites = sites;
+                }
             }
         }
     }
 }
 
-fn get_dialog_type(class_name: &str, title: &str, site: &PartialAccessSite) -> DialogType {
-    let dialog_classes = ["#32770", "FileChooserDialogClass", "NativeHWNDHost"];
+/// Empties the clipboard. Returns false if another program holds it open.
+#[cfg(windows)]
+fn clear_clipboard() -> bool {
+    unsafe {
+        if OpenClipboard(HWND(0)).is_err() {
+            println!("[ERROR] Could not open the clipboard to clear it");
+            return false;
+        }
+        let cleared = EmptyClipboard().is_ok();
+        let _ = CloseClipboard();
+        cleared
+    }
+}
+
+/// The dialog and clipboard watching loop run by start_monitoring, until `running` is cleared.
+#[cfg(windows)]
+fn watch_dialogs(
+    running: &AtomicBool,
+    stats: &Mutex<PartialAccessStats>,
+    config: &Mutex<PartialAccessConfig>,
+    context: &Mutex<PartialAccessContext>,
+    api_client: &Arc<crate::config::client::APIClient>,
+    paused: &AtomicBool,
+) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut last_blocked_hwnd: Option<HWND> = None;
+    let mut last_blocked_time = Instant::now();
+    // Dialogs stay open in observe mode, so report each one only once
+    let mut last_observed_hwnd: Option<HWND> = None;
+    // Class and title of the last dialog closed, how many times it has been closed, and when
+    let mut last_closed_dialog: Option<((String, String), u32, Instant)> = None;
+    let mut last_clipboard_seq = unsafe { GetClipboardSequenceNumber() };
+
+    while running.load(Ordering::SeqCst) {
+        // Tracked every pass so copies made before reaching a restricted site aren't blamed on it
+        let clipboard_seq = unsafe { GetClipboardSequenceNumber() };
+        let clipboard_changed = clipboard_seq != last_clipboard_seq;
+        last_clipboard_seq = clipboard_seq;
+
+        let current_config = {
+            let c = config.lock_recover();
+            c.clone()
+        };
+
+        if current_config.enabled && !paused.load(Ordering::SeqCst) {
+            // Check if current URL has specific config
+            let ctx = {
+                let c = context.lock_recover();
+                c.clone()
+            };
+
+            let site_config = current_config.sites.iter().find(|s| {
+                s.active && s.matches_url(&ctx.current_url)
+            });
+
+            // Copies made in other apps while the restricted page stays open are left alone
+            if let Some(site) = site_config.filter(|s| s.block_clipboard && clipboard_changed && clipboard_from_browser(ctx.browser_hwnd)) {
+                let enforce = current_config.enforcement_mode == EnforcementMode::Enforce;
+                if enforce {
+                    if clear_clipboard() {
+                        // Our own EmptyClipboard bumps the sequence number too
+                        last_clipboard_seq = unsafe { GetClipboardSequenceNumber() };
+                        let mut stats = stats.lock_recover();
+                        stats.clipboard_cleared += 1;
+                        stats.save();
+                        println!("[INFO] Cleared clipboard copy from partial-access site: {}", site.url_pattern);
+                    }
+                } else {
+                    println!("[INFO] Observe mode: would clear clipboard copy from partial-access site: {}", site.url_pattern);
+                }
+
+                audit_event("clipboard_blocked", serde_json::json!({
+                    "url": ctx.current_url,
+                    "site": site.url_pattern,
+                    "enforced": enforce,
+                }));
+                crate::core::screenshot::capture_block_event(&format!("clipboard copy on {}", ctx.current_domain));
+
+                let attempt_data = crate::config::client::AccessAttemptData {
+                    url: current_config.url_privacy.apply(&ctx.current_url, &current_config.url_hash_salt),
+                    domain: current_config.url_privacy.apply_domain(&ctx.current_domain, &current_config.url_hash_salt),
+                    file_type: "clipboard".to_string(),
+                    blocked: enforce,
+                    monitor_mode: site.monitor_mode.clone(),
+                    matched_pattern: None,
+                    dwell_secs: None,
+                };
+                let api = api_client.clone();
+                rt.block_on(async move {
+                    api.record_access_attempt(attempt_data, AccessAttemptKind::Clipboard).await;
+                });
+            }
+
+            let hwnd = unsafe { GetForegroundWindow() };
+            if let Some(site) = site_config
+                && hwnd.0 != 0 {
+                // Avoid repetitive blocking/logging for the same window within a short period
+                if Some(hwnd) == last_blocked_hwnd && last_blocked_time.elapsed() < Duration::from_secs(2) {
+                    std::thread::sleep(Duration::from_millis(200));
+                    continue;
+                }
+
+                let mut class_name = [0u16; 256];
+                let mut title = [0u16; 256];
+
+                unsafe {
+                    GetClassNameW(hwnd, &mut class_name);
+                    let len = GetWindowTextW(hwnd, &mut title);
+                    if len == 0 {
+                        std::thread::sleep(Duration::from_millis(150));
+                        continue;
+                    }
+                }
+
+                let class_name_str = String::from_utf16_lossy(&class_name).trim_matches('\0').to_string();
+                let title_str = String::from_utf16_lossy(&title).trim_matches('\0').to_string();
+
+                let mut dialog_type = get_dialog_type(&class_name_str, &title_str, site, &current_config);
+                if dialog_type != DialogType::None && !dialog_belongs_to_browser(hwnd, ctx.browser_hwnd, &current_config) {
+                    // e.g. a Save dialog in Word while the restricted site sits in the background
+                    dialog_type = DialogType::None;
+                }
+                if dialog_type != DialogType::None {
+                    let extensions = dialog_file_extensions(hwnd);
+                    let blocked_extension = extensions.iter().find(|e| site.blocks_extension(e)).cloned();
+                    if site.has_extension_rules() && blocked_extension.is_none() {
+                        // No file picked yet, or only allowed types: keep watching the dialog
+                        std::thread::sleep(Duration::from_millis(200));
+                        continue;
+                    }
+                    let file_type = blocked_extension
+                        .or_else(|| extensions.first().cloned())
+                        .unwrap_or_else(|| "Unknown".to_string());
+
+                    let enforce = current_config.enforcement_mode == EnforcementMode::Enforce;
+                    if !enforce && last_observed_hwnd == Some(hwnd) {
+                        std::thread::sleep(Duration::from_millis(200));
+                        continue;
+                    }
+
+                    if enforce {
+                        println!("[INFO] Blocking partial-access dialog: {} ({}) for site: {} (file type: {})", 
+                            title_str, class_name_str, site.url_pattern, file_type);
+
+                        let key = (class_name_str.clone(), title_str.clone());
+                        let attempts = match last_closed_dialog.take() {
+                            Some((last_key, n, at)) if last_key == key && at.elapsed() < Duration::from_secs(DIALOG_REPEAT_WINDOW_SECS) => n + 1,
+                            _ => 1,
+                        };
+                        close_dialog(hwnd, attempts, current_config.escalate_dialog_close, &title_str);
+                        last_closed_dialog = Some((key, attempts, Instant::now()));
+
+                        last_blocked_hwnd = Some(hwnd);
+                        last_blocked_time = Instant::now();
+
+                        let mut stats = stats.lock_recover();
+                        stats.dialogs_closed += 1;
+                        stats.save();
+                    } else {
+                        println!("[INFO] Observe mode: would block partial-access dialog: {} ({}) for site: {} (file type: {})", 
+                            title_str, class_name_str, site.url_pattern, file_type);
+                        last_observed_hwnd = Some(hwnd);
+                    }
+
+                    audit_event("dialog_blocked", serde_json::json!({
+                        "url": ctx.current_url,
+                        "site": site.url_pattern,
+                        "dialog": if dialog_type == DialogType::Upload { "upload" } else { "download" },
+                        "title": title_str,
+                        "file_type": file_type,
+                        "enforced": enforce,
+                    }));
+
+                    crate::core::screenshot::capture_block_event(&format!("file dialog on {}", ctx.current_domain));
+
+                    // Report attempt
+                    let attempt_data = crate::config::client::AccessAttemptData {
+                        url: current_config.url_privacy.apply(&ctx.current_url, &current_config.url_hash_salt),
+                        domain: current_config.url_privacy.apply_domain(&ctx.current_domain, &current_config.url_hash_salt),
+                        file_type,
+                        blocked: enforce,
+                        monitor_mode: site.monitor_mode.clone(),
+                        matched_pattern: None,
+                        dwell_secs: None,
+                    };
+
+                    let api = api_client.clone();
+                    let kind = if dialog_type == DialogType::Upload { AccessAttemptKind::Upload } else { AccessAttemptKind::Download };
+                    rt.block_on(async move {
+                        api.record_access_attempt(attempt_data, kind).await;
+                    });
+                }
+            }
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// Closes a blocked dialog. The first attempt posts WM_CLOSE (non-blocking and usually enough);
+/// if the same dialog keeps coming back and escalation is on, later attempts cancel it and close
+/// its owner window, and after DIALOG_KILL_AFTER_ATTEMPTS its process is terminated.
+#[cfg(windows)]
+fn close_dialog(hwnd: HWND, attempts: u32, escalate: bool, title: &str) {
+    if attempts >= 2 && !escalate {
+        println!("[WARN] Dialog '{}' survived {} close attempt(s); enable escalateDialogClose to force it", title, attempts - 1);
+    }
+
+    if !escalate || attempts < 2 {
+        unsafe {
+            let _ = PostMessageW(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0));
+        }
+        return;
+    }
+
+    if attempts >= DIALOG_KILL_AFTER_ATTEMPTS {
+        let pid = window_pid(hwnd);
+        println!("[ALERT] Dialog '{}' survived {} close attempts. Terminating process {}", title, attempts - 1, pid);
+        let terminated = UiaWindowInspector.terminate_process(hwnd.0);
+        audit_event("dialog_process_terminated", serde_json::json!({
+            "title": title,
+            "pid": pid,
+            "attempts": attempts,
+            "terminated": terminated,
+        }));
+        return;
+    }
+
+    println!("[INFO] Dialog '{}' survived WM_CLOSE. Cancelling it and closing its owner window", title);
+    unsafe {
+        let _ = PostMessageW(hwnd, WM_COMMAND, WPARAM(IDCANCEL.0 as usize), LPARAM(0));
+        let _ = PostMessageW(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0));
+        let owner = GetWindow(hwnd, GW_OWNER);
+        if owner.0 != 0 {
+            let _ = PostMessageW(owner, WM_CLOSE, WPARAM(0), LPARAM(0));
+        }
+    }
+}
+
+/// Nothing is enforced outside Windows yet, so the thread just waits to be stopped.
+#[cfg(not(windows))]
+fn watch_dialogs(
+    running: &AtomicBool,
+    _stats: &Mutex<PartialAccessStats>,
+    _config: &Mutex<PartialAccessConfig>,
+    _context: &Mutex<PartialAccessContext>,
+    _api_client: &Arc<crate::config::client::APIClient>,
+    _paused: &AtomicBool,
+) {
+    println!("[WARN] Partial access enforcement (file dialogs, clipboard) is only available on Windows.");
+    while running.load(Ordering::SeqCst) {
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+fn get_dialog_type(class_name: &str, title: &str, site: &PartialAccessSite, config: &PartialAccessConfig) -> DialogType {
     let title_lower = title.to_lowercase();
 
-    let is_dialog_class = dialog_classes.iter().any(|&c| class_name.contains(c));
+    let is_dialog_class = config.dialog_classes.iter().any(|c| class_name.contains(c.as_str()));
     if !is_dialog_class {
         return DialogType::None;
     }
 
-    if !site.allow_upload && site.monitor_mode == "block" {
-        let upload_keywords = ["open", "upload", "select file", "choose file"];
-        if upload_keywords.iter().any(|&k| title_lower.contains(k)) {
-            return DialogType::Upload;
+    let blocking = site.monitor_mode == "block";
+    if blocking && !site.allow_upload && config.upload_keywords.iter().any(|k| title_lower.contains(k.as_str())) {
+        return DialogType::Upload;
+    }
+    if blocking && !site.allow_download && config.download_keywords.iter().any(|k| title_lower.contains(k.as_str())) {
+        return DialogType::Download;
+    }
+
+    DialogType::None
+}
+
+/// Parses the pushed sites, logging each one that doesn't deserialize. Returns the valid
+/// sites and the number rejected.
+fn parse_sites(sites_array: &[serde_json::Value]) -> (Vec<PartialAccessSite>, usize) {
+    let mut sites = Vec::new();
+    let mut rejected = 0;
+    for (i, s) in sites_array.iter().enumerate() {
+        match serde_json::from_value::<PartialAccessSite>(s.clone()) {
+            Ok(mut site) => {
+                site.compile_pattern();
+                sites.push(site);
+            }
+            Err(e) => {
+                let pattern = s.get("urlPattern").and_then(|v| v.as_str()).unwrap_or("<no urlPattern>");
+                println!("[WARN] Ignoring partial access site #{} ({}): {}", i, pattern, e);
+                rejected += 1;
+            }
         }
     }
+    (sites, rejected)
+}
+
+/// True unless the dialog's process is allowlisted or isn't the browser showing the restricted
+/// URL. Owned dialogs run in their owner's process, so the dialog and the top of its owner
+/// chain are both checked. With no known browser window, only the allowlist applies.
+#[cfg(windows)]
+fn dialog_belongs_to_browser(dialog: HWND, browser_hwnd: Option<isize>, config: &PartialAccessConfig) -> bool {
+    let dialog_pid = window_pid(dialog);
+    if process_exe_name(dialog_pid).is_some_and(|name| config.allowed_dialog_processes.contains(&name)) {
+        return false;
+    }
+    let Some(browser_hwnd) = browser_hwnd else {
+        return true;
+    };
+    let browser_pid = window_pid(HWND(browser_hwnd));
 
-    if !site.allow_download && site.monitor_mode == "block" {
-        let download_keywords = ["save", "download"];
-        if download_keywords.iter().any(|&k| title_lower.contains(k)) {
-            return DialogType::Download;
+    let mut owner = dialog;
+    loop {
+        let next = unsafe { GetWindow(owner, GW_OWNER) };
+        if next.0 == 0 {
+            break;
         }
+        owner = next;
     }
+    dialog_pid == browser_pid || window_pid(owner) == browser_pid
+}
 
-    DialogType::None
+/// True if the clipboard owner or the foreground window belongs to the process of the browser
+/// showing the restricted URL. With no known browser window nothing is attributed to it.
+#[cfg(windows)]
+fn clipboard_from_browser(browser_hwnd: Option<isize>) -> bool {
+    let Some(browser_hwnd) = browser_hwnd else {
+        return false;
+    };
+    let browser_pid = window_pid(HWND(browser_hwnd));
+    if browser_pid == 0 {
+        return false;
+    }
+    let owner = unsafe { GetClipboardOwner() };
+    let foreground = unsafe { GetForegroundWindow() };
+    [owner, foreground].iter().any(|hwnd| hwnd.0 != 0 && window_pid(*hwnd) == browser_pid)
+}
+
+#[cfg(windows)]
+fn window_pid(hwnd: HWND) -> u32 {
+    let mut pid: u32 = 0;
+    unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+    pid
+}
+
+fn to_strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+/// A non-empty array of strings from the config, lowercased for keyword matching if asked.
+fn string_list(config: &serde_json::Value, key: &str, lowercase: bool) -> Option<Vec<String>> {
+    let list: Vec<String> = config.get(key)?.as_array()?.iter()
+        .filter_map(|v| v.as_str())
+        .map(|s| if lowercase { s.trim().to_lowercase() } else { s.trim().to_string() })
+        .filter(|s| !s.is_empty())
+        .collect();
+    if list.is_empty() { None } else { Some(list) }
+}
+
+/// Extensions of the file(s) entered in a file dialog. Before a file is picked, falls back to the
+/// selected file-type filter when it names a single extension.
+#[cfg(windows)]
+fn dialog_file_extensions(dialog: HWND) -> Vec<String> {
+    struct Texts {
+        file_names: Vec<String>,
+        filters: Vec<String>,
+    }
+
+    unsafe extern "system" fn enum_child(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        unsafe {
+            let texts = &mut *(lparam.0 as *mut Texts);
+
+            let mut class_name = [0u16; 256];
+            let len = GetClassNameW(hwnd, &mut class_name).max(0) as usize;
+            let class_name_str = String::from_utf16_lossy(&class_name[..len]);
+
+            let text = control_text(hwnd);
+            if text.is_empty() {
+                return BOOL(1);
+            }
+            if class_name_str == "Edit" {
+                texts.file_names.push(text);
+            } else if text.contains("*.") {
+                texts.filters.push(text);
+            }
+            BOOL(1)
+        }
+    }
+
+    let mut texts = Texts { file_names: Vec::new(), filters: Vec::new() };
+    unsafe {
+        let _ = EnumChildWindows(dialog, Some(enum_child), LPARAM(&mut texts as *mut Texts as isize));
+    }
+
+    let mut extensions: Vec<String> = texts.file_names.iter()
+        .flat_map(|text| split_file_names(text))
+        .filter_map(|name| std::path::Path::new(&name).extension().map(|e| normalize_extension(&e.to_string_lossy())))
+        .collect();
+
+    if extensions.is_empty() {
+        // e.g. "PDF Files (*.pdf)"; "All Files (*.*)" or multi-type filters tell us nothing
+        for filter in &texts.filters {
+            let filter_extensions: Vec<String> = filter.split(|c: char| c == ';' || c == '(' || c == ')' || c.is_whitespace())
+                .filter(|part| part.starts_with("*."))
+                .map(normalize_extension)
+                .filter(|e| !e.is_empty() && e != "*")
+                .collect();
+            if filter_extensions.len() == 1 {
+                extensions.extend(filter_extensions);
+            }
+        }
+    }
+
+    extensions.sort();
+    extensions.dedup();
+    extensions
+}
+
+/// Reads a control's text with WM_GETTEXT, which (unlike GetWindowTextW) works for
+/// edit controls owned by another process.
+#[cfg(windows)]
+fn control_text(hwnd: HWND) -> String {
+    let mut buffer = [0u16; 1024];
+    let mut copied: usize = 0;
+    let result = unsafe {
+        SendMessageTimeoutW(hwnd, WM_GETTEXT, WPARAM(buffer.len()), LPARAM(buffer.as_mut_ptr() as isize),
+            SMTO_ABORTIFHUNG, 200, Some(&mut copied))
+    };
+    if result.0 == 0 {
+        return String::new();
+    }
+    String::from_utf16_lossy(&buffer[..copied.min(buffer.len())]).trim().to_string()
+}
+
+/// Multi-select file dialogs list names as `"a.pdf" "b.exe"`.
+fn split_file_names(text: &str) -> Vec<String> {
+    if text.contains('"') {
+        text.split('"')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect()
+    } else {
+        vec![text.to_string()]
+    }
+}
+
+/// "*.PDF", ".pdf" and "pdf" all become "pdf".
+fn normalize_extension(extension: &str) -> String {
+    extension.trim().trim_start_matches('*').trim_start_matches('.').to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::PartialAccessSite;
+
+    fn site(url_pattern: &str, url_pattern_is_regex: bool) -> PartialAccessSite {
+        let mut site: PartialAccessSite = serde_json::from_value(json!({
+            "urlPattern": url_pattern,
+            "urlPatternIsRegex": url_pattern_is_regex,
+            "allowUpload": false,
+            "allowDownload": true,
+            "monitorMode": "block",
+            "active": true,
+        })).unwrap();
+        site.compile_pattern();
+        site
+    }
+
+    #[test]
+    fn plain_patterns_match_case_insensitive_substrings() {
+        let drive = site("Drive.Google.com", false);
+        assert!(drive.matches_url("https://drive.google.com/drive/my-drive"));
+        assert!(drive.matches_url("HTTPS://DRIVE.GOOGLE.COM/"));
+        assert!(!drive.matches_url("https://docs.google.com/"));
+        // Regex syntax means nothing in a plain pattern
+        assert!(!site("drive.*.com", false).matches_url("https://drive.google.com/"));
+    }
+
+    #[test]
+    fn regex_patterns_match_anywhere_in_the_url() {
+        for google in [site(r"re:^https://(drive|docs)\.google\.com/", false), site(r"^https://(drive|docs)\.google\.com/", true)] {
+            assert!(google.matches_url("https://docs.google.com/document/d/1"));
+            assert!(google.matches_url("https://DRIVE.google.com/"));
+            assert!(!google.matches_url("https://mail.google.com/"));
+            assert!(!google.matches_url("https://evil.example/?next=https://drive.google.com/"));
+        }
+    }
+
+    #[test]
+    fn invalid_regex_matches_nothing() {
+        let broken = site("re:drive.google.com/(unclosed", false);
+        assert!(!broken.matches_url("https://drive.google.com/(unclosed"));
+        assert!(!broken.matches_url("re:drive.google.com/(unclosed"));
+    }
 }
@@ -0,0 +1,58 @@
+use std::path::Path;
+#[cfg(windows)]
+use windows::core::{HSTRING, PCWSTR};
+#[cfg(windows)]
+use windows::Win32::Storage::FileSystem::{GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW};
+
+/// The "OriginalFilename" an executable was built with, from its version resource, lowercased
+/// and cut at the first dot ("EXPLORER.EXE.MUI" -> "explorer"). Renaming the file doesn't
+/// change it, so it gives away e.g. chrome.exe copied to explorer.exe. None if the file has no
+/// version resource.
+#[cfg(windows)]
+pub fn original_file_name(exe: &Path) -> Option<String> {
+    let path = HSTRING::from(exe.to_string_lossy().as_ref());
+    unsafe {
+        let size = GetFileVersionInfoSizeW(&path, None);
+        if size == 0 {
+            return None;
+        }
+        let mut block = vec![0u8; size as usize];
+        GetFileVersionInfoW(&path, 0, size, block.as_mut_ptr() as *mut _).ok()?;
+
+        // The string table is keyed by the first language/codepage pair
+        let mut translation: *mut std::ffi::c_void = std::ptr::null_mut();
+        let mut len: u32 = 0;
+        if !VerQueryValueW(block.as_ptr() as *const _, &HSTRING::from("\\VarFileInfo\\Translation"), &mut translation, &mut len).as_bool()
+            || len < 4
+        {
+            return None;
+        }
+        let lang = *(translation as *const u16);
+        let codepage = *(translation as *const u16).add(1);
+
+        let key = HSTRING::from(format!("\\StringFileInfo\\{:04x}{:04x}\\OriginalFilename", lang, codepage));
+        let mut value: *mut std::ffi::c_void = std::ptr::null_mut();
+        if !VerQueryValueW(block.as_ptr() as *const _, &key, &mut value, &mut len).as_bool() || len == 0 {
+            return None;
+        }
+        let name = PCWSTR(value as *const u16).to_string().ok()?;
+        let stem = name.trim().split('.').next().unwrap_or_default().to_lowercase();
+        (!stem.is_empty()).then_some(stem)
+    }
+}
+
+/// Version resources are a Windows (PE) feature; other executables have none.
+#[cfg(not(windows))]
+pub fn original_file_name(_exe: &Path) -> Option<String> {
+    None
+}
+
+/// True if `exe` lies under the Windows or Program Files directories.
+pub fn in_system_location(exe: &Path) -> bool {
+    let exe = exe.to_string_lossy().to_lowercase();
+    ["SystemRoot", "ProgramFiles", "ProgramFiles(x86)", "ProgramW6432"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .map(|dir| format!("{}\\", dir.trim_end_matches('\\').to_lowercase()))
+        .any(|dir| exe.starts_with(&dir))
+}
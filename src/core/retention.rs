@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::core::monitor::DAILY_ARCHIVE_DIR;
+use crate::core::screenshot::SCREENSHOT_DIR;
+
+/// Directories the agent keeps writing to; the upload queue and app_data.json are left alone
+/// since they hold data not yet sent
+const RETENTION_DIRS: [&str; 3] = ["logs", DAILY_ARCHIVE_DIR, SCREENSHOT_DIR];
+
+/// Deletes files older than `max_age_days` from logs/, data/daily/ and data/screenshots/, then
+/// the oldest remaining files of any directory still over `max_dir_bytes`. Zero disables
+/// either limit. Run at startup and on each day rollover.
+pub fn sweep(max_age_days: u64, max_dir_bytes: u64) {
+    let cutoff = SystemTime::now().checked_sub(Duration::from_secs(max_age_days * 24 * 60 * 60));
+
+    for dir in RETENTION_DIRS {
+        let Ok(entries) = fs::read_dir(dir) else { continue };
+        let mut files: Vec<(PathBuf, SystemTime, u64)> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let meta = e.metadata().ok()?;
+                meta.is_file().then(|| (e.path(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len()))
+            })
+            .collect();
+        files.sort_by_key(|(_, modified, _)| *modified);
+
+        let mut removed = 0;
+        let mut freed = 0;
+        let mut total: u64 = files.iter().map(|(_, _, len)| len).sum();
+        for (path, modified, len) in &files {
+            let expired = max_age_days > 0 && cutoff.is_some_and(|c| *modified < c);
+            let over_cap = max_dir_bytes > 0 && total > max_dir_bytes;
+            if !expired && !over_cap {
+                // Oldest first, so nothing later is expired either
+                break;
+            }
+            match fs::remove_file(path) {
+                Ok(_) => {
+                    println!("[INFO] Retention: removed {} ({})", path.display(), if expired { "expired" } else { "size cap" });
+                    removed += 1;
+                    freed += len;
+                    total -= len;
+                }
+                Err(e) => println!("[ERROR] Retention: could not remove {}: {}", path.display(), e),
+            }
+        }
+        if removed > 0 {
+            println!("[INFO] Retention: removed {} files ({} KB) from {}", removed, freed / 1024, dir);
+        }
+    }
+}
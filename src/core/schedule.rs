@@ -0,0 +1,85 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, Weekday};
+
+/// Times of the week monitoring is allowed to run. Outside them nothing is tracked, blocked
+/// or uploaded; heartbeats continue.
+#[derive(Clone, Debug, Default)]
+pub struct WorkingHours {
+    /// Empty means no restriction
+    windows: Vec<HoursWindow>,
+}
+
+#[derive(Clone, Debug)]
+struct HoursWindow {
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    /// At or before start for a window running past midnight (e.g. 22:00-06:00)
+    end: NaiveTime,
+}
+
+impl WorkingHours {
+    /// Parses `[{"days": ["mon", "tue"], "start": "09:00", "end": "18:00"}, ...]`. Entries with
+    /// unknown days or unparsable times are skipped with a warning; a window without days applies
+    /// every day. An empty list lifts the restriction.
+    pub fn from_config(value: &serde_json::Value) -> Option<Self> {
+        let entries = value.as_array()?;
+        let mut windows = Vec::new();
+        for entry in entries {
+            match parse_window(entry) {
+                Some(window) => windows.push(window),
+                None => println!("[WARN] Ignoring invalid working-hours entry: {}", entry),
+            }
+        }
+        Some(WorkingHours { windows })
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        !self.windows.is_empty()
+    }
+
+    /// Whether monitoring should run at `now`.
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        if self.windows.is_empty() {
+            return true;
+        }
+        let time = now.time();
+        let today = now.weekday();
+        let yesterday = (now - Duration::days(1)).weekday();
+
+        self.windows.iter().any(|w| {
+            if w.start < w.end {
+                w.days.contains(&today) && time >= w.start && time < w.end
+            } else {
+                // Overnight: the evening part belongs to the start day, the morning part to the next
+                (w.days.contains(&today) && time >= w.start) || (w.days.contains(&yesterday) && time < w.end)
+            }
+        })
+    }
+}
+
+fn parse_window(entry: &serde_json::Value) -> Option<HoursWindow> {
+    let start = parse_time(entry.get("start")?.as_str()?)?;
+    let end = parse_time(entry.get("end")?.as_str()?)?;
+    let days = match entry.get("days").and_then(|v| v.as_array()) {
+        Some(days) => days.iter()
+            .map(|d| d.as_str().and_then(|d| d.trim().parse::<Weekday>().ok()))
+            .collect::<Option<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    let days = if days.is_empty() {
+        vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun]
+    } else {
+        days
+    };
+    Some(HoursWindow { days, start, end })
+}
+
+/// "HH:MM" or "HH:MM:SS"; "24:00" means the end of the day.
+fn parse_time(value: &str) -> Option<NaiveTime> {
+    let value = value.trim();
+    if value == "24:00" {
+        return NaiveTime::from_hms_milli_opt(23, 59, 59, 999);
+    }
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+        .ok()
+}
@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use chrono::Local;
+#[cfg(windows)]
+use xcap::Monitor;
+
+use crate::config::settings::{CAPTURE_SCREENSHOTS, MAX_SCREENSHOTS, SCREENSHOT_MIN_INTERVAL_SECS, UPLOAD_SCREENSHOTS};
+use crate::core::lock::LockRecover;
+
+pub const SCREENSHOT_DIR: &str = "data/screenshots";
+
+static ENABLED: AtomicBool = AtomicBool::new(CAPTURE_SCREENSHOTS);
+static UPLOAD_ENABLED: AtomicBool = AtomicBool::new(UPLOAD_SCREENSHOTS);
+static LAST_CAPTURE: Mutex<Option<Instant>> = Mutex::new(None);
+/// Captures waiting for the next sync to upload them
+static PENDING_UPLOADS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+pub fn set_enabled(capture: bool, upload: bool) {
+    ENABLED.store(capture, Ordering::SeqCst);
+    UPLOAD_ENABLED.store(upload, Ordering::SeqCst);
+}
+
+/// Saves a screenshot of the primary monitor to data/screenshots/TIMESTAMP.png for forensic
+/// review of a blocked access. At most one capture per SCREENSHOT_MIN_INTERVAL_SECS is taken,
+/// and the capture itself runs on its own thread so the caller isn't held up.
+pub fn capture_block_event(reason: &str) {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return;
+    }
+
+    {
+        let mut last = LAST_CAPTURE.lock_recover();
+        if last.is_some_and(|t| t.elapsed() < Duration::from_secs(SCREENSHOT_MIN_INTERVAL_SECS)) {
+            return;
+        }
+        *last = Some(Instant::now());
+    }
+
+    let reason = reason.to_string();
+    std::thread::spawn(move || {
+        let path = PathBuf::from(SCREENSHOT_DIR).join(format!("{}.png", Local::now().format("%Y%m%d-%H%M%S")));
+        if !save_primary_monitor(&path) {
+            return;
+        }
+        println!("[INFO] Saved screenshot of blocked access ({}) to {}", reason, path.display());
+
+        prune_old_screenshots();
+        if UPLOAD_ENABLED.load(Ordering::SeqCst) {
+            PENDING_UPLOADS.lock_recover().push(path);
+        }
+    });
+}
+
+/// Captures the primary monitor to `path` as a PNG. Returns false, after logging why, if
+/// nothing was saved.
+#[cfg(windows)]
+fn save_primary_monitor(path: &Path) -> bool {
+    let monitor = match Monitor::all() {
+        Ok(monitors) => monitors.into_iter().find(|m| m.is_primary()),
+        Err(e) => {
+            println!("[ERROR] Failed to enumerate monitors for screenshot: {}", e);
+            return false;
+        }
+    };
+    let Some(monitor) = monitor else { return false };
+
+    let image = match monitor.capture_image() {
+        Ok(image) => image,
+        Err(e) => {
+            println!("[ERROR] Failed to capture screenshot: {}", e);
+            return false;
+        }
+    };
+
+    if let Err(e) = std::fs::create_dir_all(SCREENSHOT_DIR) {
+        println!("[ERROR] Failed to create {}: {}", SCREENSHOT_DIR, e);
+        return false;
+    }
+    if let Err(e) = image.save(path) {
+        println!("[ERROR] Failed to save screenshot {}: {}", path.display(), e);
+        return false;
+    }
+    true
+}
+
+/// xcap is only built for Windows, so there is no screen capture elsewhere yet.
+#[cfg(not(windows))]
+fn save_primary_monitor(_path: &Path) -> bool {
+    false
+}
+
+/// Screenshots captured since the last call that still need uploading.
+pub fn take_pending_uploads() -> Vec<PathBuf> {
+    std::mem::take(&mut *PENDING_UPLOADS.lock_recover())
+}
+
+/// Puts screenshots that failed to upload back in line for the next sync, unless they
+/// have been pruned from disk in the meantime.
+pub fn requeue_uploads(paths: Vec<PathBuf>) {
+    PENDING_UPLOADS.lock_recover().extend(paths.into_iter().filter(|p| p.exists()));
+}
+
+/// Deletes the oldest screenshots beyond MAX_SCREENSHOTS. Names are timestamps, so they sort by age.
+fn prune_old_screenshots() {
+    let Ok(entries) = std::fs::read_dir(SCREENSHOT_DIR) else { return };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "png"))
+        .collect();
+    if files.len() <= MAX_SCREENSHOTS {
+        return;
+    }
+
+    files.sort();
+    let excess = files.len() - MAX_SCREENSHOTS;
+    for path in &files[..excess] {
+        let _ = std::fs::remove_file(path);
+    }
+}
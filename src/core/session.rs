@@ -0,0 +1,129 @@
+use std::sync::OnceLock;
+#[cfg(windows)]
+use windows::core::PWSTR;
+#[cfg(windows)]
+use windows::Win32::System::RemoteDesktop::{
+    ProcessIdToSessionId, WTSEnumerateSessionsW, WTSFreeMemory, WTSGetActiveConsoleSessionId, WTSQuerySessionInformationW,
+    WTSActive, WTSUserName, WTS_CURRENT_SERVER_HANDLE, WTS_SESSION_INFOW,
+};
+#[cfg(windows)]
+use windows::Win32::System::Threading::GetCurrentProcessId;
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::{GetSystemMetrics, SM_REMOTESESSION};
+
+/// The Windows session the agent runs in (its POSIX session on other platforms). The foreground
+/// window is per session, so app tracking only ever sees the desktop of this one.
+#[derive(Clone, Debug)]
+pub struct SessionContext {
+    pub session_id: u32,
+    /// User logged on to the session; None for session 0 or if it can't be queried
+    pub user: Option<String>,
+    /// Connected over RDP (SSH elsewhere) rather than at the physical console
+    pub remote: bool,
+}
+
+impl SessionContext {
+    /// Session 0 hosts services and has no interactive desktop to track.
+    pub fn is_service_session(&self) -> bool {
+        self.session_id == 0
+    }
+}
+
+/// The agent's session, detected once; a process never moves between sessions.
+pub fn current_session() -> &'static SessionContext {
+    static SESSION: OnceLock<SessionContext> = OnceLock::new();
+    SESSION.get_or_init(detect_session)
+}
+
+#[cfg(windows)]
+fn detect_session() -> SessionContext {
+    let mut session_id: u32 = 0;
+    unsafe {
+        if ProcessIdToSessionId(GetCurrentProcessId(), &mut session_id).is_err() {
+            // Fall back to the console session, which is right for a plain desktop
+            session_id = WTSGetActiveConsoleSessionId();
+        }
+    }
+    SessionContext {
+        session_id,
+        user: session_user(session_id),
+        remote: unsafe { GetSystemMetrics(SM_REMOTESESSION) } != 0,
+    }
+}
+
+#[cfg(not(windows))]
+fn detect_session() -> SessionContext {
+    unsafe extern "C" {
+        fn getsid(pid: i32) -> i32;
+    }
+    SessionContext {
+        // getsid returns -1 on failure; clamp it so it isn't taken for session 0 (a service)
+        session_id: unsafe { getsid(0) }.max(1) as u32,
+        user: Some(whoami::username()),
+        remote: std::env::var_os("SSH_CONNECTION").is_some(),
+    }
+}
+
+/// Logs which session app usage is attributed to, and warns when other users' sessions on
+/// this machine (terminal servers, fast user switching) can't be seen from here.
+pub fn log_session_context() {
+    let session = current_session();
+    let others: Vec<(u32, String)> = active_sessions()
+        .into_iter()
+        .filter(|(id, _)| *id != session.session_id)
+        .collect();
+
+    if session.is_service_session() {
+        println!("[WARN] Running in session 0 (as a service): there is no desktop here, so app usage can't be tracked.");
+        if !others.is_empty() {
+            let users: Vec<String> = others.iter().map(|(id, user)| format!("{} (session {})", user, id)).collect();
+            println!("[WARN] Active user sessions not tracked: {}. Start the agent inside each user session, e.g. from a logon task.", users.join(", "));
+        }
+        return;
+    }
+
+    println!("[INFO] Tracking session {} ({}) of user {}",
+        session.session_id,
+        if session.remote { "remote desktop" } else { "console" },
+        session.user.as_deref().unwrap_or("unknown"));
+    if !others.is_empty() {
+        println!("[WARN] {} other active session(s) on this machine are not tracked by this instance; each needs its own agent.", others.len());
+    }
+}
+
+/// Sessions with a logged-on user, as (session id, user name).
+#[cfg(windows)]
+fn active_sessions() -> Vec<(u32, String)> {
+    let mut sessions: *mut WTS_SESSION_INFOW = std::ptr::null_mut();
+    let mut count: u32 = 0;
+    unsafe {
+        if WTSEnumerateSessionsW(WTS_CURRENT_SERVER_HANDLE, 0, 1, &mut sessions, &mut count).is_err() {
+            return Vec::new();
+        }
+        let active = std::slice::from_raw_parts(sessions, count as usize)
+            .iter()
+            .filter(|s| s.State == WTSActive)
+            .filter_map(|s| session_user(s.SessionId).map(|user| (s.SessionId, user)))
+            .collect();
+        WTSFreeMemory(sessions as *mut _);
+        active
+    }
+}
+
+/// Other users' sessions aren't enumerated outside Windows.
+#[cfg(not(windows))]
+fn active_sessions() -> Vec<(u32, String)> {
+    Vec::new()
+}
+
+#[cfg(windows)]
+fn session_user(session_id: u32) -> Option<String> {
+    let mut buffer = PWSTR::null();
+    let mut bytes: u32 = 0;
+    unsafe {
+        WTSQuerySessionInformationW(WTS_CURRENT_SERVER_HANDLE, session_id, WTSUserName, &mut buffer, &mut bytes).ok()?;
+        let user = buffer.to_string().unwrap_or_default();
+        WTSFreeMemory(buffer.as_ptr() as *mut _);
+        (!user.is_empty()).then_some(user)
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::config::settings::STATUS_TOKEN_FILE;
+use crate::core::lock::LockRecover;
+
+/// What the monitor is doing right now, refreshed every tick and served as JSON for health checks.
+#[derive(Serialize, Clone, Default)]
+pub struct MonitorStatus {
+    #[serde(rename = "uptimeSecs")]
+    pub uptime_secs: u64,
+    #[serde(rename = "currentApp")]
+    pub current_app: Option<String>,
+    #[serde(rename = "currentUrl")]
+    pub current_url: Option<String>,
+    #[serde(rename = "blockedCount")]
+    pub blocked_count: u32,
+    #[serde(rename = "suspiciousCount")]
+    pub suspicious_count: u32,
+    #[serde(rename = "dialogsClosed")]
+    pub dialogs_closed: u32,
+    /// False if the partial-access thread isn't running or is restarting after a crash
+    #[serde(rename = "partialAccessHealthy")]
+    pub partial_access_healthy: bool,
+    /// Sites in the last partial-access config push that parsed, and those that didn't
+    #[serde(rename = "partialAccessSitesAccepted")]
+    pub partial_access_sites_accepted: usize,
+    #[serde(rename = "partialAccessSitesRejected")]
+    pub partial_access_sites_rejected: usize,
+    #[serde(rename = "lastSuccessfulSync")]
+    pub last_successful_sync: Option<String>,
+    pub paused: bool,
+    #[serde(rename = "outsideWorkingHours")]
+    pub outside_working_hours: bool,
+}
+
+/// One-off actions requested through the status endpoint, picked up by the monitor loop on its
+/// next tick.
+#[derive(Default)]
+pub struct ControlRequests {
+    /// Fetch partial-access, monitor and blocked-URL config now instead of at the next interval
+    pub refresh_config: AtomicBool,
+    /// Run a sync, including a full app export, now instead of at the next interval
+    pub sync_now: AtomicBool,
+}
+
+/// Serves `GET /status` on 127.0.0.1:<port>, plus `POST /refresh-config` and `POST /sync-now`
+/// for testing policy changes without waiting for the timers. Only loopback is bound, so the
+/// endpoint is not reachable from the network. The POSTs need the X-Status-Token header from
+/// data/status_token, and anything a browser sends (it always adds Origin) is refused, so web
+/// pages and other local users can't trigger them.
+pub fn spawn_status_server(status: Arc<Mutex<MonitorStatus>>, control: Arc<ControlRequests>, port: u16) {
+    let token: Arc<str> = status_token().into();
+    tokio::spawn(async move {
+        let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+            Ok(l) => l,
+            Err(e) => {
+                println!("[ERROR] Could not start status endpoint on port {}: {}", port, e);
+                return;
+            }
+        };
+        println!("  Status endpoint: http://127.0.0.1:{}/status (POST token in {})", port, STATUS_TOKEN_FILE);
+
+        loop {
+            let mut stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    println!("[ERROR] Status endpoint accept failed: {}", e);
+                    continue;
+                }
+            };
+
+            let status = status.clone();
+            let control = control.clone();
+            let token = token.clone();
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                // Don't let a client that never sends a request hold the connection open
+                let n = match tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf)).await {
+                    Ok(Ok(n)) => n,
+                    _ => return,
+                };
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let response = handle_request(&request, &token, &status, &control);
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+}
+
+/// The response to one request. Split out of the accept loop so routing and the token check
+/// can be tested without a socket.
+fn handle_request(request: &str, token: &str, status: &Mutex<MonitorStatus>, control: &ControlRequests) -> String {
+    if header(request, "origin").is_some() {
+        println!("[WARN] Refused a browser request to the status endpoint");
+        return http_response("403 Forbidden", r#"{"error":"forbidden"}"#);
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next();
+    if method == Some("POST") && header(request, "x-status-token") != Some(token) {
+        println!("[WARN] Refused a status endpoint request without a valid X-Status-Token");
+        return http_response("401 Unauthorized", r#"{"error":"unauthorized"}"#);
+    }
+
+    match (method, parts.next()) {
+        (Some("GET"), Some("/status")) | (Some("GET"), Some("/")) => {
+            let body = serde_json::to_string(&*status.lock_recover()).unwrap_or_default();
+            http_response("200 OK", &body)
+        }
+        (Some("POST"), Some("/refresh-config")) => {
+            control.refresh_config.store(true, Ordering::SeqCst);
+            println!("[INFO] Config refresh requested through the status endpoint");
+            http_response("202 Accepted", r#"{"queued":"refresh-config"}"#)
+        }
+        (Some("POST"), Some("/sync-now")) => {
+            control.sync_now.store(true, Ordering::SeqCst);
+            println!("[INFO] Sync requested through the status endpoint");
+            http_response("202 Accepted", r#"{"queued":"sync-now"}"#)
+        }
+        _ => http_response("404 Not Found", r#"{"error":"not found"}"#),
+    }
+}
+
+/// Value of a request header, matched case-insensitively.
+fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request.lines()
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// The token POST requests must carry, read from STATUS_TOKEN_FILE or generated and saved there
+/// on first start.
+fn status_token() -> String {
+    if let Ok(saved) = std::fs::read_to_string(STATUS_TOKEN_FILE) {
+        let saved = saved.trim();
+        if !saved.is_empty() {
+            return saved.to_string();
+        }
+    }
+
+    // RandomState is seeded from the OS random source, so each one gives unpredictable hashes
+    let mut hasher = Sha256::new();
+    for _ in 0..4 {
+        hasher.update(RandomState::new().hash_one(std::process::id()).to_le_bytes());
+    }
+    hasher.update(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_le_bytes());
+    let token = format!("{:x}", hasher.finalize());
+
+    let saved = std::path::Path::new(STATUS_TOKEN_FILE).parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(STATUS_TOKEN_FILE, &token));
+    if let Err(e) = saved {
+        println!("[ERROR] Failed to save the status endpoint token to {}: {}", STATUS_TOKEN_FILE, e);
+    }
+    token
+}
+
+fn http_response(status_line: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status_line, body.len(), body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::sync::atomic::Ordering;
+
+    use super::{handle_request, ControlRequests, MonitorStatus};
+
+    const TOKEN: &str = "0123abcd";
+
+    fn send(request: &str) -> (String, ControlRequests) {
+        let control = ControlRequests::default();
+        let response = handle_request(request, TOKEN, &Mutex::new(MonitorStatus::default()), &control);
+        (response, control)
+    }
+
+    #[test]
+    fn post_needs_the_token() {
+        let (response, control) = send("POST /sync-now HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 401"));
+        assert!(!control.sync_now.load(Ordering::SeqCst));
+
+        let (response, control) = send("POST /sync-now HTTP/1.1\r\nX-Status-Token: wrong\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 401"));
+        assert!(!control.sync_now.load(Ordering::SeqCst));
+
+        let (response, control) = send("POST /refresh-config HTTP/1.1\r\nx-status-token: 0123abcd\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 202"));
+        assert!(control.refresh_config.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn browser_requests_are_refused() {
+        let (response, control) = send("POST /sync-now HTTP/1.1\r\nOrigin: https://evil.example\r\nX-Status-Token: 0123abcd\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 403"));
+        assert!(!control.sync_now.load(Ordering::SeqCst));
+
+        let (response, _) = send("GET /status HTTP/1.1\r\nOrigin: null\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 403"));
+    }
+
+    #[test]
+    fn status_needs_no_token() {
+        let (response, _) = send("GET /status HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("\"uptimeSecs\":0"));
+
+        let (response, _) = send("GET /missing HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+}
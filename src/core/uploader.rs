@@ -0,0 +1,140 @@
+use std::path::Path;
+use std::sync::Arc;
+use chrono::Local;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::client::{
+    merge_url_visits, APIClient, AppUsageData, AppUsageExportData, EnforcementHealth, NetworkContext, PartialAccessStatsData,
+    UploadOutcome, UrlMonitoringData, UrlVisit,
+};
+use crate::core::screenshot;
+
+const LOG_FILE: &str = "logs/app_timelog.log";
+
+/// Everything one sync cycle uploads, handed from the monitor loop to the uploader task.
+/// The URLs are moved out of the browser monitor and come back in a SyncOutcome on failure.
+pub struct SyncJob {
+    pub paused: bool,
+    pub network: Option<NetworkContext>,
+    /// Sent with the batch's heartbeat; only set for batch uploads
+    pub health: Option<EnforcementHealth>,
+    pub app_data: AppUsageData,
+    pub url_data: UrlMonitoringData,
+    pub partial_access_stats: PartialAccessStatsData,
+    /// Complete app totals, on the syncs where a full export is due
+    pub full_app_export: Option<AppUsageExportData>,
+    /// Send everything in one request (see BATCH_UPLOADS)
+    pub batch: bool,
+}
+
+impl SyncJob {
+    /// Folds an older, not yet uploaded job into this one. App usage and stats are cumulative
+    /// snapshots, so only the newer ones are kept; the URL visits are merged.
+    fn absorb_older(&mut self, older: SyncJob) {
+        let visits = merge_url_visits(older.url_data.visits, std::mem::take(&mut self.url_data.visits));
+        self.url_data.set_visits(visits);
+        self.url_data.is_private |= older.url_data.is_private;
+        if self.full_app_export.is_none() {
+            self.full_app_export = older.full_app_export;
+        }
+        // The older job's counts were taken off the baseline too, so carry them over
+        if let (Some(health), Some(older)) = (self.health.as_mut(), older.health) {
+            health.blocked_since_last_heartbeat += older.blocked_since_last_heartbeat;
+            health.suspicious_since_last_heartbeat += older.suspicious_since_last_heartbeat;
+            health.dialogs_closed_since_last_heartbeat += older.dialogs_closed_since_last_heartbeat;
+        }
+    }
+}
+
+pub struct SyncOutcome {
+    pub app_upload: UploadOutcome,
+    pub url_upload: UploadOutcome,
+    /// The job's URL visits and private-browsing flag when url_upload was rejected, to be put
+    /// back for the next sync
+    pub unsent_urls: Vec<UrlVisit>,
+    pub unsent_private: bool,
+    /// A batch upload carries a heartbeat, so the next one can wait
+    pub heartbeat_sent: bool,
+}
+
+/// Starts the task that performs sync uploads, so a slow or unreachable server never stalls
+/// tracking and blocking. Jobs are queued in a channel holding `capacity` cycles; when several
+/// are waiting, the older ones are merged into the newest and superseded.
+pub fn spawn_uploader(
+    api_client: Arc<APIClient>,
+    capacity: usize,
+) -> (mpsc::Sender<SyncJob>, mpsc::UnboundedReceiver<SyncOutcome>, JoinHandle<()>) {
+    let (job_tx, mut job_rx) = mpsc::channel::<SyncJob>(capacity.max(1));
+    let (outcome_tx, outcome_rx) = mpsc::unbounded_channel();
+
+    let handle = tokio::spawn(async move {
+        while let Some(mut job) = job_rx.recv().await {
+            let mut superseded = 0;
+            while let Ok(newer) = job_rx.try_recv() {
+                let older = std::mem::replace(&mut job, newer);
+                job.absorb_older(older);
+                superseded += 1;
+            }
+            if superseded > 0 {
+                println!("[WARN] Uploads are falling behind: merged {} older sync snapshot(s) into the latest", superseded);
+            }
+
+            let outcome = upload(&api_client, job).await;
+            if outcome_tx.send(outcome).is_err() {
+                break;
+            }
+        }
+    });
+
+    (job_tx, outcome_rx, handle)
+}
+
+async fn upload(api_client: &APIClient, job: SyncJob) -> SyncOutcome {
+    println!("[{}] Synchronizing with API...", Local::now().format("%H:%M:%S"));
+
+    // Retry anything queued while offline before sending fresh data
+    api_client.drain_upload_queue().await;
+
+    let SyncJob { paused, network, health, app_data, url_data, partial_access_stats, full_app_export, batch } = job;
+    // Uploads consume the payload, so keep the URLs in case they have to go back
+    let urls = url_data.visits.clone();
+    let is_private = url_data.is_private;
+
+    let (app_upload, url_upload) = if batch {
+        // The batch carries a heartbeat and the log file too
+        let outcome = api_client.upload_batch(paused, network, health.unwrap_or_default(), app_data, url_data, Path::new(LOG_FILE)).await;
+        (outcome, outcome)
+    } else {
+        (api_client.upload_app_usage(app_data).await, api_client.upload_urls(url_data).await)
+    };
+    let outcome = SyncOutcome {
+        app_upload,
+        url_upload,
+        unsent_urls: if url_upload.kept() { Vec::new() } else { urls },
+        unsent_private: !url_upload.kept() && is_private,
+        // A queued batch's heartbeat only reaches the server whenever the queue drains
+        heartbeat_sent: batch && url_upload == UploadOutcome::Sent,
+    };
+
+    // Upload screenshots of blocked access, keeping failures for the next sync
+    let mut failed_screenshots = Vec::new();
+    for path in screenshot::take_pending_uploads() {
+        if !api_client.upload_screenshot(&path).await {
+            failed_screenshots.push(path);
+        }
+    }
+    screenshot::requeue_uploads(failed_screenshots);
+
+    api_client.upload_partial_access_stats(partial_access_stats).await;
+    if let Some(export) = full_app_export {
+        api_client.upload_app_usage_export(export).await;
+    }
+
+    if !batch {
+        api_client.upload_logs(Path::new(LOG_FILE), false).await;
+    }
+
+    println!("[{}] API sync complete.", Local::now().format("%H:%M:%S"));
+    outcome
+}
@@ -0,0 +1,254 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use aho_corasick::AhoCorasick;
+use regex::{Regex, RegexSet, RegexSetBuilder};
+
+use crate::core::url_utils::to_ascii_host;
+
+/// Shorter values are usually partial address-bar typing, not URLs, and never match
+pub const MIN_URL_LEN: usize = 4;
+/// Compiled size allowed for the combined wildcard RegexSet (the regex default is 10 MB,
+/// too small for lists with tens of thousands of wildcards)
+const WILDCARD_SET_SIZE_LIMIT: usize = 256 * 1024 * 1024;
+
+/// Lowercases a URL or pattern, converts its host to punycode and strips the scheme, a leading
+/// "www." and trailing slashes, e.g. "https://www.Example.com/" -> "example.com".
+pub fn normalize(url: &str) -> String {
+    let ascii = to_ascii_host(&url.trim().to_lowercase());
+    let mut stripped = ascii.as_str();
+    if let Some(rest) = stripped.strip_prefix("http://") { stripped = rest; }
+    if let Some(rest) = stripped.strip_prefix("https://") { stripped = rest; }
+    if let Some(rest) = stripped.strip_prefix("www.") { stripped = rest; }
+    stripped.trim_end_matches('/').to_string()
+}
+
+/// True if `pattern` matches `url`. Patterns containing '*' are wildcards over the whole URL
+/// ("*.facebook.com/*"); anything else matches when the normalized URL contains the normalized
+/// pattern, so "facebook.com" also covers "https://www.facebook.com/login".
+pub fn matches(url: &str, pattern: &str) -> bool {
+    if url.len() < MIN_URL_LEN {
+        return false;
+    }
+    if pattern.contains('*') {
+        return match wildcard_regex(pattern) {
+            Ok(re) => re.is_match(&to_ascii_host(url)),
+            Err(_) => false,
+        };
+    }
+    matches_normalized(&normalize(url), pattern)
+}
+
+/// Substring match of a non-wildcard pattern against a URL already passed through normalize.
+/// Lets callers checking many patterns normalize the URL once.
+pub fn matches_normalized(normalized_url: &str, pattern: &str) -> bool {
+    let normalized_pattern = normalize(pattern);
+    !normalized_pattern.is_empty() && normalized_url.contains(&normalized_pattern)
+}
+
+/// Compiles a '*' wildcard pattern into a case-insensitive regex anchored to the whole URL.
+pub fn wildcard_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    let ascii = to_ascii_host(&pattern.trim().to_lowercase());
+    let regex_pattern = regex::escape(&ascii).replace("\\*", ".*");
+    Regex::new(&format!("(?i)^{}$", regex_pattern))
+}
+
+/// Compiled filter-list patterns, so checking a URL costs one Aho-Corasick pass over the
+/// plain patterns and one RegexSet pass over the wildcards however long the list is.
+/// Matching follows `matches`.
+pub struct PatternSet {
+    /// Automaton over the normalized plain patterns; ids index `substring_patterns`
+    substrings: Option<AhoCorasick>,
+    substring_patterns: Vec<String>,
+    wildcards: WildcardMatcher,
+    wildcard_patterns: Vec<String>,
+    /// Longest literal of each wildcard; a URL containing none of them can't match any wildcard.
+    /// None when some wildcard has no literal (e.g. "*"), so every URL has to be checked.
+    wildcard_prefilter: Option<AhoCorasick>,
+    /// URLs the prefilter let skip the wildcard pass, and those that needed it
+    prefilter_skips: AtomicU64,
+    wildcard_checks: AtomicU64,
+}
+
+enum WildcardMatcher {
+    Set(RegexSet),
+    /// Used only if the combined set exceeds the regex size limit
+    Each(Vec<Regex>),
+}
+
+impl PatternSet {
+    pub fn new(patterns: &[String]) -> Self {
+        let (wildcard_list, plain_list): (Vec<&String>, Vec<&String>) = patterns.iter().partition(|p| p.contains('*'));
+
+        let mut substring_patterns = Vec::new();
+        let mut normalized = Vec::new();
+        for pattern in plain_list {
+            let n = normalize(pattern);
+            if !n.is_empty() {
+                substring_patterns.push(pattern.clone());
+                normalized.push(n);
+            }
+        }
+        let substrings = if normalized.is_empty() {
+            None
+        } else {
+            match AhoCorasick::new(&normalized) {
+                Ok(ac) => Some(ac),
+                Err(e) => {
+                    println!("[ERROR] Could not build the substring matcher: {}", e);
+                    None
+                }
+            }
+        };
+
+        // Invalid patterns are dropped individually so one typo can't disable the whole set
+        let mut wildcard_patterns = Vec::new();
+        let mut regexes = Vec::new();
+        for pattern in wildcard_list {
+            match wildcard_regex(pattern) {
+                Ok(re) => {
+                    wildcard_patterns.push(pattern.clone());
+                    regexes.push(re);
+                }
+                Err(e) => println!("[ERROR] Invalid wildcard pattern '{}': {}", pattern, e),
+            }
+        }
+        let wildcard_prefilter = wildcard_literals(&wildcard_patterns).and_then(|literals| {
+            if literals.is_empty() {
+                return None;
+            }
+            AhoCorasick::new(&literals)
+                .map_err(|e| println!("[WARN] Could not build the wildcard prefilter ({}). Checking every URL.", e))
+                .ok()
+        });
+        let wildcards = match RegexSetBuilder::new(regexes.iter().map(|re| re.as_str()))
+            .size_limit(WILDCARD_SET_SIZE_LIMIT)
+            .build() {
+            Ok(set) => WildcardMatcher::Set(set),
+            Err(e) => {
+                println!("[WARN] Could not combine {} wildcard patterns ({}). Matching them one by one.", regexes.len(), e);
+                WildcardMatcher::Each(regexes)
+            }
+        };
+
+        PatternSet {
+            substrings,
+            substring_patterns,
+            wildcards,
+            wildcard_patterns,
+            wildcard_prefilter,
+            prefilter_skips: AtomicU64::new(0),
+            wildcard_checks: AtomicU64::new(0),
+        }
+    }
+
+    /// The pattern matching `url`, preferring plain patterns over wildcards and earlier
+    /// patterns over later ones.
+    pub fn find(&self, url: &str) -> Option<&str> {
+        if url.len() < MIN_URL_LEN {
+            return None;
+        }
+
+        if let Some(ac) = &self.substrings {
+            let normalized_url = normalize(url);
+            if let Some(id) = ac.find_overlapping_iter(&normalized_url).map(|m| m.pattern().as_usize()).min() {
+                return Some(&self.substring_patterns[id]);
+            }
+        }
+
+        if self.wildcard_patterns.is_empty() {
+            return None;
+        }
+        let url_ascii = to_ascii_host(&url.to_lowercase());
+        if let Some(prefilter) = &self.wildcard_prefilter
+            && !prefilter.is_match(&url_ascii) {
+            self.prefilter_skips.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        self.wildcard_checks.fetch_add(1, Ordering::Relaxed);
+        let index = match &self.wildcards {
+            WildcardMatcher::Set(set) => set.matches(&url_ascii).iter().next(),
+            WildcardMatcher::Each(regexes) => regexes.iter().position(|re| re.is_match(&url_ascii)),
+        };
+        index.map(|i| self.wildcard_patterns[i].as_str())
+    }
+
+    /// URLs that skipped the wildcard pass thanks to the prefilter, and URLs that ran it.
+    pub fn prefilter_stats(&self) -> (u64, u64) {
+        (self.prefilter_skips.load(Ordering::Relaxed), self.wildcard_checks.load(Ordering::Relaxed))
+    }
+}
+
+/// The longest '*'-free segment of each wildcard pattern, in the form wildcard_regex matches
+/// against. None if any pattern is all wildcards.
+fn wildcard_literals(patterns: &[String]) -> Option<Vec<String>> {
+    patterns.iter()
+        .map(|pattern| {
+            to_ascii_host(&pattern.trim().to_lowercase())
+                .split('*')
+                .max_by_key(|segment| segment.len())
+                .filter(|segment| !segment.is_empty())
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matches, normalize, PatternSet, MIN_URL_LEN};
+
+    #[test]
+    fn normalize_strips_scheme_www_and_trailing_slashes() {
+        let cases = [
+            ("https://www.Example.com/", "example.com"),
+            ("http://example.com/path//", "example.com/path"),
+            ("  WWW.example.com  ", "example.com"),
+            ("https://münchen.de/Karte", "xn--mnchen-3ya.de/karte"),
+            ("example.com/www.page", "example.com/www.page"),
+            ("", ""),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize(input), expected, "normalize({:?})", input);
+        }
+    }
+
+    #[test]
+    fn plain_patterns_match_as_substrings() {
+        let cases = [
+            ("https://www.facebook.com/login", "facebook.com", true),
+            ("https://m.facebook.com/", "https://www.facebook.com/", true),
+            ("https://xn--mnchen-3ya.de/", "münchen.de", true),
+            ("https://example.com/", "facebook.com", false),
+            // A pattern that normalizes to nothing matches nothing
+            ("https://example.com/", "https://www./", false),
+        ];
+        for (url, pattern, expected) in cases {
+            assert_eq!(matches(url, pattern), expected, "matches({:?}, {:?})", url, pattern);
+        }
+    }
+
+    #[test]
+    fn wildcard_patterns_match_the_whole_url() {
+        let cases = [
+            ("https://www.facebook.com/login", "*.facebook.com/*", true),
+            ("https://WWW.FACEBOOK.COM/login", "*.facebook.com/*", true),
+            ("https://facebook.com.evil.example/", "*.facebook.com/*", false),
+            ("https://casino.example/", "*casino*", true),
+            ("https://example.com/", "*casino*", false),
+            ("https://bücher.example/", "*xn--bcher-kva.example*", true),
+        ];
+        for (url, pattern, expected) in cases {
+            assert_eq!(matches(url, pattern), expected, "matches({:?}, {:?})", url, pattern);
+        }
+    }
+
+    #[test]
+    fn urls_shorter_than_the_minimum_never_match() {
+        let short = "a".repeat(MIN_URL_LEN - 1);
+        assert!(!matches(&short, "a"));
+        assert!(!matches(&short, "*"));
+        assert!(matches(&"a".repeat(MIN_URL_LEN), "a"));
+
+        let set = PatternSet::new(&["a".to_string(), "*".to_string()]);
+        assert_eq!(set.find(&short), None);
+        assert_eq!(set.find("abcd"), Some("a"));
+    }
+}
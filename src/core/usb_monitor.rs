@@ -0,0 +1,98 @@
+#[cfg(windows)]
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+#[cfg(windows)]
+use std::sync::atomic::Ordering;
+#[cfg(windows)]
+use std::time::Duration;
+use tokio::sync::mpsc;
+#[cfg(windows)]
+use windows::core::PCWSTR;
+#[cfg(windows)]
+use windows::Win32::Storage::FileSystem::{GetDriveTypeW, GetLogicalDrives, GetVolumeInformationW};
+
+#[cfg(windows)]
+use crate::config::settings::USB_POLL_INTERVAL_MS;
+
+/// GetDriveTypeW result for removable media (from WindowsProgramming, which we don't otherwise need)
+#[cfg(windows)]
+const DRIVE_REMOVABLE: u32 = 2;
+
+/// A removable volume that was just attached.
+#[derive(Clone, Debug)]
+pub struct UsbVolume {
+    /// Drive root, e.g. "E:\"
+    pub drive: String,
+    pub label: String,
+    /// Volume serial number as shown by `vol`, e.g. "1A2B-3C4D"
+    pub serial: String,
+}
+
+/// Starts a thread that polls the drive letters and sends each removable volume that appears.
+/// Volumes already attached at startup aren't reported. USB hard disks report as fixed drives
+/// and aren't seen here.
+#[cfg(windows)]
+pub fn spawn_usb_monitor(shutdown: Arc<AtomicBool>) -> mpsc::UnboundedReceiver<UsbVolume> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    std::thread::spawn(move || {
+        let mut known: HashSet<String> = removable_drives().into_iter().collect();
+        while !shutdown.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(USB_POLL_INTERVAL_MS));
+
+            let current: HashSet<String> = removable_drives().into_iter().collect();
+            for drive in current.difference(&known) {
+                // Card readers show empty slots as removable drives; only report mounted media
+                if let Some(volume) = volume_info(drive)
+                    && tx.send(volume).is_err() {
+                    return;
+                }
+            }
+            known = current;
+        }
+    });
+
+    rx
+}
+
+/// Drive letters are a Windows notion; elsewhere no volumes are reported and the receiver
+/// stays empty.
+#[cfg(not(windows))]
+pub fn spawn_usb_monitor(_shutdown: Arc<AtomicBool>) -> mpsc::UnboundedReceiver<UsbVolume> {
+    println!("[INFO] USB monitoring is only available on Windows.");
+    mpsc::unbounded_channel().1
+}
+
+/// Roots ("E:\") of removable drives currently present.
+#[cfg(windows)]
+fn removable_drives() -> Vec<String> {
+    let mask = unsafe { GetLogicalDrives() };
+    (0..26u8)
+        .filter(|i| mask & (1 << i) != 0)
+        .map(|i| format!("{}:\\", (b'A' + i) as char))
+        .filter(|root| unsafe { GetDriveTypeW(PCWSTR(wide(root).as_ptr())) } == DRIVE_REMOVABLE)
+        .collect()
+}
+
+#[cfg(windows)]
+fn volume_info(root: &str) -> Option<UsbVolume> {
+    let mut label = [0u16; 261];
+    let mut serial: u32 = 0;
+    unsafe {
+        GetVolumeInformationW(PCWSTR(wide(root).as_ptr()), Some(&mut label), Some(&mut serial), None, None, None).ok()?;
+    }
+    let len = label.iter().position(|&c| c == 0).unwrap_or(label.len());
+
+    Some(UsbVolume {
+        drive: root.to_string(),
+        label: String::from_utf16_lossy(&label[..len]),
+        serial: format!("{:04X}-{:04X}", serial >> 16, serial & 0xFFFF),
+    })
+}
+
+/// A null-terminated UTF-16 copy of `s` for Win32 calls.
+#[cfg(windows)]
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
@@ -0,0 +1,431 @@
+/// A top-level window as seen by the inspector.
+#[derive(Clone, Debug)]
+pub struct TopLevelWindow {
+    pub hwnd: isize,
+    pub name: String,
+    /// Win32 class name, e.g. "Chrome_WidgetWin_1"; empty if it couldn't be read
+    pub class_name: String,
+}
+
+/// The window operations BrowserMonitor needs, kept behind a trait so the
+/// blocking logic doesn't depend on a live UI Automation session.
+pub trait WindowInspector {
+    /// All top-level windows on the desktop, in Z-order.
+    fn top_level_windows(&self) -> Vec<TopLevelWindow>;
+    /// The URL currently shown in the browser window's address bar.
+    fn address_bar_url(&self, hwnd: isize) -> Option<String>;
+    /// The URL exposed by the page's Document element, for when the address bar is hidden or
+    /// can't be found: its value, or failing that its name if that is a URL.
+    fn document_url(&self, hwnd: isize) -> Option<String>;
+    /// Whether the window covers its whole monitor (F11 fullscreen or kiosk mode).
+    fn is_fullscreen(&self, hwnd: isize) -> bool;
+    fn foreground_window(&self) -> Option<isize>;
+    /// Lowercase executable name of the process that owns the window, e.g. "chrome.exe".
+    fn window_exe_name(&self, hwnd: isize) -> Option<String>;
+    /// Closes the active tab of the window. Returns false if the keystroke couldn't be sent.
+    fn close_tab(&self, hwnd: isize) -> bool;
+    /// Loads `url` in the active tab through the address bar. Returns false if the address
+    /// bar couldn't be found or filled in.
+    fn navigate(&self, hwnd: isize, url: &str) -> bool;
+    fn close_window(&self, hwnd: isize);
+    /// Kills the process that owns the window. Returns false if it couldn't be terminated.
+    fn terminate_process(&self, hwnd: isize) -> bool;
+}
+
+/// The inspector for the platform being built for.
+pub fn default_inspector() -> Box<dyn WindowInspector> {
+    #[cfg(windows)]
+    let inspector: Box<dyn WindowInspector> = Box::new(UiaWindowInspector);
+    #[cfg(not(windows))]
+    let inspector: Box<dyn WindowInspector> = Box::new(NoWindowInspector);
+    inspector
+}
+
+#[cfg(windows)]
+pub use uia::{process_exe_name, UiaWindowInspector};
+
+/// Stands in where browser windows can't be read yet (only UI Automation is supported): it
+/// sees no windows, so nothing is blocked.
+#[cfg(not(windows))]
+struct NoWindowInspector;
+
+#[cfg(not(windows))]
+impl WindowInspector for NoWindowInspector {
+    fn top_level_windows(&self) -> Vec<TopLevelWindow> {
+        Vec::new()
+    }
+
+    fn address_bar_url(&self, _hwnd: isize) -> Option<String> {
+        None
+    }
+
+    fn document_url(&self, _hwnd: isize) -> Option<String> {
+        None
+    }
+
+    fn is_fullscreen(&self, _hwnd: isize) -> bool {
+        false
+    }
+
+    fn foreground_window(&self) -> Option<isize> {
+        None
+    }
+
+    fn window_exe_name(&self, _hwnd: isize) -> Option<String> {
+        None
+    }
+
+    fn close_tab(&self, _hwnd: isize) -> bool {
+        false
+    }
+
+    fn navigate(&self, _hwnd: isize, _url: &str) -> bool {
+        false
+    }
+
+    fn close_window(&self, _hwnd: isize) {}
+
+    fn terminate_process(&self, _hwnd: isize) -> bool {
+        false
+    }
+}
+
+#[cfg(windows)]
+mod uia {
+    use uiautomation::{UIAutomation, UIElement, UITreeWalker};
+    use uiautomation::patterns::UIValuePattern;
+    use uiautomation::controls::ControlType;
+    use uiautomation::types::{Handle, UIProperty};
+    use uiautomation::variants::{Value, Variant};
+    use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowRect, GetWindowThreadProcessId, SetForegroundWindow, PostMessageW, WM_CLOSE};
+    use windows::Win32::Graphics::Gdi::{GetMonitorInfoW, MonitorFromWindow, MONITORINFO, MONITOR_DEFAULTTONEAREST};
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_KEYUP, VIRTUAL_KEY, VK_CONTROL, VK_RETURN, VK_W,
+    };
+    use windows::core::PWSTR;
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, TerminateProcess, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_TERMINATE,
+    };
+    use windows::Win32::Foundation::{CloseHandle, LPARAM, RECT, WPARAM, HWND};
+
+
+    use super::{TopLevelWindow, WindowInspector};
+
+    /// WindowInspector backed by Windows UI Automation and Win32 messages.
+    pub struct UiaWindowInspector;
+
+    impl WindowInspector for UiaWindowInspector {
+        fn top_level_windows(&self) -> Vec<TopLevelWindow> {
+            let mut windows = Vec::new();
+            let Ok(automation) = UIAutomation::new() else { return windows };
+            let Ok(root) = automation.get_root_element() else { return windows };
+            let Ok(walker) = automation.get_control_view_walker() else { return windows };
+
+            let mut current = match walker.get_first_child(&root) {
+                Ok(el) => el,
+                _ => return windows,
+            };
+
+            loop {
+                if let (Ok(name), Some(hwnd)) = (current.get_name(), element_hwnd(&current)) {
+                    let class_name = current.get_property_value(UIProperty::ClassName).ok()
+                        .and_then(|val| variant_to_string(&val))
+                        .unwrap_or_default();
+                    windows.push(TopLevelWindow { hwnd, name, class_name });
+                }
+
+                if let Ok(next) = walker.get_next_sibling(&current) {
+                    current = next;
+                } else {
+                    break;
+                }
+            }
+            windows
+        }
+
+        fn address_bar_url(&self, hwnd: isize) -> Option<String> {
+            let automation = UIAutomation::new().ok()?;
+            let walker = automation.get_control_view_walker().ok()?;
+            let browser_window = automation.element_from_handle(Handle::from(hwnd)).ok()?;
+
+            let address_bar = find_address_bar_recursive(&walker, &browser_window, 0)?;
+            element_value(&address_bar)
+        }
+
+        fn document_url(&self, hwnd: isize) -> Option<String> {
+            let automation = UIAutomation::new().ok()?;
+            let walker = automation.get_control_view_walker().ok()?;
+            let browser_window = automation.element_from_handle(Handle::from(hwnd)).ok()?;
+
+            let document = find_document_recursive(&walker, &browser_window, 0)?;
+            if let Some(url_str) = element_value(&document) {
+                return Some(url_str);
+            }
+            // Some builds leave the value empty but name the document after the page URL; the name
+            // is usually the page title though, so only take it if it parses as a web URL
+            let name = document.get_name().ok()?;
+            let url = url::Url::parse(name.trim()).ok()?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return None;
+            }
+            Some(name.trim().to_string())
+        }
+
+        fn is_fullscreen(&self, hwnd: isize) -> bool {
+            let hwnd = HWND(hwnd);
+            let mut rect = RECT::default();
+            let mut info = MONITORINFO {
+                cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+                ..Default::default()
+            };
+            unsafe {
+                if GetWindowRect(hwnd, &mut rect).is_err() {
+                    return false;
+                }
+                let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+                if !GetMonitorInfoW(monitor, &mut info).as_bool() {
+                    return false;
+                }
+            }
+            let screen = info.rcMonitor;
+            rect.left <= screen.left && rect.top <= screen.top && rect.right >= screen.right && rect.bottom >= screen.bottom
+        }
+
+        fn foreground_window(&self) -> Option<isize> {
+            let hwnd = unsafe { GetForegroundWindow() };
+            if hwnd.0 != 0 { Some(hwnd.0) } else { None }
+        }
+
+        fn close_tab(&self, hwnd: isize) -> bool {
+            send_close_tab(HWND(hwnd))
+        }
+
+        fn navigate(&self, hwnd: isize, url: &str) -> bool {
+            let Ok(automation) = UIAutomation::new() else { return false };
+            let Ok(walker) = automation.get_control_view_walker() else { return false };
+            let Ok(browser_window) = automation.element_from_handle(Handle::from(hwnd)) else { return false };
+            let Some(address_bar) = find_address_bar_recursive(&walker, &browser_window, 0) else { return false };
+            let Ok(value) = address_bar.get_pattern::<UIValuePattern>() else { return false };
+
+            unsafe {
+                if !SetForegroundWindow(HWND(hwnd)).as_bool() {
+                    return false;
+                }
+            }
+            if address_bar.set_focus().is_err() || value.set_value(url).is_err() {
+                return false;
+            }
+            // Setting the value only edits the text; Enter starts the navigation
+            let inputs = [key_input(VK_RETURN, KEYBD_EVENT_FLAGS(0)), key_input(VK_RETURN, KEYEVENTF_KEYUP)];
+            unsafe { SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) == inputs.len() as u32 }
+        }
+
+        fn close_window(&self, hwnd: isize) {
+            unsafe {
+                let _ = PostMessageW(HWND(hwnd), WM_CLOSE, WPARAM(0), LPARAM(0));
+            }
+        }
+
+        fn window_exe_name(&self, hwnd: isize) -> Option<String> {
+            let mut pid: u32 = 0;
+            unsafe { GetWindowThreadProcessId(HWND(hwnd), Some(&mut pid)) };
+            if pid == 0 {
+                return None;
+            }
+            process_exe_name(pid)
+        }
+
+        fn terminate_process(&self, hwnd: isize) -> bool {
+            let mut pid: u32 = 0;
+            unsafe { GetWindowThreadProcessId(HWND(hwnd), Some(&mut pid)) };
+            if pid == 0 {
+                return false;
+            }
+
+            unsafe {
+                match OpenProcess(PROCESS_TERMINATE, false, pid) {
+                    Ok(handle) => {
+                        let terminated = TerminateProcess(handle, 1).is_ok();
+                        let _ = CloseHandle(handle);
+                        terminated
+                    }
+                    Err(e) => {
+                        println!("[ERROR] Could not open process {} for termination: {}", pid, e);
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lowercase executable file name of a process, e.g. "winword.exe".
+    pub fn process_exe_name(pid: u32) -> Option<String> {
+        unsafe {
+            let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+            let mut buffer = [0u16; 260];
+            let mut len = buffer.len() as u32;
+            let queried = QueryFullProcessImageNameW(handle, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut len).is_ok();
+            let _ = CloseHandle(handle);
+            if !queried {
+                return None;
+            }
+            let path = String::from_utf16_lossy(&buffer[..len as usize]);
+            path.rsplit('\\').next().map(|name| name.to_lowercase())
+        }
+    }
+
+    fn element_hwnd(element: &UIElement) -> Option<isize> {
+        let val = element.get_property_value(UIProperty::NativeWindowHandle).ok()?;
+        variant_to_hwnd(&val)
+    }
+
+    /// The element's ValueValue property as a string, or None if it's empty.
+    fn element_value(element: &UIElement) -> Option<String> {
+        let val = element.get_property_value(UIProperty::ValueValue).ok()?;
+        variant_to_string(&val)
+    }
+
+    /// The string held by a Variant, read as a typed value rather than parsed out of its Display
+    /// form, so URLs containing parentheses come through intact. None if it's empty or not a string.
+    fn variant_to_string(val: &Variant) -> Option<String> {
+        match val.get_value().ok()? {
+            Value::STRING(value) if !value.is_empty() => Some(value),
+            _ => None,
+        }
+    }
+
+    /// A NativeWindowHandle Variant as an HWND. UI Automation reports it as a 32-bit integer, but
+    /// any integer type is accepted; 0 (no native window) gives None.
+    fn variant_to_hwnd(val: &Variant) -> Option<isize> {
+        let hwnd = match val.get_value().ok()? {
+            Value::I4(v) | Value::INT(v) => v as isize,
+            Value::I8(v) => v as isize,
+            Value::UI4(v) | Value::UINT(v) => v as isize,
+            Value::UI8(v) => v as isize,
+            _ => return None,
+        };
+        if hwnd != 0 { Some(hwnd) } else { None }
+    }
+
+    /// Finds the web page's Document element, which Chromium and Firefox keep in the tree
+    /// (with the page URL as its value) even when the toolbar is hidden.
+    fn find_document_recursive(walker: &UITreeWalker, element: &UIElement, depth: u32) -> Option<UIElement> {
+        if depth > 12 { return None; }
+
+        let mut current = walker.get_first_child(element).ok()?;
+        loop {
+            if matches!(current.get_control_type(), Ok(ControlType::Document)) {
+                return Some(current);
+            }
+            if let Some(found) = find_document_recursive(walker, &current, depth + 1) {
+                return Some(found);
+            }
+            current = walker.get_next_sibling(&current).ok()?;
+        }
+    }
+
+    fn find_address_bar_recursive(walker: &UITreeWalker, element: &UIElement, depth: u32) -> Option<UIElement> {
+        if depth > 12 { return None; }
+
+        let mut current = match walker.get_first_child(element) {
+            Ok(el) => el,
+            _ => return None,
+        };
+
+        loop {
+            if let Ok(name) = current.get_name() {
+                let name_lower = name.to_lowercase();
+                // Check common names
+                if name_lower.contains("address and search bar") || name_lower.contains("address bar") {
+                    return Some(current.clone());
+                }
+                // Firefox: "Search with Google or enter address"
+                if name_lower.starts_with("search with") && name_lower.contains("or enter address") {
+                    return Some(current.clone());
+                }
+            }
+
+            // Check for common AutomationIds as a robust fallback
+            if let Ok(auto_id) = current.get_automation_id() {
+                // Firefox exposes its editable address field as "urlbar-input"
+                if auto_id == "urlbar-input" {
+                    return Some(current.clone());
+                }
+                if auto_id == "addressEditBox" || auto_id.contains("url") || auto_id.contains("address") {
+                    return Some(current.clone());
+                }
+            }
+
+            if let Some(found) = find_address_bar_recursive(walker, &current, depth + 1) {
+                return Some(found);
+            }
+
+            if let Ok(next) = walker.get_next_sibling(&current) {
+                current = next;
+            } else {
+                break;
+            }
+        }
+        None
+    }
+
+    fn key_input(vk: VIRTUAL_KEY, flags: KEYBD_EVENT_FLAGS) -> INPUT {
+        INPUT {
+            r#type: INPUT_KEYBOARD,
+            Anonymous: INPUT_0 {
+                ki: KEYBDINPUT {
+                    wVk: vk,
+                    wScan: 0,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: 0,
+                },
+            },
+        }
+    }
+
+    /// Brings the browser window to the foreground and sends Ctrl+W to close the active tab.
+    fn send_close_tab(hwnd: HWND) -> bool {
+        let inputs = [
+            key_input(VK_CONTROL, KEYBD_EVENT_FLAGS(0)),
+            key_input(VK_W, KEYBD_EVENT_FLAGS(0)),
+            key_input(VK_W, KEYEVENTF_KEYUP),
+            key_input(VK_CONTROL, KEYEVENTF_KEYUP),
+        ];
+
+        unsafe {
+            if !SetForegroundWindow(hwnd).as_bool() {
+                return false;
+            }
+            SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) == inputs.len() as u32
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use uiautomation::variants::{Value, Variant};
+
+        use super::{variant_to_hwnd, variant_to_string};
+
+        #[test]
+        fn variant_to_string_keeps_the_whole_url() {
+            let url = "https://en.wikipedia.org/wiki/Rust_(programming_language)";
+            assert_eq!(variant_to_string(&Variant::from(url)).as_deref(), Some(url));
+            assert_eq!(variant_to_string(&Variant::from("")), None);
+            assert_eq!(variant_to_string(&Variant::from(Value::EMPTY)), None);
+            assert_eq!(variant_to_string(&Variant::from(Value::NULL)), None);
+            assert_eq!(variant_to_string(&Variant::from(42i32)), None);
+        }
+
+        #[test]
+        fn variant_to_hwnd_accepts_any_integer_form() {
+            assert_eq!(variant_to_hwnd(&Variant::from(0x1a2b_i32)), Some(0x1a2b));
+            assert_eq!(variant_to_hwnd(&Variant::from(0x1a2b_i64)), Some(0x1a2b));
+            assert_eq!(variant_to_hwnd(&Variant::from(0x1a2b_u32)), Some(0x1a2b));
+            assert_eq!(variant_to_hwnd(&Variant::from(0i32)), None);
+            assert_eq!(variant_to_hwnd(&Variant::from(0i64)), None);
+            assert_eq!(variant_to_hwnd(&Variant::from(Value::EMPTY)), None);
+            assert_eq!(variant_to_hwnd(&Variant::from("0x1a2b")), None);
+        }
+    }
+}
@@ -1,19 +1,75 @@
 mod config;
 mod core;
+mod ui;
 
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::config::client::APIClient;
+use crate::config::settings::{API_MAX_RETRIES, API_RETRY_BASE_DELAY_MS, API_SHORT_TIMEOUT_SECS, API_UPLOAD_TIMEOUT_SECS};
+use crate::core::browser_monitor::{BrowserMonitor, FilterMode};
 use crate::core::monitor::CybersecurityMonitor;
 
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("test-url") {
+        match args.get(2) {
+            Some(url) => test_url(url).await,
+            None => {
+                eprintln!("Usage: {} test-url <url>", args[0]);
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+
     let mut monitor = CybersecurityMonitor::new();
     
-    // Simple signal handling
-    tokio::spawn(async move {
-        ctrlc::set_handler(move || {
-            println!("\nStopping monitor...");
-            std::process::exit(0);
-        }).expect("Error setting Ctrl-C handler");
-    });
+    // Ctrl-C asks the monitor loop to stop so it can flush and upload before exiting.
+    // A second Ctrl-C while that is in progress exits immediately.
+    let shutdown = monitor.shutdown.clone();
+    ctrlc::set_handler(move || {
+        if shutdown.swap(true, Ordering::SeqCst) {
+            println!("\nForcing exit...");
+            std::process::exit(1);
+        }
+        println!("\nStopping monitor...");
+    }).expect("Error setting Ctrl-C handler");
+
+    #[cfg(windows)]
+    ui::tray::spawn_tray(monitor.paused.clone(), monitor.shutdown.clone());
 
     monitor.run().await;
+    println!("Monitor stopped.");
+}
+
+/// Checks a URL against the server's current block list and filter settings without
+/// starting the monitor, printing whether it would be blocked and why.
+async fn test_url(url: &str) {
+    let api_client = APIClient::new(
+        API_MAX_RETRIES,
+        Duration::from_millis(API_RETRY_BASE_DELAY_MS),
+        Duration::from_secs(API_SHORT_TIMEOUT_SECS),
+        Duration::from_secs(API_UPLOAD_TIMEOUT_SECS),
+    );
+    let mut browser_monitor = BrowserMonitor::new();
+
+    if let Some(config) = api_client.get_monitor_config().await {
+        if let Some(mode) = config.get("urlFilterMode").and_then(|v| v.as_str()) {
+            browser_monitor.update_mode(FilterMode::from_config(mode));
+        }
+        if let Some(categories) = config.get("blockedCategories").and_then(|v| v.as_array()) {
+            let categories = categories.iter().filter_map(|c| c.as_str()).map(str::to_string).collect();
+            browser_monitor.update_blocked_categories(categories);
+        }
+    } else {
+        println!("[WARN] Could not fetch monitor config. Assuming blacklist mode with no blocked categories.");
+    }
+    browser_monitor.update_blacklist(api_client.get_blocked_urls().await);
+
+    match browser_monitor.block_reason(url) {
+        Some(reason) => println!("BLOCKED: {} (matched {}, {:?} mode)", url, reason, browser_monitor.mode),
+        None => println!("ALLOWED: {} ({:?} mode)", url, browser_monitor.mode),
+    }
 }
@@ -0,0 +1,3 @@
+pub mod notifications;
+#[cfg(windows)]
+pub mod tray;
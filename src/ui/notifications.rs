@@ -0,0 +1,24 @@
+#[cfg(windows)]
+use tauri_winrt_notification::{Duration, Sound, Toast};
+
+/// Shows a Windows toast explaining that a page was blocked. Runs on its own thread so a slow
+/// notification platform never stalls the monitoring loop.
+#[cfg(windows)]
+pub fn show_block_notification(domain: &str, reason: &str) {
+    let message = format!("{} was blocked ({}).", domain, reason);
+    std::thread::spawn(move || {
+        let result = Toast::new(Toast::POWERSHELL_APP_ID)
+            .title("Site blocked by policy")
+            .text1(&message)
+            .sound(Some(Sound::Default))
+            .duration(Duration::Short)
+            .show();
+        if let Err(e) = result {
+            println!("[ERROR] Failed to show block notification: {}", e);
+        }
+    });
+}
+
+/// Toasts are Windows-only; elsewhere the block is only logged.
+#[cfg(not(windows))]
+pub fn show_block_notification(_domain: &str, _reason: &str) {}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tray_icon::{Icon, TrayIconBuilder};
+use tray_icon::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use windows::Win32::Foundation::HWND;
+use windows::Win32::UI::WindowsAndMessaging::{DispatchMessageW, PeekMessageW, TranslateMessage, MSG, PM_REMOVE};
+
+const ICON_SIZE: u32 = 16;
+const ACTIVE_COLOR: [u8; 4] = [46, 160, 67, 255];
+const PAUSED_COLOR: [u8; 4] = [219, 154, 4, 255];
+
+/// Shows the tray icon on its own thread. The icon needs a Win32 message loop on the thread
+/// that created it, so it can't share the tokio runtime with the monitor.
+pub fn spawn_tray(paused: Arc<AtomicBool>, shutdown: Arc<AtomicBool>) {
+    std::thread::spawn(move || run_tray(paused, shutdown));
+}
+
+fn run_tray(paused: Arc<AtomicBool>, shutdown: Arc<AtomicBool>) {
+    let status_item = MenuItem::new(status_text(false), false, None);
+    let pause_item = MenuItem::new("Pause monitoring", true, None);
+    let quit_item = MenuItem::new("Quit", true, None);
+
+    let menu = Menu::new();
+    if let Err(e) = menu.append_items(&[&status_item, &PredefinedMenuItem::separator(), &pause_item, &quit_item]) {
+        println!("[ERROR] Failed to build tray menu: {}", e);
+        return;
+    }
+
+    let mut builder = TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip(status_text(false));
+    if let Some(icon) = status_icon(false) {
+        builder = builder.with_icon(icon);
+    }
+    let tray = match builder.build() {
+        Ok(tray) => tray,
+        Err(e) => {
+            println!("[ERROR] Failed to create tray icon: {}", e);
+            return;
+        }
+    };
+
+    let mut shown_paused = false;
+    while !shutdown.load(Ordering::SeqCst) {
+        unsafe {
+            let mut msg = MSG::default();
+            while PeekMessageW(&mut msg, HWND(0), 0, 0, PM_REMOVE).as_bool() {
+                let _ = TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+
+        while let Ok(event) = MenuEvent::receiver().try_recv() {
+            if event.id == *pause_item.id() {
+                let now_paused = !paused.load(Ordering::SeqCst);
+                paused.store(now_paused, Ordering::SeqCst);
+                println!("\n[INFO] Monitoring {} from the tray.", if now_paused { "paused" } else { "resumed" });
+            } else if event.id == *quit_item.id() {
+                println!("\nStopping monitor...");
+                shutdown.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let is_paused = paused.load(Ordering::SeqCst);
+        if is_paused != shown_paused {
+            status_item.set_text(status_text(is_paused));
+            pause_item.set_text(if is_paused { "Resume monitoring" } else { "Pause monitoring" });
+            let _ = tray.set_tooltip(Some(status_text(is_paused)));
+            let _ = tray.set_icon(status_icon(is_paused));
+            shown_paused = is_paused;
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn status_text(paused: bool) -> &'static str {
+    if paused { "Cybersecurity Monitor: Paused" } else { "Cybersecurity Monitor: Active" }
+}
+
+/// A solid square: green while monitoring, amber while paused.
+fn status_icon(paused: bool) -> Option<Icon> {
+    let color = if paused { PAUSED_COLOR } else { ACTIVE_COLOR };
+    let rgba = color.repeat((ICON_SIZE * ICON_SIZE) as usize);
+    match Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE) {
+        Ok(icon) => Some(icon),
+        Err(e) => {
+            println!("[ERROR] Failed to create tray icon image: {}", e);
+            None
+        }
+    }
+}